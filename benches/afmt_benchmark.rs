@@ -18,7 +18,7 @@ pub fn criterion_benchmark(c: &mut Criterion) {
 
     // Benchmark each file in a single Criterion report
     for sample_path in apex_files {
-        let file_name = sample_path.split('/').last().unwrap_or("Unknown file");
+        let file_name = sample_path.split('/').next_back().unwrap_or("Unknown file");
 
         let session = Formatter::create_from_config(None, vec![sample_path.to_string()])
             .expect("Failed to create session");
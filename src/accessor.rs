@@ -1,6 +1,7 @@
 use tree_sitter::Node;
 
-use crate::{message_helper::red, utility::get_source_code};
+use crate::error::FormatError;
+use crate::utility::{format_context, unsupported_node};
 
 // `c` => child
 // `cv` => child value
@@ -8,54 +9,50 @@ use crate::{message_helper::red, utility::get_source_code};
 // `csv` => children value
 // `by_n` => by name
 // `by_k` => by kind
+// The accessors of mandatory children fail with `FormatError::UnsupportedNode`.
 #[allow(dead_code)]
 pub trait Accessor<'t> {
     fn value(&self) -> String;
 
-    fn first_c(&self) -> Node<'t>;
+    fn first_c(&self) -> Result<Node<'t>, FormatError>;
 
     fn try_first_c(&self) -> Option<Node<'t>>;
     fn try_c_by_n(&self, kind: &str) -> Option<Node<'t>>;
     fn try_c_by_k(&self, kind: &str) -> Option<Node<'t>>;
     fn try_cs_by_k(&self, kind: &str) -> Vec<Node<'t>>;
 
-    fn c_by_n(&self, name: &str) -> Node<'t>;
-    fn c_by_k(&self, kind: &str) -> Node<'t>;
-    fn cvalue_by_n(&self, name: &str) -> String;
-    fn cvalue_by_k(&self, name: &str) -> String;
+    fn c_by_n(&self, name: &str) -> Result<Node<'t>, FormatError>;
+    fn c_by_k(&self, kind: &str) -> Result<Node<'t>, FormatError>;
+    fn cvalue_by_n(&self, name: &str) -> Result<String, FormatError>;
+    fn cvalue_by_k(&self, name: &str) -> Result<String, FormatError>;
 
     fn all_children_vec(&self) -> Vec<Node<'t>>;
     fn children_vec(&self) -> Vec<Node<'t>>;
-    fn cs_by_k(&self, kind: &str) -> Vec<Node<'t>>;
-    fn cs_by_n(&self, name: &str) -> Vec<Node<'t>>;
+    fn cs_by_k(&self, kind: &str) -> Result<Vec<Node<'t>>, FormatError>;
+    fn cs_by_n(&self, name: &str) -> Result<Vec<Node<'t>>, FormatError>;
 
-    fn next_named(&self) -> Node<'t>;
-
-    // private fn;
-    fn v<'a>(&self) -> &'a str;
-    fn cv_by_k(&self, name: &str) -> &str;
-    fn cv_by_n<'a>(&self, name: &str) -> &'a str;
+    fn next_named(&self) -> Result<Node<'t>, FormatError>;
 }
 
 impl<'t> Accessor<'t> for Node<'t> {
-    fn next_named(&self) -> Node<'t> {
+    fn next_named(&self) -> Result<Node<'t>, FormatError> {
         let mut sibling = self.next_named_sibling();
         while let Some(node) = sibling {
             if !node.is_extra() {
-                return node;
+                return Ok(node);
             }
             sibling = node.next_named_sibling();
         }
-        panic!("{}: next_named node missing.", red(self.kind()));
-    }
-
-    fn v<'a>(&self) -> &'a str {
-        self.utf8_text(get_source_code().as_bytes())
-            .unwrap_or_else(|_| panic!("{}: get AST source_code value failed.", red(self.kind())))
+        Err(unsupported_node(
+            self,
+            "next_named node missing".to_string(),
+        ))
     }
 
     fn value(&self) -> String {
-        self.v().to_string()
+        // node boundaries of a parsed `&str` always fall on char boundaries
+        let context = format_context();
+        String::from_utf8_lossy(&context.source_code().as_bytes()[self.byte_range()]).into_owned()
     }
 
     fn children_vec(&self) -> Vec<Node<'t>> {
@@ -87,14 +84,9 @@ impl<'t> Accessor<'t> for Node<'t> {
         self.child_by_field_name(name)
     }
 
-    fn c_by_k(&self, kind: &str) -> Node<'t> {
-        self.try_c_by_k(kind).unwrap_or_else(|| {
-            panic!(
-                "## {}: missing mandatory kind child: {}\n ##Source_code: {}",
-                red(self.kind()),
-                red(kind),
-                self.value(),
-            )
+    fn c_by_k(&self, kind: &str) -> Result<Node<'t>, FormatError> {
+        self.try_c_by_k(kind).ok_or_else(|| {
+            unsupported_node(self, format!("missing mandatory kind child: {}", kind))
         })
     }
 
@@ -109,80 +101,46 @@ impl<'t> Accessor<'t> for Node<'t> {
         None
     }
 
-    fn first_c(&self) -> Node<'t> {
-        let mut index = 0;
-        while let Some(node) = self.named_child(index) {
-            if !node.is_extra() {
-                return node;
-            }
-            index += 1;
-        }
-        panic!(
-            "{}: missing a mandatory child in first_c().",
-            red(self.kind())
-        );
-    }
-
-    fn cv_by_k(&self, name: &str) -> &str {
-        let child_node = self.c_by_k(name);
-        child_node.v()
-    }
-
-    fn cv_by_n<'a>(&self, name: &str) -> &'a str {
-        let node = self.child_by_field_name(name).unwrap_or_else(|| {
-            panic!(
-                "## {}: missing mandatory name child: {}\n ##Source_code: {}",
-                red(self.kind()),
-                red(name),
-                self.value(),
-            )
-        });
-        node.v()
+    fn first_c(&self) -> Result<Node<'t>, FormatError> {
+        self.try_first_c().ok_or_else(|| {
+            unsupported_node(self, "missing a mandatory child in first_c()".to_string())
+        })
     }
 
-    fn cvalue_by_n(&self, name: &str) -> String {
-        self.cv_by_n(name).to_string()
+    fn cvalue_by_n(&self, name: &str) -> Result<String, FormatError> {
+        Ok(self.c_by_n(name)?.value())
     }
 
-    fn cvalue_by_k(&self, name: &str) -> String {
-        self.cv_by_k(name).to_string()
+    fn cvalue_by_k(&self, name: &str) -> Result<String, FormatError> {
+        Ok(self.c_by_k(name)?.value())
     }
 
-    fn c_by_n(&self, name: &str) -> Node<'t> {
-        self.child_by_field_name(name).unwrap_or_else(|| {
-            panic!(
-                "## {}: missing mandatory name child: {}\n ##Source_code: {}",
-                red(self.kind()),
-                red(name),
-                self.value(),
-            )
+    fn c_by_n(&self, name: &str) -> Result<Node<'t>, FormatError> {
+        self.child_by_field_name(name).ok_or_else(|| {
+            unsupported_node(self, format!("missing mandatory name child: {}", name))
         })
     }
 
-    fn cs_by_n(&self, name: &str) -> Vec<Node<'t>> {
+    fn cs_by_n(&self, name: &str) -> Result<Vec<Node<'t>>, FormatError> {
         let mut cursor = self.walk();
         let children: Vec<Node<'t>> = self.children_by_field_name(name, &mut cursor).collect();
         if children.is_empty() {
-            panic!(
-                "## {}: missing mandatory name children: {}\n ##Source_code: {}",
-                red(self.kind()),
-                red(name),
-                self.value(),
-            );
+            return Err(unsupported_node(
+                self,
+                format!("missing mandatory name children: {}", name),
+            ));
         }
-        children
+        Ok(children)
     }
 
-    fn cs_by_k(&self, kind: &str) -> Vec<Node<'t>> {
+    fn cs_by_k(&self, kind: &str) -> Result<Vec<Node<'t>>, FormatError> {
         let children = self.try_cs_by_k(kind);
         if children.is_empty() {
-            panic!(
-                "## {}: missing mandatory kind children: {}\n ##Source_code: {}",
-                red(self.kind()),
-                red(kind),
-                self.value(),
-            );
+            return Err(unsupported_node(
+                self,
+                format!("missing mandatory kind children: {}", kind),
+            ));
         }
-        children
+        Ok(children)
     }
 }
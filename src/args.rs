@@ -1,10 +1,96 @@
+use crate::cache::DEFAULT_CACHE_FILE;
 use clap::{Arg as ClapArg, Command};
+use std::io::IsTerminal;
+use std::ops::Range;
+
+// The part of a file to format with `--range` or `--lines`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormatRange {
+    // byte offsets, end exclusive
+    Bytes(usize, usize),
+    // 1-based line numbers, end inclusive
+    Lines(usize, usize),
+}
+
+impl FormatRange {
+    pub fn byte_range(&self, source_code: &str) -> Range<usize> {
+        match *self {
+            Self::Bytes(start, end) => start..end,
+            Self::Lines(start, end) => {
+                let mut line_starts =
+                    std::iter::once(0).chain(source_code.match_indices('\n').map(|(i, _)| i + 1));
+                let start_byte = line_starts.nth(start - 1).unwrap_or(source_code.len());
+                let end_byte = line_starts.nth(end - start).unwrap_or(source_code.len());
+                start_byte..end_byte
+            }
+        }
+    }
+}
+
+fn parse_range(value: &str, is_lines: bool) -> Result<FormatRange, String> {
+    let (start, end) = value
+        .split_once(':')
+        .ok_or_else(|| "expected START:END".to_string())?;
+    let start: usize = start.trim().parse().map_err(|e| format!("{}", e))?;
+    let end: usize = end.trim().parse().map_err(|e| format!("{}", e))?;
+
+    if is_lines && start == 0 {
+        return Err("line numbers start at 1".to_string());
+    }
+    if start > end {
+        return Err(format!("{} is after {}", start, end));
+    }
+    Ok(if is_lines {
+        FormatRange::Lines(start, end)
+    } else {
+        FormatRange::Bytes(start, end)
+    })
+}
+
+// Files to format picked from git instead of the paths, which then narrow them down.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GitSelection {
+    // the staged content of the staged files
+    Staged,
+    // files changed since a revision, and untracked files
+    ChangedSince(String),
+}
+
+// How the results are printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    // a `Report`, see `report.rs`
+    Json,
+    // the unformatted files and errors of `--check`
+    Sarif,
+    Checkstyle,
+}
 
 #[derive(Debug)]
 pub struct Args {
-    pub path: String,
+    pub paths: Vec<String>,
     pub config: Option<String>,
     pub write: bool,
+    pub check: bool,
+    pub diff: bool,
+    pub color: bool,
+    pub color_stderr: bool,
+    pub stdin: bool,
+    pub stdin_filepath: Option<String>,
+    pub verbose: bool,
+    pub range: Option<FormatRange>,
+    pub verify: bool,
+    pub verify_idempotent: bool,
+    // 0 for one per CPU
+    pub jobs: usize,
+    // the cache file of `--cache`
+    pub cache: Option<String>,
+    pub git: Option<GitSelection>,
+    pub watch: bool,
+    pub output_format: OutputFormat,
+    // add the formatted code to the JSON report
+    pub include_formatted: bool,
 }
 
 pub fn get_args() -> Args {
@@ -15,10 +101,11 @@ pub fn get_args() -> Args {
         .about(format!("Apex format tool (afmt): {}", VERSION))
         .arg_required_else_help(true)
         .arg(
-            ClapArg::new("file")
-                .value_name("FILE")
-                .help("The relative path to the file to parse")
-                .required(true)
+            ClapArg::new("paths")
+                .value_name("PATH")
+                .help("Files, directories or glob patterns of the Apex files to format, `-` for stdin")
+                .required_unless_present_any(["stdin", "staged", "changed-since"])
+                .num_args(1..)
                 .index(1),
         )
         .arg(
@@ -26,7 +113,7 @@ pub fn get_args() -> Args {
                 .short('c')
                 .long("config")
                 .value_name("CONFIG")
-                .help("Path to the .afmt.toml configuration file"),
+                .help("Path to the .afmt.toml configuration file, by default the nearest one above each file is used"),
         )
         .arg(
             ClapArg::new("write")
@@ -35,6 +122,142 @@ pub fn get_args() -> Args {
                 .help("Write the formatted result back to the file")
                 .action(clap::ArgAction::SetTrue),
         )
+        .arg(
+            ClapArg::new("check")
+                .long("check")
+                .help("Check if the files are formatted, list the ones that are not and exit with code 1")
+                .conflicts_with("write")
+                .required_if_eq_any([("output-format", "sarif"), ("output-format", "checkstyle")])
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            ClapArg::new("diff")
+                .long("diff")
+                .help("Print a unified diff for the files that are not formatted, exit code as --check")
+                .conflicts_with("write")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            ClapArg::new("stdin")
+                .long("stdin")
+                .help("Read Apex source from stdin and print only the formatted code to stdout")
+                .conflicts_with("write")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            ClapArg::new("stdin-filepath")
+                .long("stdin-filepath")
+                .value_name("PATH")
+                .help("The path of the file being formatted from stdin, used to find its .afmt.toml and in messages"),
+        )
+        .arg(
+            ClapArg::new("lines")
+                .long("lines")
+                .value_name("START:END")
+                .help("Only format the statements and members on these lines (1-based, inclusive) of a single file")
+                .value_parser(|v: &str| parse_range(v, true)),
+        )
+        .arg(
+            ClapArg::new("range")
+                .long("range")
+                .value_name("START:END")
+                .help("Only format the statements and members in this byte range (end exclusive) of a single file")
+                .conflicts_with("lines")
+                .value_parser(|v: &str| parse_range(v, false)),
+        )
+        .arg(
+            ClapArg::new("verify")
+                .long("verify")
+                .help("Re-parse the formatted code and refuse to write it if its syntax tree differs from the source")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            ClapArg::new("verify-idempotent")
+                .long("verify-idempotent")
+                .help("Format the result a second time and fail with a diff if that changes it")
+                .conflicts_with_all(["lines", "range"])
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            ClapArg::new("jobs")
+                .short('j')
+                .long("jobs")
+                .value_name("N")
+                .help("Number of files formatted in parallel, one per CPU by default")
+                .value_parser(clap::value_parser!(u64).range(1..)),
+        )
+        .arg(
+            ClapArg::new("cache")
+                .long("cache")
+                .help("Skip the files known to be formatted since the last run, see --cache-location")
+                .conflicts_with_all(["lines", "range", "stdin"])
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            ClapArg::new("cache-location")
+                .long("cache-location")
+                .value_name("FILE")
+                .help("The cache file of --cache [default: .afmtcache]")
+                .requires("cache"),
+        )
+        .arg(
+            ClapArg::new("staged")
+                .long("staged")
+                .help("Format the staged content of the Apex files staged in git, --write stages the result")
+                .conflicts_with_all(["stdin", "lines", "range", "cache"])
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            ClapArg::new("changed-since")
+                .long("changed-since")
+                .value_name("REV")
+                .help("Format the Apex files changed in git since REV, and untracked ones")
+                .conflicts_with_all(["stdin", "staged"]),
+        )
+        .arg(
+            ClapArg::new("watch")
+                .long("watch")
+                .help("Keep running and reformat the Apex files in place each time they are saved")
+                .conflicts_with_all([
+                    "write",
+                    "check",
+                    "diff",
+                    "stdin",
+                    "lines",
+                    "range",
+                    "staged",
+                    "changed-since",
+                ])
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            ClapArg::new("output-format")
+                .long("output-format")
+                .value_name("FORMAT")
+                .help("Print the results as text, or as a JSON, SARIF or Checkstyle report on stdout")
+                .value_parser(["text", "json", "sarif", "checkstyle"])
+                .default_value("text")
+                .conflicts_with_all(["diff", "watch"]),
+        )
+        .arg(
+            ClapArg::new("include-formatted")
+                .long("include-formatted")
+                .help("Add the formatted code of each file to the JSON report")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            ClapArg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .help("List the files skipped by .afmtignore or the include/exclude config patterns")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            ClapArg::new("no-color")
+                .long("no-color")
+                .help("Disable colored output (also disabled by NO_COLOR or when not writing to a terminal)")
+                .action(clap::ArgAction::SetTrue),
+        )
         .after_help(
             "EXAMPLES:\n\
              \n\
@@ -44,18 +267,103 @@ pub fn get_args() -> Args {
              # Format and write changes back to the file\n\
              afmt --write src/file.cls\n\
              \n\
+             # Format all .cls and .trigger files under a directory\n\
+             afmt --write force-app/\n\
+             \n\
+             # Format files matching a glob pattern (quoted to skip shell expansion)\n\
+             afmt --write 'force-app/**/classes/*.cls'\n\
+             \n\
+             # Check formatting in CI: exit code 1 if any file would change, 2 on errors\n\
+             afmt --check force-app/\n\
+             \n\
+             # Show what would change as a unified diff\n\
+             afmt --diff --no-color force-app/ > afmt.patch\n\
+             \n\
+             # Editor integration: format stdin to stdout\n\
+             afmt - < ./file.cls\n\
+             afmt --stdin --stdin-filepath src/file.cls < src/file.cls\n\
+             \n\
+             # Only format lines 120 to 180\n\
+             afmt --write --lines 120:180 src/file.cls\n\
+             \n\
+             # Make sure formatting doesn't change the code before writing it\n\
+             afmt --write --verify force-app/\n\
+             \n\
+             # Only format the files changed since the last run\n\
+             afmt --write --cache force-app/\n\
+             \n\
+             # Machine-readable results for CI or an editor\n\
+             afmt --check --output-format json force-app/ > afmt.json\n\
+             afmt --check --output-format sarif force-app/ > afmt.sarif\n\
+             \n\
+             # Reformat files whenever they are saved\n\
+             afmt --watch force-app/\n\
+             \n\
+             # Pre-commit hook: format and re-stage the staged files\n\
+             afmt --write --staged\n\
+             \n\
+             # Check the files changed on a branch\n\
+             afmt --check --changed-since origin/main force-app/\n\
+             \n\
+             # List the files left out by .afmtignore\n\
+             afmt --check --verbose force-app/\n\
+             \n\
              # Use a specific config file\n\
              afmt --config .afmt.toml ./file.cls\n\
             ",
         )
         .get_matches();
 
+    let paths: Vec<String> = matches
+        .get_many::<String>("paths")
+        .map(|values| values.cloned().collect())
+        .unwrap_or_default();
+
+    let color_enabled = !matches.get_flag("no-color") && std::env::var_os("NO_COLOR").is_none();
+
     Args {
-        path: matches
-            .get_one::<String>("file")
-            .expect("File path is required")
-            .to_string(),
+        stdin: matches.get_flag("stdin") || paths == ["-"],
+        stdin_filepath: matches
+            .get_one::<String>("stdin-filepath")
+            .map(|s| s.to_string()),
+        paths,
         config: matches.get_one::<String>("config").map(|s| s.to_string()),
         write: matches.get_flag("write"),
+        check: matches.get_flag("check"),
+        diff: matches.get_flag("diff"),
+        color: color_enabled && std::io::stdout().is_terminal(),
+        color_stderr: color_enabled && std::io::stderr().is_terminal(),
+        verbose: matches.get_flag("verbose"),
+        range: matches
+            .get_one::<FormatRange>("lines")
+            .or(matches.get_one::<FormatRange>("range"))
+            .copied(),
+        verify: matches.get_flag("verify"),
+        verify_idempotent: matches.get_flag("verify-idempotent"),
+        jobs: matches.get_one::<u64>("jobs").map_or(0, |&n| n as usize),
+        cache: matches.get_flag("cache").then(|| {
+            matches
+                .get_one::<String>("cache-location")
+                .cloned()
+                .unwrap_or_else(|| DEFAULT_CACHE_FILE.to_string())
+        }),
+        git: if matches.get_flag("staged") {
+            Some(GitSelection::Staged)
+        } else {
+            matches
+                .get_one::<String>("changed-since")
+                .map(|rev| GitSelection::ChangedSince(rev.clone()))
+        },
+        watch: matches.get_flag("watch"),
+        output_format: match matches
+            .get_one::<String>("output-format")
+            .map(String::as_str)
+        {
+            Some("json") => OutputFormat::Json,
+            Some("sarif") => OutputFormat::Sarif,
+            Some("checkstyle") => OutputFormat::Checkstyle,
+            _ => OutputFormat::Text,
+        },
+        include_formatted: matches.get_flag("include-formatted"),
     }
 }
@@ -0,0 +1,380 @@
+// Language server for afmt, speaking LSP over stdio.
+//
+// Formatting requests use the nearest `.afmt.toml` of the document, like the CLI,
+// and parse errors are published as diagnostics while the document is edited.
+use afmt::diagnostic::expected_note;
+use afmt::error::{FormatError, SyntaxError};
+use afmt::formatter::{Config, Formatter};
+use afmt::position::{byte_offset, utf16_position};
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+    Notification as LspNotification, PublishDiagnostics,
+};
+use lsp_types::request::{Formatting, OnTypeFormatting, RangeFormatting, Request as LspRequest};
+use lsp_types::{
+    Diagnostic, DiagnosticSeverity, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, DocumentFormattingParams, DocumentOnTypeFormattingOptions,
+    DocumentOnTypeFormattingParams, DocumentRangeFormattingParams, OneOf, Position,
+    PublishDiagnosticsParams, Range, ServerCapabilities, TextDocumentSyncCapability,
+    TextDocumentSyncKind, TextEdit, Url,
+};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::process;
+use tree_sitter::Parser;
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        process::exit(2);
+    }
+}
+
+fn run() -> Result<(), String> {
+    let (connection, io_threads) = Connection::stdio();
+
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        document_range_formatting_provider: Some(OneOf::Left(true)),
+        document_on_type_formatting_provider: Some(DocumentOnTypeFormattingOptions {
+            first_trigger_character: "}".to_string(),
+            more_trigger_character: Some(vec![";".to_string()]),
+        }),
+        ..Default::default()
+    };
+    let capabilities = serde_json::to_value(capabilities).map_err(|e| e.to_string())?;
+    connection
+        .initialize(capabilities)
+        .map_err(|e| e.to_string())?;
+
+    let mut server = Server {
+        connection: &connection,
+        parser: Formatter::new_parser(),
+        formatter: Formatter::create_from_config(None, vec![])?,
+        documents: HashMap::new(),
+    };
+    server.main_loop()?;
+
+    // the writer thread only ends once the connection is gone
+    drop(server);
+    drop(connection);
+    io_threads.join().map_err(|e| e.to_string())
+}
+
+struct Server<'c> {
+    connection: &'c Connection,
+    // one parser for the whole session
+    parser: Parser,
+    // only used to find the config of each document
+    formatter: Formatter,
+    // the text of the open documents
+    documents: HashMap<Url, String>,
+}
+
+impl Server<'_> {
+    fn main_loop(&mut self) -> Result<(), String> {
+        for msg in &self.connection.receiver {
+            match msg {
+                Message::Request(req) => {
+                    if self
+                        .connection
+                        .handle_shutdown(&req)
+                        .map_err(|e| e.to_string())?
+                    {
+                        return Ok(());
+                    }
+                    let response = self.handle_request(req);
+                    self.send(Message::Response(response))?;
+                }
+                Message::Notification(not) => self.handle_notification(not)?,
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_request(&mut self, req: Request) -> Response {
+        let id = req.id.clone();
+        let result = match req.method.as_str() {
+            Formatting::METHOD => params::<DocumentFormattingParams>(req)
+                .and_then(|p| self.format(&p.text_document.uri, None)),
+            RangeFormatting::METHOD => params::<DocumentRangeFormattingParams>(req).and_then(|p| {
+                let text = self.document(&p.text_document.uri)?;
+                let range = offset_at(text, p.range.start)..offset_at(text, p.range.end);
+                self.format(&p.text_document.uri, Some(range))
+            }),
+            OnTypeFormatting::METHOD => {
+                params::<DocumentOnTypeFormattingParams>(req).and_then(|p| {
+                    let uri = &p.text_document_position.text_document.uri;
+                    let text = self.document(uri)?;
+                    // the statement or member ending with the typed `}` or `;`
+                    let end = offset_at(text, p.text_document_position.position);
+                    self.format(uri, Some(end.saturating_sub(1)..end))
+                })
+            }
+            _ => Err((
+                ErrorCode::MethodNotFound,
+                format!("Unsupported request: {}", req.method),
+            )),
+        };
+
+        match result {
+            Ok(edits) => Response::new_ok(id, edits),
+            Err((code, message)) => Response::new_err(id, code as i32, message),
+        }
+    }
+
+    fn handle_notification(&mut self, not: Notification) -> Result<(), String> {
+        match not.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let p: DidOpenTextDocumentParams = notification_params(not)?;
+                self.update(
+                    p.text_document.uri,
+                    p.text_document.text,
+                    p.text_document.version,
+                )
+            }
+            DidChangeTextDocument::METHOD => {
+                let p: DidChangeTextDocumentParams = notification_params(not)?;
+                // full sync: the last change holds the whole text
+                match p.content_changes.into_iter().last() {
+                    Some(change) => {
+                        self.update(p.text_document.uri, change.text, p.text_document.version)
+                    }
+                    None => Ok(()),
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                let p: DidCloseTextDocumentParams = notification_params(not)?;
+                self.documents.remove(&p.text_document.uri);
+                self.publish_diagnostics(p.text_document.uri, vec![], None)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn update(&mut self, uri: Url, text: String, version: i32) -> Result<(), String> {
+        let diagnostics = match Formatter::parse_with(&mut self.parser, &text) {
+            Err(FormatError::Parse { errors }) => {
+                errors.iter().map(|e| to_diagnostic(&text, e)).collect()
+            }
+            _ => vec![],
+        };
+        self.documents.insert(uri.clone(), text);
+        self.publish_diagnostics(uri, diagnostics, Some(version))
+    }
+
+    fn publish_diagnostics(
+        &self,
+        uri: Url,
+        diagnostics: Vec<Diagnostic>,
+        version: Option<i32>,
+    ) -> Result<(), String> {
+        let params = PublishDiagnosticsParams {
+            uri,
+            diagnostics,
+            version,
+        };
+        self.send(Message::Notification(Notification::new(
+            PublishDiagnostics::METHOD.to_string(),
+            params,
+        )))
+    }
+
+    // The edits formatting the whole document, or only the statements and
+    // members overlapping `range`.
+    fn format(
+        &mut self,
+        uri: &Url,
+        range: Option<std::ops::Range<usize>>,
+    ) -> Result<Vec<TextEdit>, (ErrorCode, String)> {
+        let config = self.config_for(uri)?;
+        let text = self
+            .documents
+            .get(uri)
+            .ok_or_else(|| unknown_document(uri))?;
+        let parser = &mut self.parser;
+
+        let result = match range {
+            Some(range) => Formatter::format_range_with(parser, text, range, config),
+            None => Formatter::format_one_with(parser, text, config),
+        };
+
+        match result {
+            Ok(formatted) => Ok(text_edits(text, &formatted)),
+            // already shown as diagnostics, leave the document as it is
+            Err(FormatError::Parse { .. }) => Ok(vec![]),
+            Err(e) => Err((ErrorCode::RequestFailed, e.to_string())),
+        }
+    }
+
+    fn config_for(&self, uri: &Url) -> Result<Config, (ErrorCode, String)> {
+        match uri.to_file_path() {
+            Ok(path) => self
+                .formatter
+                .config_for(&path.to_string_lossy())
+                .map_err(|e| (ErrorCode::RequestFailed, e.to_string())),
+            // e.g. an unsaved buffer
+            Err(_) => Ok(self.formatter.config().clone()),
+        }
+    }
+
+    fn document(&self, uri: &Url) -> Result<&String, (ErrorCode, String)> {
+        self.documents.get(uri).ok_or_else(|| unknown_document(uri))
+    }
+
+    fn send(&self, msg: Message) -> Result<(), String> {
+        self.connection.sender.send(msg).map_err(|e| e.to_string())
+    }
+}
+
+fn unknown_document(uri: &Url) -> (ErrorCode, String) {
+    (
+        ErrorCode::InvalidParams,
+        format!("Unknown document: {}", uri),
+    )
+}
+
+fn params<P: DeserializeOwned>(req: Request) -> Result<P, (ErrorCode, String)> {
+    serde_json::from_value(req.params).map_err(|e| (ErrorCode::InvalidParams, e.to_string()))
+}
+
+fn notification_params<P: DeserializeOwned>(not: Notification) -> Result<P, String> {
+    serde_json::from_value(not.params).map_err(|e| format!("{}: {}", not.method, e))
+}
+
+fn to_diagnostic(text: &str, error: &SyntaxError) -> Diagnostic {
+    let mut message = error.message.clone();
+    if let Some(expected) = expected_note(error) {
+        message.push_str(&format!(", {}", expected));
+    }
+
+    Diagnostic {
+        range: Range::new(
+            position_at(text, error.location.start_byte),
+            position_at(text, error.location.end_byte),
+        ),
+        severity: Some(DiagnosticSeverity::ERROR),
+        source: Some("afmt".to_string()),
+        message,
+        ..Default::default()
+    }
+}
+
+// A single edit replacing what differs between `old` and `new`, so the
+// editor keeps the cursor and folds of the unchanged parts.
+fn text_edits(old: &str, new: &str) -> Vec<TextEdit> {
+    if old == new {
+        return vec![];
+    }
+
+    let mut prefix = old
+        .bytes()
+        .zip(new.bytes())
+        .take_while(|(a, b)| a == b)
+        .count();
+    while !old.is_char_boundary(prefix) || !new.is_char_boundary(prefix) {
+        prefix -= 1;
+    }
+
+    let max_suffix = old.len().min(new.len()) - prefix;
+    let mut suffix = old
+        .bytes()
+        .rev()
+        .zip(new.bytes().rev())
+        .take(max_suffix)
+        .take_while(|(a, b)| a == b)
+        .count();
+    while !old.is_char_boundary(old.len() - suffix) || !new.is_char_boundary(new.len() - suffix) {
+        suffix -= 1;
+    }
+
+    vec![TextEdit {
+        range: Range::new(
+            position_at(old, prefix),
+            position_at(old, old.len() - suffix),
+        ),
+        new_text: new[prefix..new.len() - suffix].to_string(),
+    }]
+}
+
+// LSP positions count UTF-16 code units within a line.
+fn offset_at(text: &str, position: Position) -> usize {
+    byte_offset(text, position.line as usize, position.character as usize)
+}
+
+fn position_at(text: &str, offset: usize) -> Position {
+    let (line, character) = utf16_position(text, offset);
+    Position::new(line as u32, character as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(old: &str, edits: &[TextEdit]) -> String {
+        let mut text = old.to_string();
+        for edit in edits.iter().rev() {
+            let start = offset_at(old, edit.range.start);
+            let end = offset_at(old, edit.range.end);
+            text.replace_range(start..end, &edit.new_text);
+        }
+        text
+    }
+
+    #[test]
+    fn utf16_positions() {
+        // `😀` is 4 bytes and a surrogate pair, `é` is 2 bytes and one code unit
+        let text = "a😀b\né c";
+        assert_eq!(offset_at(text, Position::new(0, 3)), 5);
+        assert_eq!(position_at(text, 5), Position::new(0, 3));
+        // a position inside the surrogate pair moves past it
+        assert_eq!(offset_at(text, Position::new(0, 2)), 5);
+        assert_eq!(offset_at(text, Position::new(1, 1)), 9);
+        assert_eq!(position_at(text, 9), Position::new(1, 1));
+        assert_eq!(position_at(text, 10), Position::new(1, 2));
+    }
+
+    #[test]
+    fn end_of_document() {
+        let text = "class A {\n}\n";
+        assert_eq!(position_at(text, text.len()), Position::new(2, 0));
+        assert_eq!(offset_at(text, Position::new(2, 0)), text.len());
+        assert_eq!(offset_at(text, Position::new(1, 5)), text.len() - 1);
+        assert_eq!(offset_at(text, Position::new(9, 0)), text.len());
+    }
+
+    #[test]
+    fn crlf_lines() {
+        let text = "ab\r\ncd";
+        assert_eq!(offset_at(text, Position::new(0, 2)), 2);
+        assert_eq!(offset_at(text, Position::new(0, 9)), 2);
+        assert_eq!(offset_at(text, Position::new(1, 1)), 5);
+        assert_eq!(position_at(text, 5), Position::new(1, 1));
+    }
+
+    #[test]
+    fn minimal_text_edits() {
+        assert!(text_edits("class A {}", "class A {}").is_empty());
+
+        let old = "class A {\r\n  String s = '😀';   \r\n}\r\n";
+        let new = "class A {\r\n  String s = '😀';\r\n}\r\n";
+        let edits = text_edits(old, new);
+        assert_eq!(edits.len(), 1);
+        assert_eq!(
+            edits[0].range,
+            Range::new(Position::new(1, 18), Position::new(1, 21))
+        );
+        assert_eq!(edits[0].new_text, "");
+        assert_eq!(apply(old, &edits), new);
+
+        // the edit never splits a multi-byte character
+        let old = "String s = 'é';";
+        let new = "String s = 'è';";
+        let edits = text_edits(old, new);
+        assert_eq!(edits[0].new_text, "è");
+        assert_eq!(apply(old, &edits), new);
+    }
+}
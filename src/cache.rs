@@ -0,0 +1,77 @@
+use crate::formatter::Config;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use xxhash_rust::xxh3::Xxh3;
+
+pub const DEFAULT_CACHE_FILE: &str = ".afmtcache";
+
+// bumped when the layout of the cache file changes
+const CACHE_FORMAT: u32 = 2;
+
+// Files known to be formatted, saved between runs with `--cache`.
+//
+// Each file maps to the key of the content it had when formatting left it
+// unchanged. The key also covers the afmt version and every field of the
+// effective `Config`, so a new release or a config change misses the cache.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Cache {
+    #[serde(skip)]
+    path: PathBuf,
+    format: u32,
+    entries: HashMap<String, u64>,
+}
+
+impl Cache {
+    // A missing, unreadable or outdated cache file gives an empty cache.
+    pub fn load(path: &Path) -> Self {
+        let cache = fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<Cache>(&content).ok())
+            .filter(|cache| cache.format == CACHE_FORMAT)
+            .unwrap_or_default();
+
+        Self {
+            path: path.to_path_buf(),
+            format: CACHE_FORMAT,
+            ..cache
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let content = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        fs::write(&self.path, content)
+            .map_err(|e| format!("Failed to write cache {}: {}", self.path.display(), e))
+    }
+
+    // XXH3 gives the same key for the same input on every build, unlike
+    // `DefaultHasher` which may change with the Rust release.
+    pub fn key(source_code: &str, config: &Config) -> u64 {
+        let mut hasher = Xxh3::new();
+        env!("CARGO_PKG_VERSION").hash(&mut hasher);
+        config.hash(&mut hasher);
+        source_code.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    pub fn is_formatted(&self, file: &str, key: u64) -> bool {
+        self.entries.get(&entry_name(file)) == Some(&key)
+    }
+
+    pub fn set_formatted(&mut self, file: &str, key: u64) {
+        self.entries.insert(entry_name(file), key);
+    }
+
+    pub fn remove(&mut self, file: &str) {
+        self.entries.remove(&entry_name(file));
+    }
+}
+
+// the same file can be named differently from one run to the next
+fn entry_name(file: &str) -> String {
+    fs::canonicalize(file)
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|_| file.to_string())
+}
@@ -1,14 +1,13 @@
 use std::{cell::Cell, collections::HashMap};
-use tree_sitter::Node;
+use tree_sitter::{Node, Range};
 
 use crate::{
     accessor::Accessor,
     data_model::DocBuild,
     doc::DocRef,
     doc_builder::DocBuilder,
-    utility::{
-        get_comment_bucket, is_bracket_composite_node, is_punctuation_node, panic_unknown_node,
-    },
+    error::FormatError,
+    utility::{format_context, is_bracket_composite_node, is_punctuation_node, unknown_node},
 };
 
 pub type CommentMap = HashMap<usize, CommentBucket>;
@@ -68,17 +67,27 @@ pub enum CommentType {
     Block,
 }
 
+impl CommentType {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Line => "line_comment",
+            Self::Block => "block_comment",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Comment {
     //pub id: usize,
     pub value: String,
     pub comment_type: CommentType,
     pub metadata: CommentMetadata,
+    pub range: Range,
     pub is_printed: Cell<bool>,
 }
 
 impl Comment {
-    pub fn from_node(node: Node) -> Self {
+    pub fn from_node(node: Node) -> Result<Self, FormatError> {
         //let id = node.id();
         let value = node.value().trim_end().to_string();
         let (comment_type, metadata) = match node.kind() {
@@ -90,16 +99,17 @@ impl Comment {
                 let metadata = CommentMetadata::from(&node, CommentType::Block);
                 (CommentType::Block, metadata)
             }
-            _ => panic_unknown_node(node, "Comment"),
+            _ => return Err(unknown_node(node, "Comment")),
         };
 
-        Self {
+        Ok(Self {
             //id,
             value,
             comment_type,
             metadata,
+            range: node.range(),
             is_printed: Cell::new(false),
-        }
+        })
     }
 
     pub fn has_leading_content(&self) -> bool {
@@ -226,17 +236,16 @@ pub struct Punctuation {
 }
 
 impl Punctuation {
+    // `node` is a `,` or a `;`, see `is_punctuation_node()`
     pub fn new(node: Node) -> Self {
-        match node.kind() {
-            "," => Self {
-                type_: PuncuationType::Comma,
-                id: node.id(),
-            },
-            ";" => Self {
-                type_: PuncuationType::Semicolon,
-                id: node.id(),
-            },
-            _ => panic_unknown_node(node, "Puncuation"),
+        let type_ = if node.kind() == "," {
+            PuncuationType::Comma
+        } else {
+            PuncuationType::Semicolon
+        };
+        Self {
+            type_,
+            id: node.id(),
         }
     }
 
@@ -274,7 +283,8 @@ impl<'a> DocBuild<'a> for Punctuation {
     fn build_inner(&self, b: &'a DocBuilder<'a>, result: &mut Vec<DocRef<'a>>) {
         // TODO: merge into normal bucket handling utiltiy method?
 
-        let bucket = get_comment_bucket(&self.id);
+        let context = format_context();
+        let bucket = context.comment_bucket(&self.id);
 
         // Separate line comments and block comments from pre_comments
         let (line_comments_in_pre, block_comments_in_pre): (Vec<Comment>, Vec<Comment>) = bucket
@@ -1,13 +1,15 @@
 use crate::{
     accessor::Accessor,
-    context::{Comment, CommentType, NodeInfo, Punctuation},
+    context::{NodeInfo, Punctuation},
     doc::DocRef,
     doc_builder::{DocBuilder, Insertable},
     enum_def::*,
-    message_helper::red,
+    error::FormatError,
+    formatter::IndentStyle,
     utility::*,
 };
 use std::fmt::Debug;
+use std::ops::Range;
 use tree_sitter::Node;
 
 pub trait DocBuild<'a> {
@@ -27,24 +29,25 @@ pub struct Root {
 }
 
 impl Root {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "parser_output");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "parser_output")?;
 
         let members: Vec<_> = node
             .children_vec()
             .into_iter()
-            .map(|n| BodyMember::new(&n, RootMember::new(n)))
-            .collect();
+            .map(|n| RootMember::new(n).map(|member| BodyMember::new(&n, member)))
+            .collect::<Result<_, _>>()?;
 
         let node_info = NodeInfo::with_punctuation(&node);
 
-        Self { members, node_info }
+        Ok(Self { members, node_info })
     }
 }
 
 impl<'a> DocBuild<'a> for Root {
     fn build_inner(&self, b: &'a DocBuilder<'a>, result: &mut Vec<DocRef<'a>>) {
-        let bucket = get_comment_bucket(&self.node_info.id);
+        let context = format_context();
+        let bucket = context.comment_bucket(&self.node_info.id);
         if !bucket.dangling_comments.is_empty() {
             let docs: Vec<_> = bucket
                 .dangling_comments
@@ -72,20 +75,30 @@ pub struct ClassDeclaration {
 }
 
 impl ClassDeclaration {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "class_declaration");
-
-        Self {
-            modifiers: node.try_c_by_k("modifiers").map(|n| Modifiers::new(n)),
-            name: ValueNode::new(node.c_by_n("name")),
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "class_declaration")?;
+
+        Ok(Self {
+            modifiers: node
+                .try_c_by_k("modifiers")
+                .map(Modifiers::new)
+                .transpose()?,
+            name: ValueNode::new(node.c_by_n("name")?)?,
             type_parameters: node
                 .try_c_by_k("type_parameters")
-                .map(|n| TypeParameters::new(n)),
-            superclass: node.try_c_by_k("superclass").map(|n| SuperClass::new(n)),
-            interface: node.try_c_by_k("interfaces").map(|n| Interface::new(n)),
-            body: ClassBody::new(node.c_by_n("body")),
+                .map(TypeParameters::new)
+                .transpose()?,
+            superclass: node
+                .try_c_by_k("superclass")
+                .map(SuperClass::new)
+                .transpose()?,
+            interface: node
+                .try_c_by_k("interfaces")
+                .map(Interface::new)
+                .transpose()?,
+            body: ClassBody::new(node.c_by_n("body")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -140,17 +153,20 @@ pub struct MethodDeclaration {
 }
 
 impl MethodDeclaration {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "method_declaration");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "method_declaration")?;
 
-        Self {
-            modifiers: node.try_c_by_k("modifiers").map(|n| Modifiers::new(n)),
-            type_: UnannotatedType::new(node.c_by_n("type")),
-            name: ValueNode::new(node.c_by_n("name")),
-            formal_parameters: FormalParameters::new(node.c_by_n("parameters")),
-            body: node.try_c_by_n("body").map(|n| Block::new(n)),
+        Ok(Self {
+            modifiers: node
+                .try_c_by_k("modifiers")
+                .map(Modifiers::new)
+                .transpose()?,
+            type_: UnannotatedType::new(node.c_by_n("type")?)?,
+            name: ValueNode::new(node.c_by_n("name")?)?,
+            formal_parameters: FormalParameters::new(node.c_by_n("parameters")?)?,
+            body: node.try_c_by_n("body").map(Block::new).transpose()?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -182,19 +198,19 @@ pub struct FormalParameters {
 }
 
 impl FormalParameters {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "formal_parameters");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "formal_parameters")?;
 
         let formal_parameters = node
             .try_cs_by_k("formal_parameter")
             .into_iter()
             .map(FormalParameter::new)
-            .collect();
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             formal_parameters,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -222,16 +238,22 @@ pub struct FormalParameter {
 }
 
 impl FormalParameter {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "formal_parameter");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "formal_parameter")?;
 
-        Self {
-            modifiers: node.try_c_by_k("modifiers").map(Modifiers::new),
-            type_: UnannotatedType::new(node.c_by_n("type")),
-            name: ValueNode::new(node.c_by_n("name")),
-            dimensions: node.try_c_by_k("dimensions").map(Dimensions::new),
+        Ok(Self {
+            modifiers: node
+                .try_c_by_k("modifiers")
+                .map(Modifiers::new)
+                .transpose()?,
+            type_: UnannotatedType::new(node.c_by_n("type")?)?,
+            name: ValueNode::new(node.c_by_n("name")?)?,
+            dimensions: node
+                .try_c_by_k("dimensions")
+                .map(Dimensions::new)
+                .transpose()?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -259,13 +281,13 @@ pub struct SuperClass {
 }
 
 impl SuperClass {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "superclass");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "superclass")?;
 
-        Self {
-            type_: Type::new(node.first_c()),
+        Ok(Self {
+            type_: Type::new(node.first_c()?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -286,26 +308,26 @@ pub struct Modifiers {
 }
 
 impl Modifiers {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "modifiers");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "modifiers")?;
 
         let annotations = node
             .try_cs_by_k("annotation")
             .into_iter()
             .map(Annotation::new)
-            .collect();
+            .collect::<Result<_, _>>()?;
 
         let modifiers = node
             .try_cs_by_k("modifier")
             .into_iter()
             .map(Modifier::new)
-            .collect();
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             annotations,
             modifiers,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -331,13 +353,13 @@ pub struct Modifier {
 }
 
 impl Modifier {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "modifier");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "modifier")?;
 
-        Self {
-            kind: ModifierKind::new(node.first_c()),
+        Ok(Self {
+            kind: ModifierKind::new(node.first_c()?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -357,18 +379,19 @@ pub struct Annotation {
 }
 
 impl Annotation {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "annotation");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "annotation")?;
 
         let arguments = node
             .try_c_by_n("arguments")
-            .map(AnnotationArgumentList::new);
+            .map(AnnotationArgumentList::new)
+            .transpose()?;
 
-        Self {
-            name: ValueNode::new(node.c_by_n("name")),
+        Ok(Self {
+            name: ValueNode::new(node.c_by_n("name")?)?,
             arguments,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -394,14 +417,14 @@ pub struct AnnotationKeyValue {
 }
 
 impl AnnotationKeyValue {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "annotation_key_value");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "annotation_key_value")?;
 
-        Self {
-            key: ValueNode::new(node.c_by_n("key")),
-            value: ValueNode::new(node.c_by_n("value")),
+        Ok(Self {
+            key: ValueNode::new(node.c_by_n("key")?)?,
+            value: ValueNode::new(node.c_by_n("value")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -422,26 +445,27 @@ pub struct ClassBody {
 }
 
 impl ClassBody {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "class_body");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "class_body")?;
 
         let class_members: Vec<_> = node
             .children_vec()
             .into_iter()
-            .map(|n| BodyMember::new(&n, ClassMember::new(n)))
-            .collect();
+            .map(|n| ClassMember::new(n).map(|member| BodyMember::new(&n, member)))
+            .collect::<Result<_, _>>()?;
         let node_info = NodeInfo::with_punctuation(&node);
 
-        Self {
+        Ok(Self {
             class_members,
             node_info,
-        }
+        })
     }
 }
 
 impl<'a> DocBuild<'a> for ClassBody {
     fn build_inner(&self, b: &'a DocBuilder<'a>, result: &mut Vec<DocRef<'a>>) {
-        let bucket = get_comment_bucket(&self.node_info.id);
+        let context = format_context();
+        let bucket = context.comment_bucket(&self.node_info.id);
         handle_pre_comments(b, bucket, result);
 
         if bucket.dangling_comments.is_empty() {
@@ -463,28 +487,32 @@ pub struct FieldDeclaration {
 }
 
 impl FieldDeclaration {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "field_declaration");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "field_declaration")?;
 
-        let modifiers = node.try_c_by_k("modifiers").map(|n| Modifiers::new(n));
+        let modifiers = node
+            .try_c_by_k("modifiers")
+            .map(Modifiers::new)
+            .transpose()?;
 
         let declarators = node
-            .cs_by_n("declarator")
+            .cs_by_n("declarator")?
             .into_iter()
-            .map(|n| VariableDeclarator::new(n))
-            .collect();
+            .map(VariableDeclarator::new)
+            .collect::<Result<_, _>>()?;
 
         let accessor_list = node
             .try_c_by_k("accessor_list")
-            .map(|n| AccessorList::new(n));
+            .map(AccessorList::new)
+            .transpose()?;
 
-        Self {
+        Ok(Self {
             modifiers,
-            type_: UnannotatedType::new(node.c_by_n("type")),
+            type_: UnannotatedType::new(node.c_by_n("type")?)?,
             declarators,
             accessor_list,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -524,19 +552,19 @@ pub struct ArrayInitializer {
 }
 
 impl ArrayInitializer {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "array_initializer");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "array_initializer")?;
 
         let initializers: Vec<_> = node
             .children_vec()
             .into_iter()
-            .map(|n| VariableInitializer::new(n))
-            .collect();
+            .map(VariableInitializer::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             initializers,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -564,18 +592,18 @@ pub struct AssignmentExpression {
 }
 
 impl AssignmentExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "assignment_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "assignment_expression")?;
 
-        let right_child = node.c_by_n("right");
+        let right_child = node.c_by_n("right")?;
 
-        Self {
-            left: AssignmentLeft::new(node.c_by_n("left")),
-            op: ValueNode::new(node.c_by_n("operator")),
-            right: Expression::new(right_child),
+        Ok(Self {
+            left: AssignmentLeft::new(node.c_by_n("left")?)?,
+            op: ValueNode::new(node.c_by_n("operator")?)?,
+            right: Expression::new(right_child)?,
             is_right_child_a_query_node: is_query_expression(&right_child),
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -600,17 +628,17 @@ impl<'a> DocBuild<'a> for AssignmentExpression {
 pub enum AssignmentLeft {
     Identifier(ValueNode),
     Field(FieldAccess),
-    Array(ArrayAccess),
+    Array(Box<ArrayAccess>),
 }
 
 impl AssignmentLeft {
-    pub fn new(node: Node) -> Self {
-        match node.kind() {
-            "identifier" => Self::Identifier(ValueNode::new(node)),
-            "field_access" => Self::Field(FieldAccess::new(node)),
-            "array_access" => Self::Array(ArrayAccess::new(node)),
-            _ => panic_unknown_node(node, "AssignmentLeft"),
-        }
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        Ok(match node.kind() {
+            "identifier" => Self::Identifier(ValueNode::new(node)?),
+            "field_access" => Self::Field(FieldAccess::new(node)?),
+            "array_access" => Self::Array(Box::new(ArrayAccess::new(node)?)),
+            _ => return Err(unknown_node(node, "AssignmentLeft")),
+        })
     }
 }
 
@@ -636,12 +664,12 @@ pub struct BoolType {
 }
 
 impl BoolType {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "boolean_type");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "boolean_type")?;
 
-        Self {
+        Ok(Self {
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -660,26 +688,27 @@ pub struct Block {
 }
 
 impl Block {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "block");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "block")?;
 
         let statements: Vec<BodyMember<Statement>> = node
             .children_vec()
             .into_iter()
-            .map(|n| BodyMember::new(&n, Statement::new(n)))
-            .collect();
+            .map(|n| Statement::new(n).map(|member| BodyMember::new(&n, member)))
+            .collect::<Result<_, _>>()?;
         let node_info = NodeInfo::with_punctuation(&node);
 
-        Self {
+        Ok(Self {
             statements,
             node_info,
-        }
+        })
     }
 }
 
 impl<'a> DocBuild<'a> for Block {
     fn build_inner(&self, b: &'a DocBuilder<'a>, result: &mut Vec<DocRef<'a>>) {
-        let bucket = get_comment_bucket(&self.node_info.id);
+        let context = format_context();
+        let bucket = context.comment_bucket(&self.node_info.id);
         handle_pre_comments(b, bucket, result);
 
         if bucket.dangling_comments.is_empty() {
@@ -700,13 +729,13 @@ pub struct Interface {
 }
 
 impl Interface {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "interfaces");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "interfaces")?;
 
-        Self {
-            type_list: TypeList::new(node.c_by_k("type_list")),
+        Ok(Self {
+            type_list: TypeList::new(node.c_by_k("type_list")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -727,19 +756,19 @@ pub struct TypeList {
 }
 
 impl TypeList {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "type_list");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "type_list")?;
 
         let types = node
             .children_vec()
             .into_iter()
-            .map(|n| Type::new(n))
-            .collect();
+            .map(Type::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             types,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -767,12 +796,14 @@ pub enum ObjectExpression {
 }
 
 impl ObjectExpression {
-    pub fn new(node: Node) -> Self {
-        //TODO: handle incoming comment node
-        match node.kind() {
-            "super" => Self::Super(Super::new(node)),
-            _ => Self::Primary(Box::new(PrimaryExpression::new(node))),
-        }
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        Ok(
+            //TODO: handle incoming comment node
+            match node.kind() {
+                "super" => Self::Super(Super::new(node)?),
+                _ => Self::Primary(Box::new(PrimaryExpression::new(node)?)),
+            },
+        )
     }
 }
 
@@ -865,24 +896,25 @@ pub struct MethodInvocation {
 }
 
 impl MethodInvocation {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "method_invocation");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "method_invocation")?;
 
-        let name = ValueNode::new(node.c_by_n("name"));
-        let arguments = ArgumentList::new(node.c_by_n("arguments"));
+        let name = ValueNode::new(node.c_by_n("name")?)?;
+        let arguments = ArgumentList::new(node.c_by_n("arguments")?)?;
 
         let kind = if let Some(obj) = node.try_c_by_n("object") {
-            let object = ObjectExpression::new(obj);
-            let next_named = obj.next_named();
+            let object = ObjectExpression::new(obj)?;
+            let next_named = obj.next_named()?;
             let property_navigation = if next_named.kind() == "safe_navigation_operator" {
-                PropertyNavigation::Safe(SafeNavigationOperator::new(next_named))
+                PropertyNavigation::Safe(SafeNavigationOperator::new(next_named)?)
             } else {
                 PropertyNavigation::Dot
             };
 
             let type_arguments = node
                 .try_c_by_k("type_arguments")
-                .map(|n| TypeArguments::new(n));
+                .map(TypeArguments::new)
+                .transpose()?;
             let context = build_chaining_context(&node);
 
             MethodInvocationKind::Complex {
@@ -897,10 +929,10 @@ impl MethodInvocation {
             MethodInvocationKind::Simple { name, arguments }
         };
 
-        Self {
+        Ok(Self {
             kind,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -938,17 +970,17 @@ pub struct TypeArguments {
 }
 
 impl TypeArguments {
-    pub fn new(node: Node) -> Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
         let types = node
             .children_vec()
             .into_iter()
-            .map(|n| Type::new(n))
-            .collect();
+            .map(Type::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             types,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -972,17 +1004,17 @@ pub struct ArgumentList {
 }
 
 impl ArgumentList {
-    pub fn new(node: Node) -> Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
         let expressions = node
             .children_vec()
             .into_iter()
-            .map(|n| Expression::new(n))
-            .collect();
+            .map(Expression::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             expressions,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -1006,12 +1038,12 @@ pub struct Super {
 }
 
 impl Super {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "super");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "super")?;
 
-        Self {
+        Ok(Self {
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -1029,12 +1061,12 @@ pub struct This {
 }
 
 impl This {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "this");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "this")?;
 
-        Self {
+        Ok(Self {
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -1063,34 +1095,33 @@ pub struct BinaryExpression {
 }
 
 impl BinaryExpression {
-    fn build_context(node: &Node) -> BinaryExpressionContext {
-        let op = node.c_by_n("operator").kind();
-        let precedence = get_precedence(op);
-        let parent = node
-            .parent()
-            .expect("BinaryExpression node should always have a parent");
+    fn build_context(node: &Node) -> Result<BinaryExpressionContext, FormatError> {
+        let precedence = get_precedence(&node.c_by_n("operator")?)?;
+        let parent = node.parent().ok_or_else(|| {
+            unsupported_node(node, "BinaryExpression without a parent".to_string())
+        })?;
 
         let is_a_chaining_inner_node = is_binary_exp(&parent);
-        let has_parent_same_precedence = is_binary_exp(&parent)
-            && precedence == get_precedence(parent.c_by_n("operator").kind());
+        let has_parent_same_precedence =
+            is_binary_exp(&parent) && precedence == get_precedence(&parent.c_by_n("operator")?)?;
 
-        BinaryExpressionContext {
+        Ok(BinaryExpressionContext {
             has_parent_same_precedence,
             is_a_chaining_inner_node,
             is_parent_return_statement: parent.kind() == "return_statement",
-        }
+        })
     }
 
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "binary_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "binary_expression")?;
 
-        Self {
-            left: Expression::new(node.c_by_n("left")),
-            op: node.c_by_n("operator").kind().to_string(),
-            right: Expression::new(node.c_by_n("right")),
-            context: Self::build_context(&node),
+        Ok(Self {
+            left: Expression::new(node.c_by_n("left")?)?,
+            op: node.c_by_n("operator")?.kind().to_string(),
+            right: Expression::new(node.c_by_n("right")?)?,
+            context: Self::build_context(&node)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -1126,14 +1157,14 @@ impl<'a> DocBuild<'a> for BinaryExpression {
                 ]));
             }
 
-            // otherwise:
-            result.push(b.group_indent_concat(vec![
+            // otherwise: the operands after the first are indented, or aligned with it
+            result.push(b.group(b.indent_or_align(b.concat(vec![
                 left_doc,
                 b.softline(),
                 op_doc,
                 b.txt(" "),
                 right_doc,
-            ]))
+            ]))))
         });
     }
 }
@@ -1148,23 +1179,26 @@ pub struct LocalVariableDeclaration {
 }
 
 impl LocalVariableDeclaration {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "local_variable_declaration");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "local_variable_declaration")?;
 
-        let modifiers = node.try_c_by_k("modifiers").map(|n| Modifiers::new(n));
+        let modifiers = node
+            .try_c_by_k("modifiers")
+            .map(Modifiers::new)
+            .transpose()?;
         let declarators = node
-            .cs_by_n("declarator")
+            .cs_by_n("declarator")?
             .into_iter()
-            .map(|n| VariableDeclarator::new(n))
-            .collect();
+            .map(VariableDeclarator::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             modifiers,
-            type_: UnannotatedType::new(node.c_by_n("type")),
+            type_: UnannotatedType::new(node.c_by_n("type")?)?,
             declarators,
             //is_parent_for_statement: node.parent().is_some_and(|n| n.kind() == "for_statement"),
             node_info: NodeInfo::without_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -1204,23 +1238,29 @@ pub struct VariableDeclarator {
 }
 
 impl VariableDeclarator {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "variable_declarator");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "variable_declarator")?;
 
         let mut is_value_child_a_query_node = false;
-        let op = node.try_c_by_k("assignment_operator").map(ValueNode::new);
-        let value = node.try_c_by_n("value").map(|n| {
-            is_value_child_a_query_node = is_query_expression(&n);
-            VariableInitializer::Exp(Expression::new(n))
-        });
+        let op = node
+            .try_c_by_k("assignment_operator")
+            .map(ValueNode::new)
+            .transpose()?;
+        let value = node
+            .try_c_by_n("value")
+            .map(|n| {
+                is_value_child_a_query_node = is_query_expression(&n);
+                Expression::new(n).map(VariableInitializer::Exp)
+            })
+            .transpose()?;
 
-        Self {
-            name: ValueNode::new(node.c_by_n("name")),
+        Ok(Self {
+            name: ValueNode::new(node.c_by_n("name")?)?,
             op,
             value,
             is_value_child_a_query_node,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -1230,12 +1270,10 @@ impl<'a> DocBuild<'a> for VariableDeclarator {
         build_with_comments_and_punc(b, &self.node_info, result, |b, result| {
             let mut docs = vec![self.name.build(b)];
 
-            if self.value.is_none() {
+            let Some(ref value) = self.value else {
                 result.push(b.concat(docs));
                 return;
-            }
-
-            let value = self.value.as_ref().unwrap();
+            };
             docs.push(b.txt(" "));
             if let Some(ref n) = self.op {
                 docs.push(n.build(b));
@@ -1262,22 +1300,25 @@ pub struct GenericType {
 }
 
 impl GenericType {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "generic_type");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "generic_type")?;
 
         let generic_identifier = if let Some(t) = node.try_c_by_k("type_identifier") {
             GenericIdentifier::Type(t.value())
         } else if let Some(s) = node.try_c_by_k("scoped_type_identifier") {
-            GenericIdentifier::Scoped(ScopedTypeIdentifier::new(s))
+            GenericIdentifier::Scoped(ScopedTypeIdentifier::new(s)?)
         } else {
-            panic!("## can't build generic_identifier node in GenericType");
+            return Err(unsupported_node(
+                &node,
+                "missing generic identifier in GenericType".to_string(),
+            ));
         };
 
-        Self {
+        Ok(Self {
             generic_identifier,
-            type_arguments: TypeArguments::new(node.c_by_k("type_arguments")),
+            type_arguments: TypeArguments::new(node.c_by_k("type_arguments")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -1317,17 +1358,20 @@ pub struct IfStatement {
 }
 
 impl IfStatement {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "if_statement");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "if_statement")?;
 
-        let alternative = node.try_c_by_n("alternative").map(|a| Statement::new(a));
+        let alternative = node
+            .try_c_by_n("alternative")
+            .map(Statement::new)
+            .transpose()?;
 
-        Self {
-            condition: ParenthesizedExpression::new(node.c_by_n("condition")),
-            consequence: Statement::new(node.c_by_n("consequence")),
+        Ok(Self {
+            condition: ParenthesizedExpression::new(node.c_by_n("condition")?)?,
+            consequence: Statement::new(node.c_by_n("consequence")?)?,
             alternative,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -1390,24 +1434,28 @@ pub struct ParenthesizedExpression {
 }
 
 impl ParenthesizedExpression {
-    pub fn new(node: Node) -> Self {
-        Self {
-            exp: Expression::new(node.first_c()),
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        Ok(Self {
+            exp: Expression::new(node.first_c()?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
 impl<'a> DocBuild<'a> for ParenthesizedExpression {
     fn build_inner(&self, b: &'a DocBuilder<'a>, result: &mut Vec<DocRef<'a>>) {
         build_with_comments_and_punc(b, &self.node_info, result, |b, result| {
-            // to align with prettier apex
             result.push(b.txt("("));
-            let doc = b.concat(vec![
-                b.indent(b.maybeline()),
-                b.indent(self.exp.build(b)),
-                b.maybeline(),
-            ]);
+            let doc = match b.config().indent_style {
+                // to align with prettier apex
+                IndentStyle::Block => b.concat(vec![
+                    b.indent(b.maybeline()),
+                    b.indent(self.exp.build(b)),
+                    b.maybeline(),
+                ]),
+                // right after `(`, the next lines under the first one
+                IndentStyle::Visual => b.align(0, self.exp.build(b)),
+            };
             result.push(b.group(doc));
             result.push(b.txt(")"));
         });
@@ -1416,23 +1464,27 @@ impl<'a> DocBuild<'a> for ParenthesizedExpression {
 
 #[derive(Debug)]
 pub enum ForInitOption {
-    Declaration(LocalVariableDeclaration),
+    Declaration(Box<LocalVariableDeclaration>),
     Exps(Vec<Expression>),
 }
 
 impl ForInitOption {
-    pub fn new(node: Node) -> Self {
-        match node.kind() {
-            "local_variable_declaration" => Self::Declaration(LocalVariableDeclaration::new(node)),
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        Ok(match node.kind() {
+            "local_variable_declaration" => {
+                Self::Declaration(Box::new(LocalVariableDeclaration::new(node)?))
+            }
             _ => Self::Exps(
                 node.parent()
-                    .expect("node must have parent in ForInitOption")
-                    .cs_by_n("init")
+                    .ok_or_else(|| {
+                        unsupported_node(&node, "ForInitOption without a parent".to_string())
+                    })?
+                    .cs_by_n("init")?
                     .into_iter()
-                    .map(|n| Expression::new(n))
-                    .collect(),
+                    .map(Expression::new)
+                    .collect::<Result<_, _>>()?,
             ),
-        }
+        })
     }
 }
 
@@ -1463,16 +1515,22 @@ pub struct ForStatement {
 }
 
 impl ForStatement {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "for_statement");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "for_statement")?;
 
         // when init or condition is None, the semicolon is immediate child of
         // ForStatement, for which case we need to collect and print.
         let mut punc_count = 0;
 
-        let init = node.try_c_by_n("init").map(|n| ForInitOption::new(n));
-        let condition = node.try_c_by_n("condition").map(|n| Expression::new(n));
-        let update = node.try_c_by_n("update").map(|n| Expression::new(n));
+        let init = node
+            .try_c_by_n("init")
+            .map(ForInitOption::new)
+            .transpose()?;
+        let condition = node
+            .try_c_by_n("condition")
+            .map(Expression::new)
+            .transpose()?;
+        let update = node.try_c_by_n("update").map(Expression::new).transpose()?;
 
         let semicolons = Self::build_semi_colons(&node);
 
@@ -1484,17 +1542,20 @@ impl ForStatement {
         }
 
         if punc_count > semicolons.len() {
-            panic!("## ForStatement missing semicolons.");
+            return Err(unsupported_node(
+                &node,
+                "missing semicolons in ForStatement".to_string(),
+            ));
         }
 
-        Self {
+        Ok(Self {
             init,
             condition,
             update,
-            body: Statement::new(node.c_by_n("body")),
+            body: Statement::new(node.c_by_n("body")?)?,
             semicolons,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 
     fn build_semi_colons(node: &Node) -> Vec<Punctuation> {
@@ -1511,16 +1572,17 @@ impl<'a> DocBuild<'a> for ForStatement {
         build_with_comments_and_punc(b, &self.node_info, result, |b, result| {
             result.push(b.txt("for "));
 
-            let mut semicolons_iter = self.semicolons.iter();
+            // `new()` made sure there is one for each missing part
+            let mut semicolons = self.semicolons.iter().map(|s| s.build(b));
 
             let init = match &self.init {
                 Some(i) => i.build(b),
-                None => semicolons_iter.next().unwrap().build(b),
+                None => semicolons.next().unwrap_or_else(|| b.nil()),
             };
 
             let condition = match &self.condition {
                 Some(c) => b.concat(vec![b.txt(" "), c.build(b)]),
-                None => semicolons_iter.next().unwrap().build(b),
+                None => semicolons.next().unwrap_or_else(|| b.nil()),
             };
 
             let update = match &self.update {
@@ -1559,19 +1621,22 @@ pub struct EnhancedForStatement {
 }
 
 impl EnhancedForStatement {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "enhanced_for_statement");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "enhanced_for_statement")?;
 
-        let modifiers = node.try_c_by_k("modifiers").map(|n| Modifiers::new(n));
+        let modifiers = node
+            .try_c_by_k("modifiers")
+            .map(Modifiers::new)
+            .transpose()?;
 
-        Self {
+        Ok(Self {
             modifiers,
-            type_: UnannotatedType::new(node.c_by_n("type")),
-            name: ValueNode::new(node.c_by_n("name")),
-            value: Expression::new(node.c_by_n("value")),
-            body: Statement::new(node.c_by_n("body")),
+            type_: UnannotatedType::new(node.c_by_n("type")?)?,
+            name: ValueNode::new(node.c_by_n("name")?)?,
+            value: Expression::new(node.c_by_n("value")?)?,
+            body: Statement::new(node.c_by_n("body")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -1579,6 +1644,9 @@ impl<'a> DocBuild<'a> for EnhancedForStatement {
     fn build_inner(&self, b: &'a DocBuilder<'a>, result: &mut Vec<DocRef<'a>>) {
         build_with_comments_and_punc(b, &self.node_info, result, |b, result| {
             result.push(b.txt("for ("));
+            if let Some(ref n) = self.modifiers {
+                result.push(n.build(b));
+            }
             result.push(self.type_.build(b));
             result.push(b.txt(" "));
             result.push(self.name.build(b));
@@ -1608,23 +1676,23 @@ pub enum UpdateExpressionVariant {
 }
 
 impl UpdateExpressionVariant {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "update_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "update_expression")?;
 
-        let operator_node = node.c_by_n("operator");
-        let operand_node = node.c_by_n("operand");
+        let operator_node = node.c_by_n("operator")?;
+        let operand_node = node.c_by_n("operand")?;
 
-        if operator_node.start_byte() < operand_node.start_byte() {
+        Ok(if operator_node.start_byte() < operand_node.start_byte() {
             Self::Pre {
                 operator: operator_node.value(),
-                operand: Box::new(Expression::new(operand_node)),
+                operand: Box::new(Expression::new(operand_node)?),
             }
         } else {
             Self::Post {
-                operand: Box::new(Expression::new(operand_node)),
+                operand: Box::new(Expression::new(operand_node)?),
                 operator: operator_node.value(),
             }
-        }
+        })
     }
 }
 
@@ -1652,34 +1720,36 @@ pub struct ScopedTypeIdentifier {
 }
 
 impl ScopedTypeIdentifier {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "scoped_type_identifier");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "scoped_type_identifier")?;
 
-        let prefix_node = node.first_c();
+        let prefix_node = node.first_c()?;
         let scoped_choice = match prefix_node.kind() {
             "type_identifier" => ScopedChoice::TypeIdentifier(prefix_node.value()),
-            "scoped_type_identifier" => ScopedChoice::Scoped(Box::new(Self::new(prefix_node))),
-            "generic_type" => ScopedChoice::Generic(Box::new(GenericType::new(prefix_node))),
-            _ => panic_unknown_node(prefix_node, "ScopedTypeIdentifier"),
+            "scoped_type_identifier" => ScopedChoice::Scoped(Box::new(Self::new(prefix_node)?)),
+            "generic_type" => ScopedChoice::Generic(Box::new(GenericType::new(prefix_node)?)),
+            _ => return Err(unknown_node(prefix_node, "ScopedTypeIdentifier")),
         };
 
         let annotations: Vec<_> = node
             .try_cs_by_k("annotation")
             .into_iter()
-            .map(|n| Annotation::new(n))
-            .collect();
+            .map(Annotation::new)
+            .collect::<Result<_, _>>()?;
 
-        let type_identifier_node = node
-            .cs_by_k("type_identifier")
-            .pop()
-            .expect("## mandatory node type_identifier missing in ScopedTypeIdentifier");
+        let type_identifier_node = node.cs_by_k("type_identifier")?.pop().ok_or_else(|| {
+            unsupported_node(
+                &node,
+                "type_identifier missing in ScopedTypeIdentifier".to_string(),
+            )
+        })?;
 
-        Self {
+        Ok(Self {
             scoped_choice,
             annotations,
             type_identifier: type_identifier_node.value(),
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -1733,20 +1803,24 @@ pub struct ConstructorDeclaration {
 }
 
 impl ConstructorDeclaration {
-    pub fn new(node: Node) -> Self {
-        let modifiers = node.try_c_by_k("modifiers").map(|n| Modifiers::new(n));
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        let modifiers = node
+            .try_c_by_k("modifiers")
+            .map(Modifiers::new)
+            .transpose()?;
         let type_parameters = node
             .try_c_by_k("type_parameters")
-            .map(|n| TypeParameters::new(n));
+            .map(TypeParameters::new)
+            .transpose()?;
 
-        Self {
+        Ok(Self {
             modifiers,
             type_parameters,
-            name: ValueNode::new(node.c_by_n("name")),
-            parameters: FormalParameters::new(node.c_by_n("parameters")),
-            body: ConstructorBody::new(node.c_by_n("body")),
+            name: ValueNode::new(node.c_by_n("name")?)?,
+            parameters: FormalParameters::new(node.c_by_n("parameters")?)?,
+            body: ConstructorBody::new(node.c_by_n("body")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -1776,29 +1850,30 @@ pub struct ConstructorBody {
 }
 
 impl ConstructorBody {
-    pub fn new(node: Node) -> Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
         let mut constructor_invocation = None;
         let mut statements: Vec<BodyMember<Statement>> = Vec::new();
 
         for (i, c) in node.children_vec().into_iter().enumerate() {
             if i == 0 && c.kind() == "explicit_constructor_invocation" {
-                constructor_invocation = Some(BodyMember::new(&c, ConstructInvocation::new(c)));
+                constructor_invocation = Some(BodyMember::new(&c, ConstructInvocation::new(c)?));
             } else {
-                statements.push(BodyMember::new(&c, Statement::new(c)));
+                statements.push(BodyMember::new(&c, Statement::new(c)?));
             }
         }
 
-        Self {
+        Ok(Self {
             constructor_invocation,
             statements,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
 impl<'a> DocBuild<'a> for ConstructorBody {
     fn build_inner(&self, b: &'a DocBuilder<'a>, result: &mut Vec<DocRef<'a>>) {
-        let bucket = get_comment_bucket(&self.node_info.id);
+        let context = format_context();
+        let bucket = context.comment_bucket(&self.node_info.id);
         handle_pre_comments(b, bucket, result);
 
         if bucket.dangling_comments.is_empty() {
@@ -1841,28 +1916,33 @@ pub struct ConstructInvocation {
 }
 
 impl ConstructInvocation {
-    pub fn new(node: Node) -> Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
         let object = node
             .try_c_by_n("object")
-            .map(|n| Box::new(PrimaryExpression::new(n)));
+            .map(|n| PrimaryExpression::new(n).map(Box::new))
+            .transpose()?;
 
         let type_arguments = node
             .try_c_by_k("type_arguments")
-            .map(|n| TypeArguments::new(n));
-
-        let constructor = node.try_c_by_n("constructor").map(|n| match n.kind() {
-            "this" => Constructor::This,
-            "super" => Constructor::Super,
-            _ => panic_unknown_node(n, "Constructor"),
-        });
+            .map(TypeArguments::new)
+            .transpose()?;
+
+        let constructor = node
+            .try_c_by_n("constructor")
+            .map(|n| match n.kind() {
+                "this" => Ok(Constructor::This),
+                "super" => Ok(Constructor::Super),
+                _ => Err(unknown_node(n, "Constructor")),
+            })
+            .transpose()?;
 
-        Self {
+        Ok(Self {
             object,
             type_arguments,
             constructor,
-            arguments: ArgumentList::new(node.c_by_n("arguments")),
+            arguments: ArgumentList::new(node.c_by_n("arguments")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -1908,19 +1988,19 @@ pub struct TypeParameters {
 }
 
 impl TypeParameters {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "type_parameters");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "type_parameters")?;
 
         let type_parameters: Vec<_> = node
-            .cs_by_k("type_parameter")
+            .cs_by_k("type_parameter")?
             .into_iter()
-            .map(|n| TypeParameter::new(n))
-            .collect();
+            .map(TypeParameter::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             type_parameters,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -1946,18 +2026,18 @@ pub struct TypeParameter {
 }
 
 impl TypeParameter {
-    pub fn new(node: Node) -> Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
         let annotations: Vec<_> = node
             .try_cs_by_k("annotation")
             .into_iter()
-            .map(|n| Annotation::new(n))
-            .collect();
+            .map(Annotation::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             annotations,
-            type_identifier: node.cvalue_by_k("type_identifier"),
+            type_identifier: node.cvalue_by_k("type_identifier")?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -1985,21 +2065,25 @@ pub struct ObjectCreationExpression {
 }
 
 impl ObjectCreationExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "object_creation_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "object_creation_expression")?;
 
         let type_arguments = node
             .try_c_by_k("type_arguments")
-            .map(|n| TypeArguments::new(n));
-        let class_body = node.try_c_by_k("class_body").map(|n| ClassBody::new(n));
-
-        Self {
+            .map(TypeArguments::new)
+            .transpose()?;
+        let class_body = node
+            .try_c_by_k("class_body")
+            .map(ClassBody::new)
+            .transpose()?;
+
+        Ok(Self {
             type_arguments,
-            type_: UnannotatedType::new(node.c_by_n("type")),
-            arguments: ArgumentList::new(node.c_by_n("arguments")),
+            type_: UnannotatedType::new(node.c_by_n("type")?)?,
+            arguments: ArgumentList::new(node.c_by_n("arguments")?)?,
             class_body,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -2030,14 +2114,14 @@ pub struct RunAsStatement {
 }
 
 impl RunAsStatement {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "run_as_statement");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "run_as_statement")?;
 
-        Self {
-            user: ParenthesizedExpression::new(node.c_by_n("user")),
-            block: Block::new(node.c_by_k("block")),
+        Ok(Self {
+            user: ParenthesizedExpression::new(node.c_by_n("user")?)?,
+            block: Block::new(node.c_by_k("block")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -2060,15 +2144,15 @@ pub struct DoStatement {
 }
 
 impl DoStatement {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "do_statement");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "do_statement")?;
 
-        Self {
-            body: Block::new(node.c_by_n("body")),
-            condition: ParenthesizedExpression::new(node.c_by_n("condition")),
+        Ok(Self {
+            body: Block::new(node.c_by_n("body")?)?,
+            condition: ParenthesizedExpression::new(node.c_by_n("condition")?)?,
 
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -2091,14 +2175,14 @@ pub struct WhileStatement {
 }
 
 impl WhileStatement {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "while_statement");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "while_statement")?;
 
-        Self {
-            condition: ParenthesizedExpression::new(node.c_by_n("condition")),
-            body: Statement::new(node.c_by_n("body")),
+        Ok(Self {
+            condition: ParenthesizedExpression::new(node.c_by_n("condition")?)?,
+            body: Statement::new(node.c_by_n("body")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -2127,15 +2211,15 @@ pub struct UnaryExpression {
 }
 
 impl UnaryExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "unary_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "unary_expression")?;
 
-        let operator = node.cvalue_by_n("operator");
-        Self {
+        let operator = node.cvalue_by_n("operator")?;
+        Ok(Self {
             operator,
-            operand: Box::new(Expression::new(node.c_by_n("operand"))),
+            operand: Box::new(Expression::new(node.c_by_n("operand")?)?),
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -2158,33 +2242,33 @@ pub struct FieldAccess {
 }
 
 impl FieldAccess {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "field_access");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "field_access")?;
 
-        let obj_node = node.c_by_n("object");
+        let obj_node = node.c_by_n("object")?;
         let object = if obj_node.kind() == "super" {
-            MethodObject::Super(Super::new(obj_node))
+            MethodObject::Super(Super::new(obj_node)?)
         } else {
-            MethodObject::Primary(Box::new(PrimaryExpression::new(obj_node)))
+            MethodObject::Primary(Box::new(PrimaryExpression::new(obj_node)?))
         };
 
-        Self {
+        Ok(Self {
             object,
-            property_navigation: Self::get_property_navigation(&node),
-            field: FieldOption::new(node.c_by_n("field")),
+            property_navigation: Self::get_property_navigation(&node)?,
+            field: FieldOption::new(node.c_by_n("field")?)?,
             context: build_chaining_context(&node),
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 
-    fn get_property_navigation(parent_node: &Node) -> PropertyNavigation {
+    fn get_property_navigation(parent_node: &Node) -> Result<PropertyNavigation, FormatError> {
         let property_navigation =
             if let Some(n) = parent_node.try_c_by_k("safe_navigation_operator") {
-                PropertyNavigation::Safe(SafeNavigationOperator::new(n))
+                PropertyNavigation::Safe(SafeNavigationOperator::new(n)?)
             } else {
                 PropertyNavigation::Dot
             };
-        property_navigation
+        Ok(property_navigation)
     }
 }
 
@@ -2207,7 +2291,7 @@ impl<'a> DocBuild<'a> for FieldAccess {
             if self
                 .context
                 .as_ref()
-                .map_or(false, |context| context.is_top_most_in_a_chain)
+                .is_some_and(|context| context.is_top_most_in_a_chain)
             {
                 result.push(b.group_indent_concat(docs));
             } else {
@@ -2224,11 +2308,11 @@ pub enum FieldOption {
 }
 
 impl FieldOption {
-    pub fn new(node: Node) -> Self {
-        match node.kind() {
-            "this" => Self::This(This::new(node)),
-            _ => Self::Identifier(ValueNode::new(node)),
-        }
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        Ok(match node.kind() {
+            "this" => Self::This(This::new(node)?),
+            _ => Self::Identifier(ValueNode::new(node)?),
+        })
     }
 }
 
@@ -2255,17 +2339,23 @@ pub struct EnumDeclaration {
 }
 
 impl EnumDeclaration {
-    pub fn new(node: Node) -> Self {
-        let modifiers = node.try_c_by_k("modifiers").map(|n| Modifiers::new(n));
-        let interface = node.try_c_by_k("interfaces").map(|n| Interface::new(n));
-
-        Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        let modifiers = node
+            .try_c_by_k("modifiers")
+            .map(Modifiers::new)
+            .transpose()?;
+        let interface = node
+            .try_c_by_k("interfaces")
+            .map(Interface::new)
+            .transpose()?;
+
+        Ok(Self {
             modifiers,
-            name: ValueNode::new(node.c_by_n("name")),
+            name: ValueNode::new(node.c_by_n("name")?)?,
             interface,
-            body: EnumBody::new(node.c_by_n("body")),
+            body: EnumBody::new(node.c_by_n("body")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -2294,25 +2384,26 @@ pub struct EnumBody {
 }
 
 impl EnumBody {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "enum_body");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "enum_body")?;
 
         let enum_constants = node
             .try_cs_by_k("enum_constant")
             .into_iter()
-            .map(|n| EnumConstant::new(n))
-            .collect();
+            .map(EnumConstant::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             enum_constants,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
 impl<'a> DocBuild<'a> for EnumBody {
     fn build_inner(&self, b: &'a DocBuilder<'a>, result: &mut Vec<DocRef<'a>>) {
-        let bucket = get_comment_bucket(&self.node_info.id);
+        let context = format_context();
+        let bucket = context.comment_bucket(&self.node_info.id);
         handle_pre_comments(b, bucket, result);
 
         if bucket.dangling_comments.is_empty() {
@@ -2342,15 +2433,18 @@ pub struct EnumConstant {
 }
 
 impl EnumConstant {
-    pub fn new(node: Node) -> Self {
-        let modifiers = node.try_c_by_k("modifiers").map(|n| Modifiers::new(n));
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        let modifiers = node
+            .try_c_by_k("modifiers")
+            .map(Modifiers::new)
+            .transpose()?;
 
-        Self {
+        Ok(Self {
             modifiers,
-            name: ValueNode::new(node.c_by_n("name")),
+            name: ValueNode::new(node.c_by_n("name")?)?,
 
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -2387,24 +2481,26 @@ pub enum DmlExpressionVariant {
 }
 
 impl DmlExpressionVariant {
-    pub fn new(node: Node) -> Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
         let security_mode = node
             .try_c_by_k("dml_security_mode")
-            .map(|n| DmlSecurityMode::new(n));
-        let target = Expression::new(node.c_by_n("target"));
+            .map(DmlSecurityMode::new)
+            .transpose()?;
+        let target = Expression::new(node.c_by_n("target")?)?;
 
-        let dml_type = DmlType::new(node.c_by_k("dml_type"));
-        match dml_type.variant {
+        let dml_type = DmlType::new(node.c_by_k("dml_type")?)?;
+        Ok(match dml_type.variant {
             DmlTypeVariant::Merge => Self::Merge {
                 dml_type,
                 security_mode,
                 target,
-                merge_with: Expression::new(node.c_by_n("merge_with")),
+                merge_with: Expression::new(node.c_by_n("merge_with")?)?,
             },
             DmlTypeVariant::Upsert => {
                 let unannotated = node
                     .try_c_by_n("upsert_key")
-                    .map(|n| Box::new(UnannotatedType::new(n)));
+                    .map(|n| UnannotatedType::new(n).map(Box::new))
+                    .transpose()?;
                 Self::Upsert {
                     dml_type,
                     security_mode,
@@ -2417,7 +2513,7 @@ impl DmlExpressionVariant {
                 security_mode,
                 target,
             },
-        }
+        })
     }
 }
 
@@ -2451,7 +2547,13 @@ impl<'a> DocBuild<'a> for DmlExpressionVariant {
                 }
 
                 let docs = b.to_docs(vec![exp, exp_extra]);
-                let sep = Insertable::new::<&str>(None, None, Some(b.softline()));
+                // sfapex rejects a tab between the two records, indentation included
+                let line = if b.config().use_tabs {
+                    b.txt(" ")
+                } else {
+                    b.softline()
+                };
+                let sep = Insertable::new::<&str>(None, None, Some(line));
                 let doc = b.group(b.indent(b.intersperse(&docs, sep)));
                 result.push(doc);
             }
@@ -2491,13 +2593,13 @@ pub enum DmlSecurityMode {
 }
 
 impl DmlSecurityMode {
-    pub fn new(n: Node) -> Self {
-        let child = n.first_c();
-        match child.kind() {
+    pub fn new(n: Node) -> Result<Self, FormatError> {
+        let child = n.first_c()?;
+        Ok(match child.kind() {
             "user" => Self::User(child.value()),
             "system" => Self::System(child.value()),
-            _ => panic_unknown_node(n, "DmlSecurityMode"),
-        }
+            _ => return Err(unknown_node(n, "DmlSecurityMode")),
+        })
     }
 }
 
@@ -2522,17 +2624,17 @@ pub enum DmlTypeVariant {
 }
 
 impl DmlTypeVariant {
-    pub fn new(node: Node) -> Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
         let k = node.kind();
-        match k {
+        Ok(match k {
             "insert" => Self::Insert,
             "update" => Self::Update,
             "delete" => Self::Delete,
             "undelete" => Self::Undelete,
             "merge" => Self::Merge,
             "upsert" => Self::Upsert,
-            _ => panic!("## unknown node: {} in DmlTypeVariant ", red(k)),
-        }
+            _ => return Err(unknown_node(node, "DmlTypeVariant")),
+        })
     }
 }
 
@@ -2558,14 +2660,14 @@ pub struct ArrayAccess {
 }
 
 impl ArrayAccess {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "array_access");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "array_access")?;
 
-        Self {
-            array: PrimaryExpression::new(node.c_by_n("array")),
-            index: Expression::new(node.c_by_n("index")),
+        Ok(Self {
+            array: PrimaryExpression::new(node.c_by_n("array")?)?,
+            index: Expression::new(node.c_by_n("index")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -2588,41 +2690,45 @@ pub struct ArrayCreationExpression {
 }
 
 impl ArrayCreationExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "array_creation_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "array_creation_expression")?;
 
         let value_node = node.try_c_by_n("value");
         let dimensions_node = node.try_c_by_n("dimensions");
 
-        let variant = if value_node.is_none() {
-            // DD
-            let dimensions_exprs = node
-                .cs_by_k("dimensions_expr")
-                .into_iter()
-                .map(|n| DimensionsExpr::new(n))
-                .collect();
-            let dimensions = node.try_c_by_k("dimensions").map(|n| Dimensions::new(n));
-            ArrayCreationVariant::DD {
-                dimensions_exprs,
-                dimensions,
-            }
-        } else if dimensions_node.is_none() {
-            //OnlyV
-            let value = ArrayInitializer::new(node.c_by_n("value"));
-            ArrayCreationVariant::OnlyV { value }
-        } else {
-            //DV
-            ArrayCreationVariant::DV {
-                value: ArrayInitializer::new(value_node.unwrap()),
-                dimensions: Dimensions::new(dimensions_node.unwrap()),
-            }
-        };
+        let variant =
+            if let (Some(value_node), Some(dimensions_node)) = (value_node, dimensions_node) {
+                //DV
+                ArrayCreationVariant::DV {
+                    value: ArrayInitializer::new(value_node)?,
+                    dimensions: Dimensions::new(dimensions_node)?,
+                }
+            } else if value_node.is_none() {
+                // DD
+                let dimensions_exprs = node
+                    .cs_by_k("dimensions_expr")?
+                    .into_iter()
+                    .map(DimensionsExpr::new)
+                    .collect::<Result<_, _>>()?;
+                let dimensions = node
+                    .try_c_by_k("dimensions")
+                    .map(Dimensions::new)
+                    .transpose()?;
+                ArrayCreationVariant::DD {
+                    dimensions_exprs,
+                    dimensions,
+                }
+            } else {
+                //OnlyV
+                let value = ArrayInitializer::new(node.c_by_n("value")?)?;
+                ArrayCreationVariant::OnlyV { value }
+            };
 
-        Self {
-            type_: SimpleType::new(node.c_by_n("type")),
+        Ok(Self {
+            type_: SimpleType::new(node.c_by_n("type")?)?,
             variant,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -2684,12 +2790,12 @@ pub struct Dimensions {
 }
 
 impl Dimensions {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "dimensions");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "dimensions")?;
 
-        Self {
+        Ok(Self {
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -2708,13 +2814,13 @@ pub struct DimensionsExpr {
 }
 
 impl DimensionsExpr {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "dimensions_expr");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "dimensions_expr")?;
 
-        Self {
-            exp: Expression::new(node.first_c()),
+        Ok(Self {
+            exp: Expression::new(node.first_c()?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -2735,16 +2841,16 @@ pub struct ReturnStatement {
 }
 
 impl ReturnStatement {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "return_statement");
-        let exp = node.try_first_c().map(|n| Expression::new(n));
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "return_statement")?;
+        let exp = node.try_first_c().map(Expression::new).transpose()?;
         let node_info = if exp.is_none() {
             NodeInfo::with_inner_punctuation(&node)
         } else {
             NodeInfo::with_punctuation(&node)
         };
 
-        Self { exp, node_info }
+        Ok(Self { exp, node_info })
     }
 }
 
@@ -2769,15 +2875,15 @@ pub struct TernaryExpression {
 }
 
 impl TernaryExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "ternary_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "ternary_expression")?;
 
-        Self {
-            condition: Expression::new(node.c_by_n("condition")),
-            consequence: Expression::new(node.c_by_n("consequence")),
-            alternative: Expression::new(node.c_by_n("alternative")),
+        Ok(Self {
+            condition: Expression::new(node.c_by_n("condition")?)?,
+            consequence: Expression::new(node.c_by_n("consequence")?)?,
+            alternative: Expression::new(node.c_by_n("alternative")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -2806,30 +2912,30 @@ pub struct TryStatement {
 }
 
 impl TryStatement {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "try_statement");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "try_statement")?;
 
         let tail = if node.try_c_by_k("finally_clause").is_some() {
             TryStatementTail::CatchesFinally(
                 node.try_cs_by_k("catch_clause")
                     .into_iter()
-                    .map(|n| CatchClause::new(n))
-                    .collect(),
-                FinallyClause::new(node.c_by_k("finally_clause")),
+                    .map(CatchClause::new)
+                    .collect::<Result<_, _>>()?,
+                FinallyClause::new(node.c_by_k("finally_clause")?)?,
             )
         } else {
             TryStatementTail::Catches(
-                node.cs_by_k("catch_clause")
+                node.cs_by_k("catch_clause")?
                     .into_iter()
-                    .map(|n| CatchClause::new(n))
-                    .collect(),
+                    .map(CatchClause::new)
+                    .collect::<Result<_, _>>()?,
             )
         };
-        Self {
-            body: Block::new(node.c_by_n("body")),
+        Ok(Self {
+            body: Block::new(node.c_by_n("body")?)?,
             tail,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -2875,14 +2981,14 @@ pub struct CatchClause {
 }
 
 impl CatchClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "catch_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "catch_clause")?;
 
-        Self {
-            formal_parameter: FormalParameter::new(node.c_by_k("formal_parameter")),
-            body: Block::new(node.c_by_n("body")),
+        Ok(Self {
+            formal_parameter: FormalParameter::new(node.c_by_k("formal_parameter")?)?,
+            body: Block::new(node.c_by_n("body")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -2906,13 +3012,13 @@ pub struct FinallyClause {
 }
 
 impl FinallyClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "finally_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "finally_clause")?;
 
-        Self {
-            body: Block::new(node.c_by_k("block")),
+        Ok(Self {
+            body: Block::new(node.c_by_k("block")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -2932,11 +3038,11 @@ pub struct StaticInitializer {
 }
 
 impl StaticInitializer {
-    pub fn new(node: Node) -> Self {
-        Self {
-            block: Block::new(node.c_by_k("block")),
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        Ok(Self {
+            block: Block::new(node.c_by_k("block")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -2960,25 +3066,30 @@ pub struct InterfaceDeclaration {
 }
 
 impl InterfaceDeclaration {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "interface_declaration");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "interface_declaration")?;
 
-        let modifiers = node.try_c_by_k("modifiers").map(|n| Modifiers::new(n));
+        let modifiers = node
+            .try_c_by_k("modifiers")
+            .map(Modifiers::new)
+            .transpose()?;
         let type_parameters = node
             .try_c_by_k("type_parameters")
-            .map(|n| TypeParameters::new(n));
+            .map(TypeParameters::new)
+            .transpose()?;
         let extends = node
             .try_c_by_k("extends_interfaces")
-            .map(|n| ExtendsInterface::new(n));
+            .map(ExtendsInterface::new)
+            .transpose()?;
 
-        Self {
+        Ok(Self {
             modifiers,
-            name: ValueNode::new(node.c_by_n("name")),
+            name: ValueNode::new(node.c_by_n("name")?)?,
             type_parameters,
             extends,
-            body: InterfaceBody::new(node.c_by_n("body")),
+            body: InterfaceBody::new(node.c_by_n("body")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3012,11 +3123,11 @@ pub struct ExtendsInterface {
 }
 
 impl ExtendsInterface {
-    pub fn new(node: Node) -> Self {
-        Self {
-            type_list: TypeList::new(node.c_by_k("type_list")),
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        Ok(Self {
+            type_list: TypeList::new(node.c_by_k("type_list")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3037,40 +3148,26 @@ pub struct InterfaceBody {
 }
 
 impl InterfaceBody {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "interface_body");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "interface_body")?;
 
         let members: Vec<_> = node
             .children_vec()
             .into_iter()
-            .map(|n| {
-                let member = match n.kind() {
-                    "constant_declaration" => {
-                        InterfaceMember::Constant(ConstantDeclaration::new(n))
-                    }
-                    "enum_declaration" => InterfaceMember::EnumD(EnumDeclaration::new(n)),
-                    "method_declaration" => InterfaceMember::Method(MethodDeclaration::new(n)),
-                    "class_declaration" => InterfaceMember::Class(ClassDeclaration::new(n)),
-                    "interface_declaration" => {
-                        InterfaceMember::Interface(InterfaceDeclaration::new(n))
-                    }
-                    _ => panic_unknown_node(n, "InterfaceBody"),
-                };
-
-                BodyMember::new(&n, member)
-            })
-            .collect();
+            .map(|n| InterfaceMember::new(n).map(|member| BodyMember::new(&n, member)))
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             members,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
 impl<'a> DocBuild<'a> for InterfaceBody {
     fn build_inner(&self, b: &'a DocBuilder<'a>, result: &mut Vec<DocRef<'a>>) {
-        let bucket = get_comment_bucket(&self.node_info.id);
+        let context = format_context();
+        let bucket = context.comment_bucket(&self.node_info.id);
         handle_pre_comments(b, bucket, result);
 
         if bucket.dangling_comments.is_empty() {
@@ -3089,7 +3186,23 @@ pub enum InterfaceMember {
     Method(MethodDeclaration),
     Class(ClassDeclaration),
     Interface(InterfaceDeclaration),
-    Semicolon,
+    Verbatim(Verbatim),
+}
+
+impl InterfaceMember {
+    pub fn new(n: Node) -> Result<Self, FormatError> {
+        if is_format_ignored(&n) {
+            return Ok(Self::Verbatim(Verbatim::new(n)?));
+        }
+        Ok(match n.kind() {
+            "constant_declaration" => Self::Constant(ConstantDeclaration::new(n)?),
+            "enum_declaration" => Self::EnumD(EnumDeclaration::new(n)?),
+            "method_declaration" => Self::Method(MethodDeclaration::new(n)?),
+            "class_declaration" => Self::Class(ClassDeclaration::new(n)?),
+            "interface_declaration" => Self::Interface(InterfaceDeclaration::new(n)?),
+            _ => return Err(unknown_node(n, "InterfaceBody")),
+        })
+    }
 }
 
 impl<'a> DocBuild<'a> for InterfaceMember {
@@ -3110,8 +3223,8 @@ impl<'a> DocBuild<'a> for InterfaceMember {
             Self::Interface(n) => {
                 result.push(n.build(b));
             }
-            _ => {
-                unimplemented!()
+            Self::Verbatim(n) => {
+                result.push(n.build(b));
             }
         }
     }
@@ -3126,20 +3239,23 @@ pub struct ConstantDeclaration {
 }
 
 impl ConstantDeclaration {
-    pub fn new(node: Node) -> Self {
-        let modifiers = node.try_c_by_k("modifiers").map(|n| Modifiers::new(n));
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        let modifiers = node
+            .try_c_by_k("modifiers")
+            .map(Modifiers::new)
+            .transpose()?;
         let declarators = node
-            .cs_by_n("declarator")
+            .cs_by_n("declarator")?
             .into_iter()
-            .map(|n| VariableDeclarator::new(n))
-            .collect();
+            .map(VariableDeclarator::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             modifiers,
-            type_: UnannotatedType::new(node.c_by_n("type")),
+            type_: UnannotatedType::new(node.c_by_n("type")?)?,
             declarators,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3169,21 +3285,21 @@ pub struct AccessorList {
 }
 
 impl AccessorList {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "accessor_list");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "accessor_list")?;
 
         let accessor_declarations: Vec<_> = node
-            .cs_by_k("accessor_declaration")
+            .cs_by_k("accessor_declaration")?
             .into_iter()
-            .map(|n| AccessorDeclaration::new(n))
-            .collect();
+            .map(AccessorDeclaration::new)
+            .collect::<Result<_, _>>()?;
         let child_has_body_section = accessor_declarations.iter().any(|n| n.body.is_some());
 
-        Self {
+        Ok(Self {
             accessor_declarations,
             child_has_body_section,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3225,15 +3341,18 @@ pub struct AccessorDeclaration {
 }
 
 impl AccessorDeclaration {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "accessor_declaration");
-
-        Self {
-            modifiers: node.try_c_by_k("modifiers").map(|n| Modifiers::new(n)),
-            accessor: node.cvalue_by_n("accessor"),
-            body: node.try_c_by_n("body").map(|n| Block::new(n)),
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "accessor_declaration")?;
+
+        Ok(Self {
+            modifiers: node
+                .try_c_by_k("modifiers")
+                .map(Modifiers::new)
+                .transpose()?,
+            accessor: node.cvalue_by_n("accessor")?,
+            body: node.try_c_by_n("body").map(Block::new).transpose()?,
             node_info: NodeInfo::with_inner_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3261,14 +3380,14 @@ pub struct CastExpression {
 }
 
 impl CastExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "cast_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "cast_expression")?;
 
-        Self {
-            type_: Type::new(node.c_by_n("type")),
-            value: Expression::new(node.c_by_n("value")),
+        Ok(Self {
+            type_: Type::new(node.c_by_n("type")?)?,
+            value: Expression::new(node.c_by_n("value")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3290,13 +3409,13 @@ pub struct ThrowStatement {
 }
 
 impl ThrowStatement {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "throw_statement");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "throw_statement")?;
 
-        Self {
-            exp: Expression::new(node.first_c()),
+        Ok(Self {
+            exp: Expression::new(node.first_c()?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3316,13 +3435,16 @@ pub struct BreakStatement {
 }
 
 impl BreakStatement {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "break_statement");
-
-        Self {
-            identifier: node.try_c_by_k("identifier").map(|n| ValueNode::new(n)),
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "break_statement")?;
+
+        Ok(Self {
+            identifier: node
+                .try_c_by_k("identifier")
+                .map(ValueNode::new)
+                .transpose()?,
             node_info: NodeInfo::with_inner_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3346,20 +3468,23 @@ pub struct ContinueStatement {
 }
 
 impl ContinueStatement {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "continue_statement");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "continue_statement")?;
 
-        let identifier = node.try_c_by_k("identifier").map(|n| ValueNode::new(n));
+        let identifier = node
+            .try_c_by_k("identifier")
+            .map(ValueNode::new)
+            .transpose()?;
         let node_info = if identifier.is_none() {
             NodeInfo::with_inner_punctuation(&node)
         } else {
             NodeInfo::with_punctuation(&node)
         };
 
-        Self {
+        Ok(Self {
             identifier,
             node_info,
-        }
+        })
     }
 }
 
@@ -3384,14 +3509,14 @@ pub struct SwitchExpression {
 }
 
 impl SwitchExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "switch_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "switch_expression")?;
 
-        Self {
-            condition: Expression::new(node.c_by_n("condition")),
-            body: SwitchBlock::new(node.c_by_n("body")),
+        Ok(Self {
+            condition: Expression::new(node.c_by_n("condition")?)?,
+            body: SwitchBlock::new(node.c_by_n("body")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3414,19 +3539,19 @@ pub struct SwitchBlock {
 }
 
 impl SwitchBlock {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "switch_block");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "switch_block")?;
 
         let rules = node
-            .cs_by_k("switch_rule")
+            .cs_by_k("switch_rule")?
             .into_iter()
-            .map(|n| SwitchRule::new(n))
-            .collect();
+            .map(SwitchRule::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             rules,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3452,14 +3577,14 @@ pub struct SwitchRule {
 }
 
 impl SwitchRule {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "switch_rule");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "switch_rule")?;
 
-        Self {
-            label: SwitchLabel::new(node.c_by_k("switch_label")),
-            block: Block::new(node.c_by_k("block")),
+        Ok(Self {
+            label: SwitchLabel::new(node.c_by_k("switch_label")?)?,
+            block: Block::new(node.c_by_k("block")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3481,21 +3606,21 @@ pub enum SwitchLabel {
 }
 
 impl SwitchLabel {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "switch_label");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "switch_label")?;
 
-        if node.children_vec().is_empty() {
+        Ok(if node.children_vec().is_empty() {
             Self::Else
         } else if let Some(when_node) = node.try_c_by_k("when_sobject_type") {
-            Self::WhenSObject(WhenSObjectType::new(when_node))
+            Self::WhenSObject(WhenSObjectType::new(when_node)?)
         } else {
             let expressions = node
                 .children_vec()
                 .into_iter()
                 .map(Expression::new)
-                .collect();
+                .collect::<Result<_, _>>()?;
             Self::Expressions(expressions)
-        }
+        })
     }
 }
 
@@ -3527,7 +3652,7 @@ pub struct WhenSObjectType {
 }
 
 impl WhenSObjectType {
-    pub fn new(node: Node) -> Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
         let mut unannotated_type = None;
         let mut identifier = None;
 
@@ -3537,17 +3662,18 @@ impl WhenSObjectType {
                     identifier = Some(child.value());
                 }
                 _ => {
-                    unannotated_type = Some(UnannotatedType::new(child));
+                    unannotated_type = Some(UnannotatedType::new(child)?);
                 }
             }
         }
 
-        Self {
-            unannotated_type: unannotated_type
-                .expect("Missing unannotated_type in WhenSObjectType"),
-            identifier: identifier.expect("Missing identifier in WhenSObjectType"),
+        let missing =
+            |what: &str| unsupported_node(&node, format!("{} missing in WhenSObjectType", what));
+        Ok(Self {
+            unannotated_type: unannotated_type.ok_or_else(|| missing("unannotated_type"))?,
+            identifier: identifier.ok_or_else(|| missing("identifier"))?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3569,14 +3695,14 @@ pub struct InstanceOfExpression {
 }
 
 impl InstanceOfExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "instanceof_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "instanceof_expression")?;
 
-        Self {
-            left: Expression::new(node.c_by_n("left")),
-            right: Type::new(node.c_by_n("right")),
+        Ok(Self {
+            left: Expression::new(node.c_by_n("left")?)?,
+            right: Type::new(node.c_by_n("right")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3597,14 +3723,17 @@ pub struct VersionExpression {
 }
 
 impl VersionExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "version_expression");
-
-        let version_number = node.try_c_by_n("version_num").map(|n| ValueNode::new(n));
-        Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "version_expression")?;
+
+        let version_number = node
+            .try_c_by_n("version_num")
+            .map(ValueNode::new)
+            .transpose()?;
+        Ok(Self {
             version_number,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3628,13 +3757,13 @@ pub struct JavaFieldAccess {
 }
 
 impl JavaFieldAccess {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "java_field_access");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "java_field_access")?;
 
-        Self {
-            field_access: FieldAccess::new(node.c_by_k("field_access")),
+        Ok(Self {
+            field_access: FieldAccess::new(node.c_by_k("field_access")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3654,14 +3783,14 @@ pub struct JavaType {
 }
 
 impl JavaType {
-    pub fn new(node: Node) -> Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
         let scoped_type_identifier =
-            ScopedTypeIdentifier::new(node.c_by_k("scoped_type_identifier"));
+            ScopedTypeIdentifier::new(node.c_by_k("scoped_type_identifier")?)?;
 
-        Self {
+        Ok(Self {
             scoped_type_identifier,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3682,14 +3811,14 @@ pub struct ArrayType {
 }
 
 impl ArrayType {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "array_type");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "array_type")?;
 
-        Self {
-            element: UnannotatedType::new(node.c_by_n("element")),
-            dimensions: Dimensions::new(node.c_by_n("dimensions")),
+        Ok(Self {
+            element: UnannotatedType::new(node.c_by_n("element")?)?,
+            dimensions: Dimensions::new(node.c_by_n("dimensions")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3712,22 +3841,22 @@ pub struct TriggerDeclaration {
 }
 
 impl TriggerDeclaration {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "trigger_declaration");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "trigger_declaration")?;
 
         let events = node
-            .cs_by_k("trigger_event")
+            .cs_by_k("trigger_event")?
             .into_iter()
-            .map(|n| TriggerEvent::new(n))
-            .collect();
+            .map(TriggerEvent::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
-            name: ValueNode::new(node.c_by_n("name")),
-            object: ValueNode::new(node.c_by_n("object")),
+        Ok(Self {
+            name: ValueNode::new(node.c_by_n("name")?)?,
+            object: ValueNode::new(node.c_by_n("object")?)?,
             events,
-            body: TriggerBody::new(node.c_by_n("body")),
+            body: TriggerBody::new(node.c_by_n("body")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3759,13 +3888,13 @@ pub struct TriggerEvent {
 }
 
 impl TriggerEvent {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "trigger_event");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "trigger_event")?;
 
-        Self {
-            event: TriggerEventVariant::new(node.first_c()),
+        Ok(Self {
+            event: TriggerEventVariant::new(node.first_c()?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3784,13 +3913,13 @@ pub struct TriggerBody {
 }
 
 impl TriggerBody {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "trigger_body");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "trigger_body")?;
 
-        Self {
-            block: Block::new(node.c_by_k("block")),
+        Ok(Self {
+            block: Block::new(node.c_by_k("block")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3810,20 +3939,22 @@ pub struct QueryExpression {
 }
 
 impl QueryExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "query_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "query_expression")?;
 
         let query_body = if let Some(soql_node) = node.try_c_by_k("soql_query_body") {
-            QueryBody::Soql(SoqlQueryBody::new(soql_node))
+            QueryBody::Soql(Box::new(SoqlQueryBody::new(soql_node)?))
         } else {
-            QueryBody::Sosl(SoslQueryBody::new(node.c_by_k("sosl_query_body")))
+            QueryBody::Sosl(Box::new(SoslQueryBody::new(
+                node.c_by_k("sosl_query_body")?,
+            )?))
         };
 
-        Self {
+        Ok(Self {
             query_body,
             context: build_chaining_context(&node),
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3858,8 +3989,8 @@ impl<'a> DocBuild<'a> for QueryExpression {
 
 #[derive(Debug)]
 pub enum QueryBody {
-    Soql(SoqlQueryBody),
-    Sosl(SoslQueryBody),
+    Soql(Box<SoqlQueryBody>),
+    Sosl(Box<SoslQueryBody>),
 }
 
 impl<'a> DocBuild<'a> for QueryBody {
@@ -3889,28 +4020,37 @@ pub struct SoslQueryBody {
 }
 
 impl SoslQueryBody {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "sosl_query_body");
-
-        let find_clause = FindClause::new(node.c_by_k("find_clause"));
-        let in_clause = node.try_c_by_k("in_clause").map(|n| InClause::new(n));
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "sosl_query_body")?;
+
+        let find_clause = FindClause::new(node.c_by_k("find_clause")?)?;
+        let in_clause = node
+            .try_c_by_k("in_clause")
+            .map(InClause::new)
+            .transpose()?;
         let returning_clause = node
             .try_c_by_k("returning_clause")
-            .map(|n| ReturningClause::new(n));
+            .map(ReturningClause::new)
+            .transpose()?;
         let with_clauses = node
             .try_cs_by_k("with_clause")
             .into_iter()
-            .map(|n| SoslWithClause::new(n))
-            .collect();
+            .map(SoslWithClause::new)
+            .collect::<Result<_, _>>()?;
         let using_clause = node
             .try_c_by_k("sosl_using_clause")
-            .map(|n| SoslUsingClause::new(n));
-        let limit_clause = node.try_c_by_k("limit_clause").map(|n| LimitClause::new(n));
+            .map(SoslUsingClause::new)
+            .transpose()?;
+        let limit_clause = node
+            .try_c_by_k("limit_clause")
+            .map(LimitClause::new)
+            .transpose()?;
         let update_clause = node
             .try_c_by_k("update_clause")
-            .map(|n| UpdateClause::new(n));
+            .map(UpdateClause::new)
+            .transpose()?;
 
-        Self {
+        Ok(Self {
             find_clause,
             in_clause,
             returning_clause,
@@ -3919,7 +4059,7 @@ impl SoslQueryBody {
             limit_clause,
             update_clause,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3965,14 +4105,16 @@ pub enum FindClause {
 }
 
 impl FindClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "find_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "find_clause")?;
 
-        if let Some(bound_node) = node.try_c_by_k("bound_apex_expression") {
-            Self::Bound(BoundApexExpression::new(bound_node))
-        } else {
-            Self::Term(node.cvalue_by_k("term"))
-        }
+        Ok(
+            if let Some(bound_node) = node.try_c_by_k("bound_apex_expression") {
+                Self::Bound(BoundApexExpression::new(bound_node)?)
+            } else {
+                Self::Term(node.cvalue_by_k("term")?)
+            },
+        )
     }
 }
 
@@ -3997,13 +4139,13 @@ pub struct InClause {
 }
 
 impl InClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "in_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "in_clause")?;
 
-        Self {
-            in_type: ValueNode::new(node.c_by_k("in_type")),
+        Ok(Self {
+            in_type: ValueNode::new(node.c_by_k("in_type")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4025,17 +4167,17 @@ pub struct ReturningClause {
 }
 
 impl ReturningClause {
-    pub fn new(node: Node) -> Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
         let sobject_returns = node
-            .cs_by_k("sobject_return")
+            .cs_by_k("sobject_return")?
             .into_iter()
-            .map(|n| SObjectReturn::new(n))
-            .collect();
+            .map(SObjectReturn::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             sobject_returns,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4062,34 +4204,48 @@ pub struct SObjectReturn {
 }
 
 impl SObjectReturn {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "sobject_return");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "sobject_return")?;
 
         let sobject_return_query = node
             .try_c_by_k("selected_fields")
-            .map(|n| SObjectReturnQuery {
-                selected_fields: n
-                    .children_vec()
-                    .into_iter()
-                    .map(|selectable_node| SelectableExpression::new(selectable_node))
-                    .collect(),
-                using_clause: node.try_c_by_k("using_clause").map(|n| UsingClause::new(n)),
-                where_clause: node.try_c_by_k("where_clause").map(|n| WhereClause::new(n)),
-                order_by_clause: node
-                    .try_c_by_k("order_by_clause")
-                    .map(|n| OrderByClause::new(n)),
-                limit_clause: node.try_c_by_k("limit_clause").map(|n| LimitClause::new(n)),
-                offset_clause: node
-                    .try_c_by_k("offset_clause")
-                    .map(|n| OffsetClause::new(n)),
-                node_info: NodeInfo::with_punctuation(&n),
-            });
-
-        Self {
-            identifier: ValueNode::new(node.c_by_k("identifier")),
+            .map(|n| -> Result<_, FormatError> {
+                Ok(SObjectReturnQuery {
+                    selected_fields: n
+                        .children_vec()
+                        .into_iter()
+                        .map(SelectableExpression::new)
+                        .collect::<Result<_, _>>()?,
+                    using_clause: node
+                        .try_c_by_k("using_clause")
+                        .map(UsingClause::new)
+                        .transpose()?,
+                    where_clause: node
+                        .try_c_by_k("where_clause")
+                        .map(WhereClause::new)
+                        .transpose()?,
+                    order_by_clause: node
+                        .try_c_by_k("order_by_clause")
+                        .map(OrderByClause::new)
+                        .transpose()?,
+                    limit_clause: node
+                        .try_c_by_k("limit_clause")
+                        .map(LimitClause::new)
+                        .transpose()?,
+                    offset_clause: node
+                        .try_c_by_k("offset_clause")
+                        .map(OffsetClause::new)
+                        .transpose()?,
+                    node_info: NodeInfo::with_punctuation(&n),
+                })
+            })
+            .transpose()?;
+
+        Ok(Self {
+            identifier: ValueNode::new(node.c_by_k("identifier")?)?,
             sobject_return_query,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4169,35 +4325,46 @@ pub struct SoqlQueryBody {
 }
 
 impl SoqlQueryBody {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "soql_query_body");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "soql_query_body")?;
 
-        let where_clause = node.try_c_by_n("where_clause").map(|n| WhereClause::new(n));
+        let where_clause = node
+            .try_c_by_n("where_clause")
+            .map(WhereClause::new)
+            .transpose()?;
         let with_clause = node
             .try_c_by_n("with_clause")
-            .map(|n| SoqlWithClause::new(n));
+            .map(SoqlWithClause::new)
+            .transpose()?;
         let group_by_clause = node
             .try_c_by_n("group_by_clause")
-            .map(|n| GroupByClause::new(n));
+            .map(GroupByClause::new)
+            .transpose()?;
         let order_by_clause = node
             .try_c_by_n("order_by_clause")
-            .map(|n| OrderByClause::new(n));
-        let limit_clause = node.try_c_by_n("limit_clause").map(|n| LimitClause::new(n));
+            .map(OrderByClause::new)
+            .transpose()?;
+        let limit_clause = node
+            .try_c_by_n("limit_clause")
+            .map(LimitClause::new)
+            .transpose()?;
         let offset_clause = node
             .try_c_by_n("offset_clause")
-            .map(|n| OffsetClause::new(n));
+            .map(OffsetClause::new)
+            .transpose()?;
         let all_rows_clause = node
             .try_c_by_n("all_rows_clause")
-            .map(|n| AllRowsClause::new(n));
+            .map(AllRowsClause::new)
+            .transpose()?;
         let for_clause = node
             .try_cs_by_k("for_clause")
             .into_iter()
-            .map(|n| ForClause::new(n))
-            .collect();
+            .map(ForClause::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
-            select_clause: SelectClause::new(node.c_by_n("select_clause")),
-            from_clause: FromClause::new(node.c_by_n("from_clause")),
+        Ok(Self {
+            select_clause: SelectClause::new(node.c_by_n("select_clause")?)?,
+            from_clause: FromClause::new(node.c_by_n("from_clause")?)?,
             where_clause,
             with_clause,
             group_by_clause,
@@ -4207,7 +4374,7 @@ impl SoqlQueryBody {
             for_clause,
             all_rows_clause,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4263,13 +4430,13 @@ pub struct FromClause {
 }
 
 impl FromClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "from_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "from_clause")?;
 
-        Self {
-            content: StorageVariant::new(node.first_c()),
+        Ok(Self {
+            content: StorageVariant::new(node.first_c()?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4290,14 +4457,14 @@ pub struct StorageAlias {
 }
 
 impl StorageAlias {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "storage_alias");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "storage_alias")?;
 
-        Self {
-            storage_identifier: StorageIdentifier::new(node.c_by_k("storage_identifier")),
-            identifier: ValueNode::new(node.c_by_k("identifier")),
+        Ok(Self {
+            storage_identifier: StorageIdentifier::new(node.c_by_k("storage_identifier")?)?,
+            identifier: ValueNode::new(node.c_by_k("identifier")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4318,13 +4485,13 @@ pub struct LimitClause {
 }
 
 impl LimitClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "limit_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "limit_clause")?;
 
-        Self {
-            limit_value: LimitValue::new(node.first_c()),
+        Ok(Self {
+            limit_value: LimitValue::new(node.first_c()?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4344,17 +4511,17 @@ pub struct UpdateClause {
 }
 
 impl UpdateClause {
-    pub fn new(node: Node) -> Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
         let update_types = node
-            .cs_by_k("update_type")
+            .cs_by_k("update_type")?
             .into_iter()
-            .map(|n| ValueNode::new(n))
-            .collect();
+            .map(ValueNode::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             update_types,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4378,13 +4545,13 @@ pub struct BoundApexExpression {
 }
 
 impl BoundApexExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "bound_apex_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "bound_apex_expression")?;
 
-        Self {
-            exp: Box::new(Expression::new(node.first_c())),
+        Ok(Self {
+            exp: Box::new(Expression::new(node.first_c()?)?),
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4404,13 +4571,13 @@ pub struct SoslUsingClause {
 }
 
 impl SoslUsingClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "sosl_using_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "sosl_using_clause")?;
 
-        Self {
-            search: UsingSearch::new(node.first_c()),
+        Ok(Self {
+            search: UsingSearch::new(node.first_c()?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4430,12 +4597,12 @@ pub enum UsingSearch {
 }
 
 impl UsingSearch {
-    pub fn new(node: Node) -> Self {
-        match node.kind() {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        Ok(match node.kind() {
             "using_phrase_search" => Self::Phrase,
             "using_advanced_search" => Self::Advanced,
-            _ => panic_unknown_node(node, "UsingSearch"),
-        }
+            _ => return Err(unknown_node(node, "UsingSearch")),
+        })
     }
 }
 
@@ -4459,13 +4626,13 @@ pub struct UsingClause {
 }
 
 impl UsingClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "using_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "using_clause")?;
 
-        Self {
-            option: UsingClauseOption::new(node.first_c()),
+        Ok(Self {
+            option: UsingClauseOption::new(node.first_c()?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4486,13 +4653,13 @@ pub enum UsingClauseOption {
 }
 
 impl UsingClauseOption {
-    pub fn new(node: Node) -> Self {
-        match node.kind() {
-            "using_scope_clause" => Self::Scope(UsingScopeClause::new(node)),
-            "using_lookup_clause" => Self::Lookup(UsingLookupClause::new(node)),
-            "using_listview_clause" => Self::Listview(UsingListviewClause::new(node)),
-            _ => panic_unknown_node(node, "UsingClauseOption"),
-        }
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        Ok(match node.kind() {
+            "using_scope_clause" => Self::Scope(UsingScopeClause::new(node)?),
+            "using_lookup_clause" => Self::Lookup(UsingLookupClause::new(node)?),
+            "using_listview_clause" => Self::Listview(UsingListviewClause::new(node)?),
+            _ => return Err(unknown_node(node, "UsingClauseOption")),
+        })
     }
 }
 
@@ -4519,13 +4686,13 @@ pub struct UsingScopeClause {
 }
 
 impl UsingScopeClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "using_scope_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "using_scope_clause")?;
 
-        Self {
-            type_: ValueNode::new(node.c_by_k("using_scope_type")),
+        Ok(Self {
+            type_: ValueNode::new(node.c_by_k("using_scope_type")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4546,19 +4713,20 @@ pub struct UsingLookupClause {
 }
 
 impl UsingLookupClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "using_lookup_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "using_lookup_clause")?;
 
-        let lookup_field = DottedIdentifier::new(node.c_by_n("using_lookup_clause"));
+        let lookup_field = DottedIdentifier::new(node.c_by_n("using_lookup_clause")?)?;
         let bind_clause = node
             .try_c_by_k("using_lookup_bind_clause")
-            .map(|n| UsingLookupBindClause::new(n));
+            .map(UsingLookupBindClause::new)
+            .transpose()?;
 
-        Self {
+        Ok(Self {
             lookup_field,
             bind_clause,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4582,13 +4750,13 @@ pub struct UsingListviewClause {
 }
 
 impl UsingListviewClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "using_listview_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "using_listview_clause")?;
 
-        Self {
-            identifier: ValueNode::new(node.c_by_k("identifier")),
+        Ok(Self {
+            identifier: ValueNode::new(node.c_by_k("identifier")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4608,20 +4776,20 @@ pub struct UsingLookupBindClause {
 }
 
 impl UsingLookupBindClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "using_lookup_bind_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "using_lookup_bind_clause")?;
 
         let bind_exps = node
             .try_cs_by_k("using_lookup_bind_expression")
             .into_iter()
-            .map(|n| UsingLookupBindExpression::new(n))
-            .collect();
+            .map(UsingLookupBindExpression::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             bind_exps,
 
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4646,14 +4814,14 @@ pub struct UsingLookupBindExpression {
 }
 
 impl UsingLookupBindExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "using_lookup_bind_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "using_lookup_bind_expression")?;
 
-        Self {
-            field: ValueNode::new(node.c_by_k("field")),
-            bound_value: SoqlLiteral::new(node.c_by_n("bound_value")),
+        Ok(Self {
+            field: ValueNode::new(node.c_by_k("field")?)?,
+            bound_value: SoqlLiteral::new(node.c_by_n("bound_value")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4674,13 +4842,13 @@ pub struct WhereClause {
 }
 
 impl WhereClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "where_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "where_clause")?;
 
-        Self {
-            boolean_exp: BooleanExpression::new(node.first_c()),
+        Ok(Self {
+            boolean_exp: BooleanExpression::new(node.first_c()?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4705,14 +4873,14 @@ pub struct ComparisonExpression {
 }
 
 impl ComparisonExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "comparison_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "comparison_expression")?;
 
-        Self {
-            value: Box::new(ValueExpression::new(node.first_c())),
-            comparison: get_comparsion(&node),
+        Ok(Self {
+            value: Box::new(ValueExpression::new(node.first_c()?)?),
+            comparison: get_comparsion(&node)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4758,17 +4926,17 @@ pub struct ComparableList {
 }
 
 impl ComparableList {
-    pub fn new(node: Node) -> Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
         let values = node
             .children_vec()
             .into_iter()
-            .map(|n| ComparableListValue::new(n))
-            .collect();
+            .map(ComparableListValue::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             values,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4793,19 +4961,19 @@ pub struct OrderByClause {
 }
 
 impl OrderByClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "order_by_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "order_by_clause")?;
 
         let exps = node
-            .cs_by_k("order_expression")
+            .cs_by_k("order_expression")?
             .into_iter()
-            .map(|n| OrderExpression::new(n))
-            .collect();
+            .map(OrderExpression::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             exps,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4831,22 +4999,24 @@ pub struct OrderExpression {
 }
 
 impl OrderExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "order_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "order_expression")?;
 
         let direction = node
             .try_c_by_k("order_direction")
-            .map(|n| ValueNode::new(n));
+            .map(ValueNode::new)
+            .transpose()?;
         let null_direction = node
             .try_c_by_k("order_null_direction")
-            .map(|n| ValueNode::new(n));
+            .map(ValueNode::new)
+            .transpose()?;
 
-        Self {
-            value_expression: ValueExpression::new(node.first_c()),
+        Ok(Self {
+            value_expression: ValueExpression::new(node.first_c()?)?,
             direction,
             null_direction,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4874,12 +5044,12 @@ pub struct SubQuery {
 }
 
 impl SubQuery {
-    pub fn new(node: Node) -> Self {
-        let soql_query_body = Box::new(SoqlQueryBody::new(node.c_by_k("soql_query_body")));
-        Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        let soql_query_body = Box::new(SoqlQueryBody::new(node.c_by_k("soql_query_body")?)?);
+        Ok(Self {
             soql_query_body,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4904,14 +5074,14 @@ pub struct MapCreationExpression {
 }
 
 impl MapCreationExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "map_creation_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "map_creation_expression")?;
 
-        Self {
-            type_: SimpleType::new(node.c_by_n("type")),
-            value: MapInitializer::new(node.c_by_n("value")),
+        Ok(Self {
+            type_: SimpleType::new(node.c_by_n("type")?)?,
+            value: MapInitializer::new(node.c_by_n("value")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4932,19 +5102,19 @@ pub struct MapInitializer {
 }
 
 impl MapInitializer {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "map_initializer");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "map_initializer")?;
 
         let initializers: Vec<_> = node
             .children_vec()
             .into_iter()
-            .map(|n| MapKeyInitializer::new(n))
-            .collect();
+            .map(MapKeyInitializer::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             initializers,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4970,19 +5140,22 @@ pub struct MapKeyInitializer {
 }
 
 impl MapKeyInitializer {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "map_key_initializer");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "map_key_initializer")?;
 
         let children = node.children_vec();
         if children.len() != 2 {
-            panic!("### must be exactly 2 child nodes in MapKeyInitializer");
+            return Err(unsupported_node(
+                &node,
+                "must be exactly 2 child nodes in MapKeyInitializer".to_string(),
+            ));
         }
 
-        Self {
-            exp1: Box::new(Expression::new(children[0])),
-            exp2: Box::new(Expression::new(children[1])),
+        Ok(Self {
+            exp1: Box::new(Expression::new(children[0])?),
+            exp2: Box::new(Expression::new(children[1])?),
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5004,8 +5177,8 @@ pub struct GroupByClause {
 }
 
 impl GroupByClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "group_by_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "group_by_clause")?;
 
         let mut exps = Vec::new();
         let mut have_clause = None;
@@ -5013,24 +5186,24 @@ impl GroupByClause {
         for child in node.children_vec() {
             match child.kind() {
                 "field_identifier" => {
-                    exps.push(GroupByExpression::Field(FieldIdentifier::new(child)));
+                    exps.push(GroupByExpression::Field(FieldIdentifier::new(child)?));
                 }
                 "function_expression" => {
-                    exps.push(GroupByExpression::Func(FunctionExpression::new(child)));
+                    exps.push(GroupByExpression::Func(Box::new(FunctionExpression::new(
+                        child,
+                    )?)));
                 }
                 "having_clause" => {
-                    have_clause = Some(HavingClause::new(child));
-                }
-                other => {
-                    panic!("## unknown node: {} in GroupByClause", red(other));
+                    have_clause = Some(HavingClause::new(child)?);
                 }
+                _ => return Err(unknown_node(child, "GroupByClause")),
             }
         }
-        Self {
+        Ok(Self {
             exps,
             have_clause,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5055,7 +5228,7 @@ impl<'a> DocBuild<'a> for GroupByClause {
 #[derive(Debug)]
 pub enum GroupByExpression {
     Field(FieldIdentifier),
-    Func(FunctionExpression),
+    Func(Box<FunctionExpression>),
 }
 
 impl<'a> DocBuild<'a> for GroupByExpression {
@@ -5078,13 +5251,13 @@ pub struct HavingClause {
 }
 
 impl HavingClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "having_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "having_clause")?;
 
-        Self {
-            boolean_exp: BooleanExpression::new(node.first_c()),
+        Ok(Self {
+            boolean_exp: BooleanExpression::new(node.first_c()?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5108,13 +5281,13 @@ pub struct SoslWithClause {
 }
 
 impl SoslWithClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "with_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "with_clause")?;
 
-        Self {
-            with_type: SoslWithType::new(node.c_by_k("with_type")),
+        Ok(Self {
+            with_type: SoslWithType::new(node.c_by_k("with_type")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5134,13 +5307,13 @@ pub struct SoqlWithClause {
 }
 
 impl SoqlWithClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "with_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "with_clause")?;
 
-        Self {
-            with_type: SoqlWithType::new(node.c_by_k("with_type")),
+        Ok(Self {
+            with_type: SoqlWithType::new(node.c_by_k("with_type")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5162,17 +5335,19 @@ pub enum SoqlWithTypeVariant {
 }
 
 impl SoqlWithTypeVariant {
-    pub fn new(node: Node) -> Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
         let with_type = if node.named_child_count() == 0 {
-            return Self::SimpleType(ValueNode::new(node));
+            return Ok(Self::SimpleType(ValueNode::new(node)?));
         } else {
-            let child = node.first_c();
+            let child = node.first_c()?;
             match child.kind() {
-                "with_user_id_type" => Self::UserId(ValueNode::new(child.c_by_k("string_literal"))),
-                _ => panic_unknown_node(node, "WithType"),
+                "with_user_id_type" => {
+                    Self::UserId(ValueNode::new(child.c_by_k("string_literal")?)?)
+                }
+                _ => return Err(unknown_node(node, "WithType")),
             }
         };
-        with_type
+        Ok(with_type)
     }
 }
 
@@ -5203,23 +5378,23 @@ pub enum SoslWithType {
 }
 
 impl SoslWithType {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "with_type");
-
-        let child = node.first_c();
-        match child.kind() {
-            "with_data_cat_expression" => Self::DataCat(WithDataCatExpression::new(child)),
-            "with_division_expression" => Self::Division(WithDivisionExpression::new(child)),
-            "with_snippet_expression" => Self::Snippet(WithSnippetExpression::new(child)),
-            "with_network_expression" => Self::Network(WithNetworkExpression::new(child)),
-            "with_metadata_expression" => Self::Metadata(WithMetadataExpression::new(child)),
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "with_type")?;
+
+        let child = node.first_c()?;
+        Ok(match child.kind() {
+            "with_data_cat_expression" => Self::DataCat(WithDataCatExpression::new(child)?),
+            "with_division_expression" => Self::Division(WithDivisionExpression::new(child)?),
+            "with_snippet_expression" => Self::Snippet(WithSnippetExpression::new(child)?),
+            "with_network_expression" => Self::Network(WithNetworkExpression::new(child)?),
+            "with_metadata_expression" => Self::Metadata(WithMetadataExpression::new(child)?),
             "with_spell_correction_expression" => {
-                Self::Spell(WithSpellCorrectionExpression::new(child))
+                Self::Spell(WithSpellCorrectionExpression::new(child)?)
             }
             "with_highlight" => Self::Highlight,
-            "with_pricebook_expression" => Self::PriceBook(WithPriceBookExpression::new(child)),
-            _ => panic_unknown_node(child, "SoslWithType"),
-        }
+            "with_pricebook_expression" => Self::PriceBook(WithPriceBookExpression::new(child)?),
+            _ => return Err(unknown_node(child, "SoslWithType")),
+        })
     }
 }
 
@@ -5261,19 +5436,19 @@ pub struct WithDataCatExpression {
 }
 
 impl WithDataCatExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "with_data_cat_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "with_data_cat_expression")?;
 
         let filters = node
-            .cs_by_k("with_data_cat_filter")
+            .cs_by_k("with_data_cat_filter")?
             .into_iter()
-            .map(|n| WithDataCatFilter::new(n))
-            .collect();
+            .map(WithDataCatFilter::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             filters,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5300,27 +5475,30 @@ pub struct WithDataCatFilter {
 }
 
 impl WithDataCatFilter {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "with_data_cat_filter");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "with_data_cat_filter")?;
 
-        let all_identififers = node.cs_by_k("identifier");
+        let all_identififers = node.cs_by_k("identifier")?;
         if all_identififers.len() < 2 {
-            panic!("At least 2 identifier nodes should exist in WithDataCatFilter");
+            return Err(unsupported_node(
+                &node,
+                "at least 2 identifier nodes should exist in WithDataCatFilter".to_string(),
+            ));
         }
 
-        let identifier = ValueNode::new(all_identififers[0]);
+        let identifier = ValueNode::new(all_identififers[0])?;
         let identifiers: Vec<_> = all_identififers
             .into_iter()
             .skip(1)
-            .map(|n| ValueNode::new(n))
-            .collect();
+            .map(ValueNode::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             identifier,
-            filter_type: ValueNodeUpperCase::new(node.c_by_k("with_data_cat_filter_type")),
+            filter_type: ValueNodeUpperCase::new(node.c_by_k("with_data_cat_filter_type")?)?,
             identifiers,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5354,15 +5532,15 @@ pub enum WithDivisionExpression {
 }
 
 impl WithDivisionExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "with_division_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "with_division_expression")?;
 
-        let child = node.first_c();
-        match child.kind() {
-            "bound_apex_expression" => Self::Bound(BoundApexExpression::new(child)),
+        let child = node.first_c()?;
+        Ok(match child.kind() {
+            "bound_apex_expression" => Self::Bound(BoundApexExpression::new(child)?),
             "string_literal" => Self::StringLiteral(child.value()),
-            _ => panic_unknown_node(node, "WithDivisionExpression"),
-        }
+            _ => return Err(unknown_node(node, "WithDivisionExpression")),
+        })
     }
 }
 
@@ -5387,15 +5565,15 @@ pub struct WithSnippetExpression {
 }
 
 impl WithSnippetExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "with_snippet_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "with_snippet_expression")?;
 
-        let int = node.try_c_by_k("int").map(|n| ValueNode::new(n));
+        let int = node.try_c_by_k("int").map(ValueNode::new).transpose()?;
 
-        Self {
+        Ok(Self {
             int,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5420,13 +5598,13 @@ pub struct WithNetworkExpression {
 }
 
 impl WithNetworkExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "with_network_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "with_network_expression")?;
 
-        Self {
-            comparison: get_comparsion(&node),
+        Ok(Self {
+            comparison: get_comparsion(&node)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5446,13 +5624,13 @@ pub struct WithMetadataExpression {
 }
 
 impl WithMetadataExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "with_metadata_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "with_metadata_expression")?;
 
-        Self {
-            string_literal: ValueNode::new(node.c_by_k("string_literal")),
+        Ok(Self {
+            string_literal: ValueNode::new(node.c_by_k("string_literal")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5472,13 +5650,13 @@ pub struct WithSpellCorrectionExpression {
 }
 
 impl WithSpellCorrectionExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "with_spell_correction_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "with_spell_correction_expression")?;
 
-        Self {
-            boolean: ValueNode::new(node.c_by_k("boolean")),
+        Ok(Self {
+            boolean: ValueNode::new(node.c_by_k("boolean")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5498,13 +5676,13 @@ pub struct WithPriceBookExpression {
 }
 
 impl WithPriceBookExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "with_pricebook_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "with_pricebook_expression")?;
 
-        Self {
-            string_literal: ValueNode::new(node.c_by_k("string_literal")),
+        Ok(Self {
+            string_literal: ValueNode::new(node.c_by_k("string_literal")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5524,19 +5702,19 @@ pub struct DottedIdentifier {
 }
 
 impl DottedIdentifier {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "dotted_identifier");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "dotted_identifier")?;
 
         let identifiers = node
-            .cs_by_k("identifier")
+            .cs_by_k("identifier")?
             .into_iter()
-            .map(|n| ValueNode::new(n))
-            .collect();
+            .map(ValueNode::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             identifiers,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5560,11 +5738,11 @@ pub struct ValueNode {
 }
 
 impl ValueNode {
-    pub fn new(node: Node) -> Self {
-        Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        Ok(Self {
             value: node.value(),
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5583,11 +5761,11 @@ pub struct ValueNodeLowerCase {
 }
 
 impl ValueNodeLowerCase {
-    pub fn new(node: Node) -> Self {
-        Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        Ok(Self {
             value: node.value(),
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5606,11 +5784,11 @@ pub struct ValueNodeUpperCase {
 }
 
 impl ValueNodeUpperCase {
-    pub fn new(node: Node) -> Self {
-        Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        Ok(Self {
             value: node.value(),
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5629,13 +5807,13 @@ pub struct ExpressionStatement {
 }
 
 impl ExpressionStatement {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "expression_statement");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "expression_statement")?;
 
-        Self {
-            exp: Expression::new(node.first_c()),
+        Ok(Self {
+            exp: Expression::new(node.first_c()?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5653,12 +5831,12 @@ pub struct SafeNavigationOperator {
 }
 
 impl SafeNavigationOperator {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "safe_navigation_operator");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "safe_navigation_operator")?;
 
-        Self {
+        Ok(Self {
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5677,13 +5855,13 @@ pub struct CountExpression {
 }
 
 impl CountExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "count_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "count_expression")?;
 
-        Self {
-            function_name: ValueNode::new(node.c_by_n("function_name")),
+        Ok(Self {
+            function_name: ValueNode::new(node.c_by_n("function_name")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5703,13 +5881,13 @@ pub struct FunctionExpression {
 }
 
 impl FunctionExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "function_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "function_expression")?;
 
-        Self {
-            variant: FunctionExpressionVariant::new(node),
+        Ok(Self {
+            variant: FunctionExpressionVariant::new(node)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5728,13 +5906,13 @@ pub struct FieldIdentifier {
 }
 
 impl FieldIdentifier {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "field_identifier");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "field_identifier")?;
 
-        Self {
-            variant: FieldIdentifierVariant::new(node),
+        Ok(Self {
+            variant: FieldIdentifierVariant::new(node)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5753,13 +5931,13 @@ pub struct GeoLocationType {
 }
 
 impl GeoLocationType {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "geo_location_type");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "geo_location_type")?;
 
-        Self {
-            variant: GeoLocationTypeVariant::new(node),
+        Ok(Self {
+            variant: GeoLocationTypeVariant::new(node)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5778,13 +5956,13 @@ pub struct SelectClause {
 }
 
 impl SelectClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "select_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "select_clause")?;
 
-        Self {
-            variant: SelectClauseVariant::new(node),
+        Ok(Self {
+            variant: SelectClauseVariant::new(node)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5803,13 +5981,13 @@ pub struct StorageIdentifier {
 }
 
 impl StorageIdentifier {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "storage_identifier");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "storage_identifier")?;
 
-        Self {
-            variant: StorageIdentifierVariant::new(node),
+        Ok(Self {
+            variant: StorageIdentifierVariant::new(node)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5828,19 +6006,19 @@ pub struct AndExpression {
 }
 
 impl AndExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "and_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "and_expression")?;
 
         let condition_exps = node
             .children_vec()
             .into_iter()
-            .map(|n| ConditionExpression::new(n))
-            .collect();
+            .map(ConditionExpression::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             condition_exps,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5865,19 +6043,19 @@ pub struct OrExpression {
 }
 
 impl OrExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "or_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "or_expression")?;
 
         let condition_exps = node
             .children_vec()
             .into_iter()
-            .map(|n| ConditionExpression::new(n))
-            .collect();
+            .map(ConditionExpression::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             condition_exps,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5902,13 +6080,13 @@ pub struct NotExpression {
 }
 
 impl NotExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "not_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "not_expression")?;
 
-        Self {
-            condition_exp: ConditionExpression::new(node.first_c()),
+        Ok(Self {
+            condition_exp: ConditionExpression::new(node.first_c()?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5929,13 +6107,13 @@ pub struct SoqlWithType {
 }
 
 impl SoqlWithType {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "with_type");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "with_type")?;
 
-        Self {
-            variant: SoqlWithTypeVariant::new(node),
+        Ok(Self {
+            variant: SoqlWithTypeVariant::new(node)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5954,13 +6132,13 @@ pub struct ForClause {
 }
 
 impl ForClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "for_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "for_clause")?;
 
-        Self {
-            for_type: ValueNode::new(node.c_by_k("for_type")),
+        Ok(Self {
+            for_type: ValueNode::new(node.c_by_k("for_type")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5978,12 +6156,12 @@ pub struct AllRowsClause {
 }
 
 impl AllRowsClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "all_rows_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "all_rows_clause")?;
 
-        Self {
+        Ok(Self {
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -6002,13 +6180,13 @@ pub struct UpdateExpression {
 }
 
 impl UpdateExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "update_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "update_expression")?;
 
-        Self {
-            variant: UpdateExpressionVariant::new(node),
+        Ok(Self {
+            variant: UpdateExpressionVariant::new(node)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -6027,13 +6205,13 @@ pub struct DmlExpression {
 }
 
 impl DmlExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "dml_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "dml_expression")?;
 
-        Self {
-            variant: DmlExpressionVariant::new(node),
+        Ok(Self {
+            variant: DmlExpressionVariant::new(node)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -6052,13 +6230,13 @@ pub struct DmlType {
 }
 
 impl DmlType {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "dml_type");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "dml_type")?;
 
-        Self {
-            variant: DmlTypeVariant::new(node.first_c()),
+        Ok(Self {
+            variant: DmlTypeVariant::new(node.first_c()?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -6069,3 +6247,37 @@ impl<'a> DocBuild<'a> for DmlType {
         });
     }
 }
+
+// A statement or member kept as written in the source, see `is_format_ignored()`.
+#[derive(Debug)]
+pub struct Verbatim {
+    pub value: String,
+    pub byte_range: Range<usize>,
+    pub node_info: NodeInfo,
+}
+
+impl Verbatim {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        Ok(Self {
+            value: node.value(),
+            byte_range: node.byte_range(),
+            node_info: NodeInfo::with_punctuation(&node),
+        })
+    }
+}
+
+impl<'a> DocBuild<'a> for Verbatim {
+    fn build_inner(&self, b: &'a DocBuilder<'a>, result: &mut Vec<DocRef<'a>>) {
+        // not `build_with_comments()`: dangling comments are part of the source text here
+        let context = format_context();
+        let bucket = context.comment_bucket(&self.node_info.id);
+        handle_pre_comments(b, bucket, result);
+        result.push(b.verbatim(&self.value));
+        mark_comments_as_printed(&self.byte_range);
+        handle_post_comments(b, bucket, result);
+
+        if let Some(ref n) = self.node_info.punc {
+            result.push(n.build(b));
+        }
+    }
+}
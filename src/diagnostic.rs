@@ -0,0 +1,247 @@
+use crate::error::{ErrorLocation, SyntaxError};
+use crate::message_helper::{paint, Style};
+use tree_sitter::{Language, Node, Tree};
+
+const MAX_EXCERPT_LINES: usize = 3;
+const MAX_EXPECTED_TOKENS: usize = 12;
+const DELIMITERS: [&str; 7] = ["`;`", "`,`", "`)`", "`]`", "`}`", "`(`", "`{`"];
+
+// Collect every ERROR and MISSING node of the tree, in source order.
+// Nested ERROR nodes are reported once through their outermost ERROR node.
+pub fn collect_syntax_errors(tree: &Tree, source_code: &str) -> Vec<SyntaxError> {
+    let mut errors = Vec::new();
+    let mut cursor = tree.walk();
+
+    loop {
+        let node = cursor.node();
+        let is_error = node.is_error() || node.is_missing();
+        if is_error {
+            errors.push(build_syntax_error(&node, &tree.language(), source_code));
+        }
+
+        if !is_error && node.has_error() && cursor.goto_first_child() {
+            continue;
+        }
+
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                return errors;
+            }
+        }
+    }
+}
+
+fn build_syntax_error(node: &Node, language: &Language, source_code: &str) -> SyntaxError {
+    let message = if node.is_missing() {
+        format!("missing `{}`", node.kind())
+    } else {
+        let snippet = &source_code[node.byte_range()];
+        let first_line = snippet.lines().next().unwrap_or_default().trim();
+        if first_line.is_empty() {
+            "unexpected input".to_string()
+        } else {
+            format!("unexpected `{}`", first_line)
+        }
+    };
+
+    let start = node.start_position();
+    let end = node.end_position();
+    let excerpt = source_code
+        .lines()
+        .skip(start.row)
+        .take(end.row - start.row + 1)
+        .map(|l| l.trim_end_matches('\r').to_string())
+        .collect();
+
+    SyntaxError {
+        location: ErrorLocation::from_node(node),
+        message,
+        context: node.parent().map(|p| p.kind().to_string()),
+        expected: if node.is_missing() {
+            vec![]
+        } else {
+            expected_tokens(node, language)
+        },
+        end_line: end.row + 1,
+        end_column: end.column + 1,
+        excerpt,
+    }
+}
+
+// Tokens the parser would have accepted right after the last valid leaf
+// in front of the error node, without the token it found there.
+fn expected_tokens(node: &Node, language: &Language) -> Vec<String> {
+    let state = match previous_leaf(node) {
+        Some(leaf) => leaf.next_parse_state(),
+        None => return vec![],
+    };
+    // 0 is the error recovery state, u16::MAX means "no state"
+    if state == 0 || state == u16::MAX {
+        return vec![];
+    }
+    let Some(mut lookahead) = language.lookahead_iterator(state) else {
+        return vec![];
+    };
+
+    let found = first_leaf(node);
+    let mut tokens: Vec<String> = Vec::new();
+    for symbol in lookahead.by_ref() {
+        if symbol == found.kind_id() {
+            continue;
+        }
+        if !language.node_kind_is_visible(symbol) {
+            continue;
+        }
+        let Some(name) = language.node_kind_for_id(symbol) else {
+            continue;
+        };
+        if name == "line_comment" || name == "block_comment" || name == "end" {
+            continue;
+        }
+        let token = if language.node_kind_is_named(symbol) {
+            name.to_string()
+        } else {
+            format!("`{}`", name)
+        };
+        if !tokens.contains(&token) {
+            tokens.push(token);
+        }
+    }
+
+    // delimiters first, then the other punctuation; they are the most likely fixes
+    tokens.sort_by_key(|t| {
+        (
+            !DELIMITERS.contains(&t.as_str()),
+            t.chars().any(|c| c.is_alphanumeric()),
+        )
+    });
+    tokens
+}
+
+fn first_leaf<'t>(node: &Node<'t>) -> Node<'t> {
+    let mut leaf = *node;
+    while let Some(first) = leaf.child(0) {
+        leaf = first;
+    }
+    leaf
+}
+
+fn previous_leaf<'t>(node: &Node<'t>) -> Option<Node<'t>> {
+    let mut current = *node;
+    let mut prev = loop {
+        if let Some(p) = current.prev_sibling() {
+            break p;
+        }
+        current = current.parent()?;
+    };
+    while let Some(last) = prev.child(prev.child_count().saturating_sub(1)) {
+        prev = last;
+    }
+    Some(prev)
+}
+
+// Render a syntax error in a compiler style, the first line being
+// `file:line:column: error: message` which editors understand:
+//
+// src/A.cls:3:19: error: unexpected `+`
+//   |
+// 3 |     Integer x = 1 +;
+//   |                   ^
+//   = note: in `local_variable_declaration`, expected one of `(`, `;`, ...
+pub fn render(error: &SyntaxError, color: bool) -> String {
+    let loc = &error.location;
+    let last_shown_line = (loc.line + MAX_EXCERPT_LINES - 1).min(error.end_line);
+    let gutter = last_shown_line.to_string().len();
+    let bar = paint(&format!("{:>gutter$} |", ""), Style::Cyan, color);
+
+    let mut out = format!(
+        "{}: {}: {}\n",
+        loc,
+        paint("error", Style::Red, color),
+        error.message
+    );
+    out.push_str(&bar);
+    out.push('\n');
+
+    for (i, text) in error.excerpt.iter().take(MAX_EXCERPT_LINES).enumerate() {
+        let line_no = loc.line + i;
+        let number = paint(&format!("{:>gutter$} |", line_no), Style::Cyan, color);
+        out.push_str(&format!("{} {}\n", number, text));
+
+        let from = if i == 0 {
+            loc.column - 1
+        } else {
+            text.len() - text.trim_start().len()
+        };
+        let to = if line_no == error.end_line {
+            error.end_column - 1
+        } else {
+            text.len()
+        };
+        out.push_str(&format!(
+            "{} {}\n",
+            bar,
+            paint(&underline(text, from, to), Style::Red, color)
+        ));
+    }
+    if error.excerpt.len() > MAX_EXCERPT_LINES {
+        out.push_str(&format!("{:>gutter$} ...\n", ""));
+    }
+
+    let mut notes = Vec::new();
+    if let Some(ref context) = error.context {
+        notes.push(format!("in `{}`", context));
+    }
+    if let Some(expected) = expected_note(error) {
+        notes.push(expected);
+    }
+    if !notes.is_empty() {
+        out.push_str(&format!(
+            "{:>gutter$} {} {}\n",
+            "",
+            paint("=", Style::Cyan, color),
+            paint(&format!("note: {}", notes.join(", ")), Style::Yellow, color)
+        ));
+    }
+
+    out
+}
+
+// "expected one of ..." with the first tokens the parser would have accepted.
+pub fn expected_note(error: &SyntaxError) -> Option<String> {
+    if error.expected.is_empty() {
+        return None;
+    }
+
+    let mut expected = error
+        .expected
+        .iter()
+        .take(MAX_EXPECTED_TOKENS)
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(", ");
+    if error.expected.len() > MAX_EXPECTED_TOKENS {
+        expected.push_str(&format!(
+            " and {} more",
+            error.expected.len() - MAX_EXPECTED_TOKENS
+        ));
+    }
+    Some(format!("expected one of {}", expected))
+}
+
+// `from` and `to` are byte columns in `text`; tabs are kept so the carets
+// line up with the excerpt above.
+fn underline(text: &str, from: usize, to: usize) -> String {
+    let from = from.min(text.len());
+    let to = to.clamp(from, text.len());
+
+    let mut line: String = text
+        .get(..from)
+        .unwrap_or_default()
+        .chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let width = text.get(from..to).map_or(0, |s| s.chars().count());
+    line.push_str(&"^".repeat(width.max(1)));
+    line
+}
@@ -0,0 +1,68 @@
+use crate::message_helper::{paint, Style};
+use similar::{ChangeTag, TextDiff};
+use std::path::Path;
+
+const CONTEXT_LINES: usize = 3;
+
+// Render a unified diff between the original file content and the formatted
+// result. Returns an empty string when both are identical.
+pub fn unified_diff(path: &str, original: &str, formatted: &str, color: bool) -> String {
+    let diff = TextDiff::from_lines(original, formatted);
+
+    // git style `a/` and `b/` prefixes so the output can be applied with `git apply`
+    let (old_name, new_name) = if Path::new(path).is_absolute() {
+        (path.to_string(), path.to_string())
+    } else {
+        let path = path.trim_start_matches("./");
+        (format!("a/{}", path), format!("b/{}", path))
+    };
+    let mut out = String::new();
+
+    for (i, hunk) in diff
+        .unified_diff()
+        .context_radius(CONTEXT_LINES)
+        .iter_hunks()
+        .enumerate()
+    {
+        if i == 0 {
+            out.push_str(&paint(&format!("--- {}", old_name), Style::Red, color));
+            out.push('\n');
+            out.push_str(&paint(&format!("+++ {}", new_name), Style::Green, color));
+            out.push('\n');
+        }
+
+        out.push_str(&paint(&hunk.header().to_string(), Style::Cyan, color));
+        out.push('\n');
+
+        for change in hunk.iter_changes() {
+            let line = change.value().trim_end_matches(['\r', '\n']);
+            match change.tag() {
+                ChangeTag::Delete => out.push_str(&paint(&format!("-{}", line), Style::Red, color)),
+                ChangeTag::Insert => {
+                    out.push_str(&paint(&format!("+{}", line), Style::Green, color))
+                }
+                ChangeTag::Equal => out.push_str(&format!(" {}", line)),
+            }
+            out.push('\n');
+
+            if change.missing_newline() {
+                out.push_str("\\ No newline at end of file\n");
+            }
+        }
+    }
+
+    out
+}
+
+// The 1-based lines, end inclusive, of the first run of original lines which
+// formatting changes. Lines only inserted point at the line they go before.
+pub fn first_changed_lines(original: &str, formatted: &str) -> Option<(usize, usize)> {
+    let diff = TextDiff::from_lines(original, formatted);
+    let group = diff.grouped_ops(0).into_iter().next()?;
+    let start = group.first()?.old_range().start;
+    let end = group.last()?.old_range().end;
+
+    let line_count = original.lines().count().max(1);
+    let start_line = (start + 1).min(line_count);
+    Some((start_line, end.max(start_line)))
+}
@@ -1,7 +1,10 @@
+use crate::formatter::{Config, IndentStyle};
+use unicode_width::UnicodeWidthStr;
+
 pub type DocRef<'a> = &'a Doc<'a>;
 
-pub fn pretty_print(doc_ref: DocRef, max_width: u32) -> String {
-    let mut printer = PrettyPrinter::new(doc_ref, max_width);
+pub fn pretty_print(doc_ref: DocRef, config: PrettyConfig) -> String {
+    let mut printer = PrettyPrinter::new(doc_ref, config);
     printer.print()
 }
 
@@ -10,7 +13,7 @@ pub enum Doc<'a> {
     Newline,
     NewlineWithNoIndent,
     ForceBreak,        // immediately use multi-line mode in choice(x, y) or group()
-    Text(String, u32), // The given text should not contain line breaks
+    Text(String, u32), // The given text should not contain line breaks; the width is in columns
     Softline,          // a space or a newline
     Maybeline,         // empty or a newline
     Flat(DocRef<'a>),
@@ -18,25 +21,47 @@ pub enum Doc<'a> {
     Dedent(u32, DocRef<'a>),
     Concat(Vec<DocRef<'a>>),
     Choice(DocRef<'a>, DocRef<'a>),
-    //Align(u32, DocRef<'a>),
+    Align(u32, DocRef<'a>), // lines after the first start at the current column plus the offset
 }
 
 struct PrettyPrinter<'a> {
     max_width: u32,
+    use_tabs: bool,
+    tab_width: u32,
     col: u32,
     chunks: Vec<Chunk<'a>>,
 }
 
+#[derive(Clone, Copy)]
 pub struct PrettyConfig {
+    pub max_width: u32,
     pub indent_size: u32,
+    pub use_tabs: bool,
+    pub tab_width: u32,
+    pub indent_style: IndentStyle,
 }
 
 impl PrettyConfig {
-    pub fn new(indent_size: u32) -> Self {
-        if indent_size == 0 {
+    pub fn new(config: &Config) -> Self {
+        if config.indent_size == 0 {
             panic!("indent_size must be greater than 0")
         } else {
-            Self { indent_size }
+            Self {
+                max_width: config.max_width,
+                indent_size: config.indent_size,
+                use_tabs: config.use_tabs,
+                tab_width: config.tab_width,
+                indent_style: config.indent_style,
+            }
+        }
+    }
+
+    // The columns of one indent level, a tab counts as `tab_width` of them.
+    pub fn indent_width(&self) -> u32 {
+        if self.use_tabs {
+            self.tab_width
+        } else {
+            self.indent_size
         }
     }
 }
@@ -46,7 +71,8 @@ struct Chunk<'a> {
     doc_ref: DocRef<'a>,
     indent: u32,
     flat: bool,
-    //align: u32,
+    // columns past `indent` where new lines start, always printed as spaces
+    align: u32,
 }
 
 impl<'a> Chunk<'a> {
@@ -77,18 +103,29 @@ impl<'a> Chunk<'a> {
             ..self
         }
     }
+
+    fn aligned(self, col: u32, doc_ref: DocRef<'a>) -> Self {
+        Chunk {
+            doc_ref,
+            align: col.saturating_sub(self.indent),
+            ..self
+        }
+    }
 }
 
 impl<'a> PrettyPrinter<'a> {
-    fn new(doc_ref: DocRef<'a>, max_width: u32) -> Self {
+    fn new(doc_ref: DocRef<'a>, config: PrettyConfig) -> Self {
         let chunk = Chunk {
             doc_ref,
             indent: 0,
             flat: false,
+            align: 0,
         };
 
         Self {
-            max_width,
+            max_width: config.max_width,
+            use_tabs: config.use_tabs,
+            tab_width: config.tab_width,
             col: 0,
             chunks: vec![chunk],
         }
@@ -104,7 +141,7 @@ impl<'a> PrettyPrinter<'a> {
             match chunk.doc_ref {
                 Doc::Newline => {
                     // Set a pending newline with the current indent
-                    newline_buffer.set_pending(chunk.indent);
+                    newline_buffer.set_pending(&chunk);
                 }
                 Doc::NewlineWithNoIndent => {
                     // Clear any pending newline and insert a newline without indent
@@ -118,12 +155,12 @@ impl<'a> PrettyPrinter<'a> {
                         result.push(' ');
                         self.col += 1;
                     } else {
-                        newline_buffer.set_pending(chunk.indent);
+                        newline_buffer.set_pending(&chunk);
                     }
                 }
                 Doc::Maybeline => {
                     if !chunk.flat {
-                        newline_buffer.set_pending(chunk.indent);
+                        newline_buffer.set_pending(&chunk);
                     }
                 }
                 Doc::ForceBreak => {
@@ -133,24 +170,29 @@ impl<'a> PrettyPrinter<'a> {
                 Doc::Text(text, width) => {
                     // Before printing text, flush any pending newline
                     if newline_buffer.is_pending() {
-                        self.insert_newline_with_indent(
-                            &mut result,
-                            newline_buffer.get_indent(),
-                        );
+                        self.insert_newline_with_indent(&mut result, &newline_buffer);
                         newline_buffer.clear();
                     }
 
-                    if text == " " && result.ends_with(' ') {
+                    if text == " " && result.ends_with([' ', '\t']) {
                         // TODO: better way to handle this challenge?
                         // do nothing to avoid "double spacing" in comment node handling
                     } else {
                         result.push_str(text);
-                        self.col += width;
+                        self.col += self.text_width(text, *width, self.col);
                     }
                 }
                 Doc::Flat(x) => self.chunks.push(chunk.flat(x)),
                 Doc::Indent(i, x) => self.chunks.push(chunk.indented(*i, x)),
                 Doc::Dedent(i, x) => self.chunks.push(chunk.dedented(*i, x)),
+                Doc::Align(offset, x) => {
+                    // the column is only known once a pending newline is printed
+                    if newline_buffer.is_pending() {
+                        self.insert_newline_with_indent(&mut result, &newline_buffer);
+                        newline_buffer.clear();
+                    }
+                    self.chunks.push(chunk.aligned(self.col + offset, x));
+                }
                 Doc::Concat(seq) => {
                     for n in seq.iter().rev() {
                         self.chunks.push(chunk.with_doc(n));
@@ -158,10 +200,7 @@ impl<'a> PrettyPrinter<'a> {
                 }
                 Doc::Choice(x, y) => {
                     if newline_buffer.is_pending() {
-                        self.insert_newline_with_indent(
-                            &mut result,
-                            newline_buffer.get_indent(),
-                        );
+                        self.insert_newline_with_indent(&mut result, &newline_buffer);
                         newline_buffer.clear();
                     }
 
@@ -183,29 +222,47 @@ impl<'a> PrettyPrinter<'a> {
 
         // Flush the last element in case buffer has a newline
         if newline_buffer.is_pending() {
-            self.insert_newline_with_indent(&mut result, newline_buffer.get_indent());
+            self.insert_newline_with_indent(&mut result, &newline_buffer);
             newline_buffer.clear();
         }
 
         result
     }
 
-    fn insert_newline_with_indent(&mut self, result: &mut String, indent: u32) {
+    fn insert_newline_with_indent(&mut self, result: &mut String, newline: &NewlineBuffer) {
         result.push('\n');
-        for _ in 0..indent {
-            result.push(' ');
+        let (indent, align) = (newline.get_indent(), newline.get_align());
+        if self.use_tabs {
+            // whole indent levels as tabs, what's left and the alignment as spaces
+            for _ in 0..indent / self.tab_width {
+                result.push('\t');
+            }
+            for _ in 0..indent % self.tab_width + align {
+                result.push(' ');
+            }
+        } else {
+            for _ in 0..indent + align {
+                result.push(' ');
+            }
         }
-        self.col = indent;
+        self.col = indent + align;
     }
 
-    //fn insert_newline_with_indent(&mut self, result: &mut String, chunk: &Chunk) {
-    //    result.push('\n');
-    //    let total_indent = chunk.indent;
-    //    for _ in 0..total_indent {
-    //        result.push(' ');
-    //    }
-    //    self.col = total_indent;
-    //}
+    // The columns `text` takes when printed at `col`: its width, unless a tab
+    // in it, e.g. in a comment, moves to the next multiple of `tab_width`.
+    fn text_width(&self, text: &str, width: u32, col: u32) -> u32 {
+        if !text.contains('\t') {
+            return width;
+        }
+        let mut end = col;
+        for (i, part) in text.split('\t').enumerate() {
+            if i > 0 {
+                end = (end / self.tab_width + 1) * self.tab_width;
+            }
+            end += part.width() as u32;
+        }
+        end - col
+    }
 
     fn fits(&self, chunk: Chunk<'a>) -> bool {
         let mut remaining_width = self.max_width.saturating_sub(self.col);
@@ -241,8 +298,10 @@ impl<'a> PrettyPrinter<'a> {
                         return true;
                     }
                 }
-                Doc::Text(_, text_width) => {
-                    if *text_width <= remaining_width {
+                Doc::Text(text, width) => {
+                    let col = self.max_width.saturating_sub(remaining_width);
+                    let text_width = self.text_width(text, *width, col);
+                    if text_width <= remaining_width {
                         remaining_width -= text_width;
                     } else {
                         return false;
@@ -251,10 +310,8 @@ impl<'a> PrettyPrinter<'a> {
                 Doc::Flat(x) => stack.push(chunk.flat(x)),
                 Doc::Indent(i, x) => stack.push(chunk.indented(*i, x)),
                 Doc::Dedent(i, x) => stack.push(chunk.dedented(*i, x)),
-                //Doc::Align(relative_align_col, x) => {
-                //    let new_align = chunk.align + relative_align_col;
-                //    stack.push(chunk.align(new_align, x));
-                //}
+                // only the first line is measured, which alignment doesn't move
+                Doc::Align(_, x) => stack.push(chunk.with_doc(x)),
                 Doc::Concat(seq) => {
                     for n in seq.iter().rev() {
                         stack.push(chunk.with_doc(n));
@@ -277,6 +334,7 @@ impl<'a> PrettyPrinter<'a> {
 struct NewlineBuffer {
     has_pending_newline: bool,
     indent_level: u32,
+    align: u32,
 }
 
 impl NewlineBuffer {
@@ -284,17 +342,20 @@ impl NewlineBuffer {
         Self {
             has_pending_newline: false,
             indent_level: 0,
+            align: 0,
         }
     }
 
-    fn set_pending(&mut self, indent: u32) {
+    fn set_pending(&mut self, chunk: &Chunk) {
         self.has_pending_newline = true;
-        self.indent_level = indent;
+        self.indent_level = chunk.indent;
+        self.align = chunk.align;
     }
 
     fn clear(&mut self) {
         self.has_pending_newline = false;
         self.indent_level = 0;
+        self.align = 0;
     }
 
     fn is_pending(&self) -> bool {
@@ -304,4 +365,8 @@ impl NewlineBuffer {
     fn get_indent(&self) -> u32 {
         self.indent_level
     }
+
+    fn get_align(&self) -> u32 {
+        self.align
+    }
 }
@@ -2,8 +2,10 @@ use crate::{
     data_model::DocBuild,
     doc::{Doc, DocRef, PrettyConfig},
     enum_def::BodyMember,
+    formatter::IndentStyle,
 };
 use typed_arena::Arena;
+use unicode_width::UnicodeWidthStr;
 
 pub struct DocBuilder<'a> {
     arena: Arena<Doc<'a>>,
@@ -18,6 +20,10 @@ impl<'a> DocBuilder<'a> {
         }
     }
 
+    pub fn config(&self) -> &PrettyConfig {
+        &self.config
+    }
+
     // NOTE: group does NOT work with b.nl() so don't wrap b.nl() in any inputs
     pub fn group_surround(
         &'a self,
@@ -43,6 +49,8 @@ impl<'a> DocBuilder<'a> {
             ]);
         }
 
+        // the lines around the elements stay flat, the elements line up after the opening bracket
+        let visual = self.config.indent_style == IndentStyle::Visual;
         let mut docs = Vec::new();
 
         if let Some(n) = open.pre {
@@ -52,13 +60,13 @@ impl<'a> DocBuilder<'a> {
             docs.push(self.txt(n));
         }
         if let Some(n) = open.suf {
-            docs.push(self.indent(n));
+            docs.push(if visual { self.flat(n) } else { self.indent(n) });
         }
 
-        docs.push(self.indent(self.intersperse(elems, sep)));
+        docs.push(self.indent_or_align(self.intersperse(elems, sep)));
 
         if let Some(n) = close.pre {
-            docs.push(n);
+            docs.push(if visual { self.flat(n) } else { n });
         }
         if let Some(n) = close.str {
             docs.push(self.txt(n));
@@ -189,9 +197,24 @@ impl<'a> DocBuilder<'a> {
         self.arena.alloc(Doc::NewlineWithNoIndent)
     }
 
+    // Source text printed as-is: only the first line follows the current indentation,
+    // the others keep their original leading whitespace.
+    pub fn verbatim(&'a self, text: &str) -> DocRef<'a> {
+        let mut docs = Vec::new();
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                docs.push(self.nl_with_no_indent());
+            }
+            docs.push(self.txt(line.trim_end_matches('\r')));
+        }
+        self.concat(docs)
+    }
+
+    // The width is in display columns: East Asian wide characters and most emoji
+    // take two, combining marks none. Tabs are measured by the printer.
     pub fn txt(&'a self, text: impl ToString) -> DocRef<'a> {
         let s = text.to_string();
-        let width = s.len() as u32;
+        let width = s.width() as u32;
         self.arena.alloc(Doc::Text(s, width))
     }
 
@@ -218,18 +241,33 @@ impl<'a> DocBuilder<'a> {
     }
 
     pub fn indent(&'a self, doc_ref: DocRef<'a>) -> DocRef<'a> {
-        let relative_indent = self.config.indent_size;
+        let relative_indent = self.config.indent_width();
         self.arena.alloc(Doc::Indent(relative_indent, doc_ref))
     }
 
+    // Indent by a number of columns rather than by `indent_size`.
+    pub fn indent_by(&'a self, width: u32, doc_ref: DocRef<'a>) -> DocRef<'a> {
+        self.arena.alloc(Doc::Indent(width, doc_ref))
+    }
+
     pub fn dedent(&'a self, doc_ref: DocRef<'a>) -> DocRef<'a> {
-        let relative_indent = self.config.indent_size;
+        let relative_indent = self.config.indent_width();
         self.arena.alloc(Doc::Dedent(relative_indent, doc_ref))
     }
 
-    //fn align(&'a self, relative_col_offset: u32, doc_ref: DocRef<'a>) -> DocRef<'a> {
-    //    self.arena.alloc(Doc::Align(relative_col_offset, doc_ref))
-    //}
+    // One indent level deeper in the Block style. In the Visual style, the lines
+    // of `doc_ref` after the first start at the column of its first line instead.
+    pub fn indent_or_align(&'a self, doc_ref: DocRef<'a>) -> DocRef<'a> {
+        match self.config.indent_style {
+            IndentStyle::Block => self.indent(doc_ref),
+            IndentStyle::Visual => self.align(0, doc_ref),
+        }
+    }
+
+    // New lines in `doc_ref` start at the column where it starts, plus `relative_col_offset`.
+    pub fn align(&'a self, relative_col_offset: u32, doc_ref: DocRef<'a>) -> DocRef<'a> {
+        self.arena.alloc(Doc::Align(relative_col_offset, doc_ref))
+    }
 
     pub fn concat(&'a self, doc_refs: impl IntoIterator<Item = DocRef<'a>>) -> DocRef<'a> {
         let n_vec = doc_refs.into_iter().collect::<Vec<_>>();
@@ -4,7 +4,11 @@ use crate::{
     data_model::*,
     doc::DocRef,
     doc_builder::{DocBuilder, Insertable},
-    utility::{assert_check, build_with_comments_and_punc, get_comment_bucket, panic_unknown_node},
+    error::FormatError,
+    utility::{
+        assert_check, build_with_comments_and_punc, format_context, is_format_ignored,
+        unknown_node, unsupported_node,
+    },
 };
 use tree_sitter::Node;
 
@@ -14,17 +18,21 @@ pub enum RootMember {
     Enum(Box<EnumDeclaration>),
     Interface(Box<InterfaceDeclaration>),
     Trigger(Box<TriggerDeclaration>),
+    Verbatim(Verbatim),
 }
 
 impl RootMember {
-    pub fn new(n: Node) -> Self {
-        match n.kind() {
-            "class_declaration" => Self::Class(Box::new(ClassDeclaration::new(n))),
-            "enum_declaration" => Self::Enum(Box::new(EnumDeclaration::new(n))),
-            "trigger_declaration" => Self::Trigger(Box::new(TriggerDeclaration::new(n))),
-            "interface_declaration" => Self::Interface(Box::new(InterfaceDeclaration::new(n))),
-            _ => panic_unknown_node(n, "Root"),
+    pub fn new(n: Node) -> Result<Self, FormatError> {
+        if is_format_ignored(&n) {
+            return Ok(Self::Verbatim(Verbatim::new(n)?));
         }
+        Ok(match n.kind() {
+            "class_declaration" => Self::Class(Box::new(ClassDeclaration::new(n)?)),
+            "enum_declaration" => Self::Enum(Box::new(EnumDeclaration::new(n)?)),
+            "trigger_declaration" => Self::Trigger(Box::new(TriggerDeclaration::new(n)?)),
+            "interface_declaration" => Self::Interface(Box::new(InterfaceDeclaration::new(n)?)),
+            _ => return Err(unknown_node(n, "Root")),
+        })
     }
 }
 
@@ -43,6 +51,9 @@ impl<'a> DocBuild<'a> for RootMember {
             RootMember::Trigger(n) => {
                 result.push(n.build(b));
             }
+            RootMember::Verbatim(n) => {
+                result.push(n.build(b));
+            }
         }
     }
 }
@@ -57,22 +68,26 @@ pub enum ClassMember {
     Enum(EnumDeclaration),
     Static(StaticInitializer),
     Constructor(ConstructorDeclaration),
+    Verbatim(Verbatim),
     //SemiColumn,
 }
 
 impl ClassMember {
-    pub fn new(n: Node) -> Self {
-        match n.kind() {
-            "field_declaration" => Self::Field(Box::new(FieldDeclaration::new(n))),
-            "class_declaration" => Self::NestedClass(Box::new(ClassDeclaration::new(n))),
-            "method_declaration" => Self::Method(Box::new(MethodDeclaration::new(n))),
-            "interface_declaration" => Self::Interface(Box::new(InterfaceDeclaration::new(n))),
-            "block" => Self::Block(Box::new(Block::new(n))),
-            "constructor_declaration" => Self::Constructor(ConstructorDeclaration::new(n)),
-            "enum_declaration" => Self::Enum(EnumDeclaration::new(n)),
-            "static_initializer" => Self::Static(StaticInitializer::new(n)),
-            _ => panic_unknown_node(n, "ClassMember"),
+    pub fn new(n: Node) -> Result<Self, FormatError> {
+        if is_format_ignored(&n) {
+            return Ok(Self::Verbatim(Verbatim::new(n)?));
         }
+        Ok(match n.kind() {
+            "field_declaration" => Self::Field(Box::new(FieldDeclaration::new(n)?)),
+            "class_declaration" => Self::NestedClass(Box::new(ClassDeclaration::new(n)?)),
+            "method_declaration" => Self::Method(Box::new(MethodDeclaration::new(n)?)),
+            "interface_declaration" => Self::Interface(Box::new(InterfaceDeclaration::new(n)?)),
+            "block" => Self::Block(Box::new(Block::new(n)?)),
+            "constructor_declaration" => Self::Constructor(ConstructorDeclaration::new(n)?),
+            "enum_declaration" => Self::Enum(EnumDeclaration::new(n)?),
+            "static_initializer" => Self::Static(StaticInitializer::new(n)?),
+            _ => return Err(unknown_node(n, "ClassMember")),
+        })
     }
 }
 
@@ -103,6 +118,9 @@ impl<'a> DocBuild<'a> for ClassMember {
             Self::Static(s) => {
                 result.push(s.build(b));
             }
+            Self::Verbatim(n) => {
+                result.push(n.build(b));
+            }
         }
     }
 }
@@ -114,17 +132,17 @@ pub enum UnannotatedType {
 }
 
 impl UnannotatedType {
-    pub fn new(n: Node) -> Self {
-        match n.kind() {
+    pub fn new(n: Node) -> Result<Self, FormatError> {
+        Ok(match n.kind() {
             "type_identifier"
             | "void_type"
             | "boolean_type"
             | "generic_type"
             | "java_type"
-            | "scoped_type_identifier" => Self::Simple(SimpleType::new(n)),
-            "array_type" => Self::Array(Box::new(ArrayType::new(n))),
-            _ => panic_unknown_node(n, "UnnanotatedType"),
-        }
+            | "scoped_type_identifier" => Self::Simple(SimpleType::new(n)?),
+            "array_type" => Self::Array(Box::new(ArrayType::new(n)?)),
+            _ => return Err(unknown_node(n, "UnnanotatedType")),
+        })
     }
 }
 
@@ -148,16 +166,16 @@ pub enum SimpleType {
 }
 
 impl SimpleType {
-    pub fn new(n: Node) -> Self {
-        match n.kind() {
-            "type_identifier" => Self::Identifier(ValueNode::new(n)),
-            "void_type" => Self::Void(ValueNode::new(n)),
-            "boolean_type" => Self::Bool(BoolType::new(n)),
-            "java_type" => Self::Java(JavaType::new(n)),
-            "generic_type" => Self::Generic(GenericType::new(n)),
-            "scoped_type_identifier" => Self::Scoped(ScopedTypeIdentifier::new(n)),
-            _ => panic_unknown_node(n, "SimpleType"),
-        }
+    pub fn new(n: Node) -> Result<Self, FormatError> {
+        Ok(match n.kind() {
+            "type_identifier" => Self::Identifier(ValueNode::new(n)?),
+            "void_type" => Self::Void(ValueNode::new(n)?),
+            "boolean_type" => Self::Bool(BoolType::new(n)?),
+            "java_type" => Self::Java(JavaType::new(n)?),
+            "generic_type" => Self::Generic(GenericType::new(n)?),
+            "scoped_type_identifier" => Self::Scoped(ScopedTypeIdentifier::new(n)?),
+            _ => return Err(unknown_node(n, "SimpleType")),
+        })
     }
 }
 
@@ -193,11 +211,11 @@ pub enum VariableInitializer {
 }
 
 impl VariableInitializer {
-    pub fn new(n: Node) -> Self {
-        match n.kind() {
-            "array_initializer" => Self::Array(Box::new(ArrayInitializer::new(n))),
-            _ => Self::Exp(Expression::new(n)),
-        }
+    pub fn new(n: Node) -> Result<Self, FormatError> {
+        Ok(match n.kind() {
+            "array_initializer" => Self::Array(Box::new(ArrayInitializer::new(n)?)),
+            _ => Self::Exp(Expression::new(n)?),
+        })
     }
 }
 
@@ -228,10 +246,10 @@ pub enum Expression {
 }
 
 impl Expression {
-    pub fn new(n: Node) -> Self {
-        match n.kind() {
-            "assignment_expression" => Self::Assignment(Box::new(AssignmentExpression::new(n))),
-            "binary_expression" => Self::Binary(Box::new(BinaryExpression::new(n))),
+    pub fn new(n: Node) -> Result<Self, FormatError> {
+        Ok(match n.kind() {
+            "assignment_expression" => Self::Assignment(Box::new(AssignmentExpression::new(n)?)),
+            "binary_expression" => Self::Binary(Box::new(BinaryExpression::new(n)?)),
             "int"
             | "decimal_floating_point_literal"
             | "query_expression"
@@ -249,15 +267,15 @@ impl Expression {
             | "version_expression"
             | "java_field_access"
             | "this"
-            | "array_creation_expression" => Self::Primary(Box::new(PrimaryExpression::new(n))),
-            "update_expression" => Self::Update(UpdateExpression::new(n)),
-            "unary_expression" => Self::Unary(UnaryExpression::new(n)),
-            "dml_expression" => Self::Dml(Box::new(DmlExpression::new(n))),
-            "ternary_expression" => Self::Te(Box::new(TernaryExpression::new(n))),
-            "cast_expression" => Self::Cast(Box::new(CastExpression::new(n))),
-            "instanceof_expression" => Self::Instance(Box::new(InstanceOfExpression::new(n))),
-            _ => panic_unknown_node(n, "Expression"),
-        }
+            | "array_creation_expression" => Self::Primary(Box::new(PrimaryExpression::new(n)?)),
+            "update_expression" => Self::Update(UpdateExpression::new(n)?),
+            "unary_expression" => Self::Unary(UnaryExpression::new(n)?),
+            "dml_expression" => Self::Dml(Box::new(DmlExpression::new(n)?)),
+            "ternary_expression" => Self::Te(Box::new(TernaryExpression::new(n)?)),
+            "cast_expression" => Self::Cast(Box::new(CastExpression::new(n)?)),
+            "instanceof_expression" => Self::Instance(Box::new(InstanceOfExpression::new(n)?)),
+            _ => return Err(unknown_node(n, "Expression")),
+        })
     }
 }
 
@@ -326,34 +344,34 @@ pub enum PrimaryExpression {
     Array(Box<ArrayAccess>),
     ArrayCreation(ArrayCreationExpression),
     Version(VersionExpression),
-    Query(QueryExpression),
+    Query(Box<QueryExpression>),
     This(This),
     Java(JavaFieldAccess),
 }
 
 impl PrimaryExpression {
-    pub fn new(n: Node) -> Self {
-        match n.kind() {
+    pub fn new(n: Node) -> Result<Self, FormatError> {
+        Ok(match n.kind() {
             "int"
             | "decimal_floating_point_literal"
             | "boolean"
             | "null_literal"
-            | "string_literal" => Self::Literal(Literal_::new(n)),
-            "identifier" => Self::Identifier(ValueNode::new(n)),
-            "class_literal" => Self::Class(ClassLiteral::new(n)),
-            "method_invocation" => Self::Method(MethodInvocation::new(n)),
-            "parenthesized_expression" => Self::Parenth(ParenthesizedExpression::new(n)),
-            "object_creation_expression" => Self::Obj(ObjectCreationExpression::new(n)),
-            "map_creation_expression" => Self::Map(MapCreationExpression::new(n)),
-            "field_access" => Self::Field(FieldAccess::new(n)),
-            "array_access" => Self::Array(Box::new(ArrayAccess::new(n))),
-            "array_creation_expression" => Self::ArrayCreation(ArrayCreationExpression::new(n)),
-            "version_expression" => Self::Version(VersionExpression::new(n)),
-            "query_expression" => Self::Query(QueryExpression::new(n)),
-            "java_field_access" => Self::Java(JavaFieldAccess::new(n)),
-            "this" => Self::This(This::new(n)),
-            _ => panic_unknown_node(n, "PrimaryExpression"),
-        }
+            | "string_literal" => Self::Literal(Literal_::new(n)?),
+            "identifier" => Self::Identifier(ValueNode::new(n)?),
+            "class_literal" => Self::Class(ClassLiteral::new(n)?),
+            "method_invocation" => Self::Method(MethodInvocation::new(n)?),
+            "parenthesized_expression" => Self::Parenth(ParenthesizedExpression::new(n)?),
+            "object_creation_expression" => Self::Obj(ObjectCreationExpression::new(n)?),
+            "map_creation_expression" => Self::Map(MapCreationExpression::new(n)?),
+            "field_access" => Self::Field(FieldAccess::new(n)?),
+            "array_access" => Self::Array(Box::new(ArrayAccess::new(n)?)),
+            "array_creation_expression" => Self::ArrayCreation(ArrayCreationExpression::new(n)?),
+            "version_expression" => Self::Version(VersionExpression::new(n)?),
+            "query_expression" => Self::Query(Box::new(QueryExpression::new(n)?)),
+            "java_field_access" => Self::Java(JavaFieldAccess::new(n)?),
+            "this" => Self::This(This::new(n)?),
+            _ => return Err(unknown_node(n, "PrimaryExpression")),
+        })
     }
 }
 
@@ -413,13 +431,13 @@ pub struct ClassLiteral {
 }
 
 impl ClassLiteral {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "class_literal");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "class_literal")?;
 
-        Self {
-            type_: UnannotatedType::new(node.first_c()),
+        Ok(Self {
+            type_: UnannotatedType::new(node.first_c()?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -442,15 +460,15 @@ pub enum Literal_ {
 }
 
 impl Literal_ {
-    pub fn new(node: Node) -> Self {
-        match node.kind() {
-            "boolean" => Self::Bool(ValueNodeLowerCase::new(node)),
-            "null_literal" => Self::Null(ValueNodeLowerCase::new(node)),
-            "int" => Self::Int(ValueNode::new(node)),
-            "string_literal" => Self::Str(ValueNode::new(node)),
-            "decimal_floating_point_literal" => Self::Decimal(ValueNodeLowerCase::new(node)),
-            _ => panic_unknown_node(node, "Literal_"),
-        }
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        Ok(match node.kind() {
+            "boolean" => Self::Bool(ValueNodeLowerCase::new(node)?),
+            "null_literal" => Self::Null(ValueNodeLowerCase::new(node)?),
+            "int" => Self::Int(ValueNode::new(node)?),
+            "string_literal" => Self::Str(ValueNode::new(node)?),
+            "decimal_floating_point_literal" => Self::Decimal(ValueNodeLowerCase::new(node)?),
+            _ => return Err(unknown_node(node, "Literal_")),
+        })
     }
 }
 
@@ -496,9 +514,9 @@ pub enum ModifierKind {
 }
 
 impl ModifierKind {
-    pub fn new(n: Node) -> Self {
+    pub fn new(n: Node) -> Result<Self, FormatError> {
         let kind = n.kind();
-        match kind {
+        Ok(match kind {
             "global" => Self::Global,
             "public" => Self::Public,
             "with_sharing" => Self::WithSharing,
@@ -514,8 +532,8 @@ impl ModifierKind {
             "testMethod" => Self::TestMethod,
             "transient" => Self::Transient,
             "webservice" => Self::Webservice,
-            _ => panic_unknown_node(n, "Modifier"),
-        }
+            _ => return Err(unknown_node(n, "Modifier")),
+        })
     }
 }
 
@@ -608,30 +626,34 @@ pub enum Statement {
     Break(BreakStatement),
     Continue(ContinueStatement),
     Switch(Box<SwitchExpression>),
+    Verbatim(Verbatim),
     SemiColumn,
 }
 
 impl Statement {
-    pub fn new(n: Node) -> Self {
-        match n.kind() {
-            "if_statement" => Self::If(Box::new(IfStatement::new(n))),
-            "expression_statement" => Self::Exp(ExpressionStatement::new(n)),
-            "local_variable_declaration" => Self::Local(LocalVariableDeclaration::new(n)),
-            "block" => Self::Block(Box::new(Block::new(n))),
-            "for_statement" => Self::For(Box::new(ForStatement::new(n))),
-            "enhanced_for_statement" => Self::EnhancedFor(Box::new(EnhancedForStatement::new(n))),
-            "run_as_statement" => Self::Run(RunAsStatement::new(n)),
-            "do_statement" => Self::Do(Box::new(DoStatement::new(n))),
-            "while_statement" => Self::While(Box::new(WhileStatement::new(n))),
-            "return_statement" => Self::Return(ReturnStatement::new(n)),
-            "try_statement" => Self::Try(Box::new(TryStatement::new(n))),
-            "throw_statement" => Self::Throw(ThrowStatement::new(n)),
-            "break_statement" => Self::Break(BreakStatement::new(n)),
-            "continue_statement" => Self::Continue(ContinueStatement::new(n)),
-            "switch_expression" => Self::Switch(Box::new(SwitchExpression::new(n))),
+    pub fn new(n: Node) -> Result<Self, FormatError> {
+        if is_format_ignored(&n) {
+            return Ok(Self::Verbatim(Verbatim::new(n)?));
+        }
+        Ok(match n.kind() {
+            "if_statement" => Self::If(Box::new(IfStatement::new(n)?)),
+            "expression_statement" => Self::Exp(ExpressionStatement::new(n)?),
+            "local_variable_declaration" => Self::Local(LocalVariableDeclaration::new(n)?),
+            "block" => Self::Block(Box::new(Block::new(n)?)),
+            "for_statement" => Self::For(Box::new(ForStatement::new(n)?)),
+            "enhanced_for_statement" => Self::EnhancedFor(Box::new(EnhancedForStatement::new(n)?)),
+            "run_as_statement" => Self::Run(RunAsStatement::new(n)?),
+            "do_statement" => Self::Do(Box::new(DoStatement::new(n)?)),
+            "while_statement" => Self::While(Box::new(WhileStatement::new(n)?)),
+            "return_statement" => Self::Return(ReturnStatement::new(n)?),
+            "try_statement" => Self::Try(Box::new(TryStatement::new(n)?)),
+            "throw_statement" => Self::Throw(ThrowStatement::new(n)?),
+            "break_statement" => Self::Break(BreakStatement::new(n)?),
+            "continue_statement" => Self::Continue(ContinueStatement::new(n)?),
+            "switch_expression" => Self::Switch(Box::new(SwitchExpression::new(n)?)),
             ";" => Self::SemiColumn,
-            _ => panic_unknown_node(n, "Statement"),
-        }
+            _ => return Err(unknown_node(n, "Statement")),
+        })
     }
     pub fn is_block(&self) -> bool {
         matches!(self, Statement::Block(_))
@@ -686,6 +708,9 @@ impl<'a> DocBuild<'a> for Statement {
             Self::Switch(n) => {
                 result.push(n.build(b));
             }
+            Self::Verbatim(n) => {
+                result.push(n.build(b));
+            }
             Self::SemiColumn => {
                 result.push(b.txt(";"));
             }
@@ -699,17 +724,17 @@ pub enum Type {
 }
 
 impl Type {
-    pub fn new(n: Node) -> Self {
-        match n.kind() {
+    pub fn new(n: Node) -> Result<Self, FormatError> {
+        Ok(match n.kind() {
             "type_identifier"
             | "void_type"
             | "boolean_type"
             | "generic_type"
             | "scoped_type_identifier"
-            | "java_type" => Self::Unannotated(UnannotatedType::Simple(SimpleType::new(n))),
-            "array_type" => Self::Unannotated(UnannotatedType::Array(Box::new(ArrayType::new(n)))),
-            _ => panic_unknown_node(n, "Type"),
-        }
+            | "java_type" => Self::Unannotated(UnannotatedType::Simple(SimpleType::new(n)?)),
+            "array_type" => Self::Unannotated(UnannotatedType::Array(Box::new(ArrayType::new(n)?))),
+            _ => return Err(unknown_node(n, "Type")),
+        })
     }
 }
 
@@ -750,22 +775,22 @@ pub enum AnnotationArgumentList {
 }
 
 impl AnnotationArgumentList {
-    pub fn new(n: Node) -> Self {
+    pub fn new(n: Node) -> Result<Self, FormatError> {
         if n.named_child_count() == 0 {
-            return Self::Nil;
+            return Ok(Self::Nil);
         }
 
         let key_values = n.try_cs_by_k("annotation_key_value");
 
-        if key_values.is_empty() {
-            Self::Value(ValueNode::new(n.c_by_n("value")))
+        Ok(if key_values.is_empty() {
+            Self::Value(ValueNode::new(n.c_by_n("value")?)?)
         } else {
             let key_values = key_values
                 .into_iter()
                 .map(AnnotationKeyValue::new)
-                .collect();
+                .collect::<Result<_, _>>()?;
             Self::KeyValues(key_values)
-        }
+        })
     }
 }
 
@@ -810,7 +835,8 @@ impl<M> BodyMember<M> {
     // take comment nodes into consideration
     fn has_trailing_newline(node: &Node) -> bool {
         let node_id = &node.id();
-        let bucket = get_comment_bucket(node_id);
+        let context = format_context();
+        let bucket = context.comment_bucket(node_id);
 
         // we assume post_comments are always inline
         // because this method is not called by last element of BodyMember
@@ -819,7 +845,7 @@ impl<M> BodyMember<M> {
         }
 
         node.next_named_sibling()
-            .is_some_and(|n| node.end_position().row < n.start_position().row - 1)
+            .is_some_and(|n| node.end_position().row + 1 < n.start_position().row)
     }
 }
 
@@ -835,8 +861,8 @@ pub enum TriggerEventVariant {
 }
 
 impl TriggerEventVariant {
-    pub fn new(n: Node) -> Self {
-        match n.kind() {
+    pub fn new(n: Node) -> Result<Self, FormatError> {
+        Ok(match n.kind() {
             "before_insert" => Self::BeforeInsert,
             "before_update" => Self::BeforeUpdate,
             "before_delete" => Self::BeforeDelete,
@@ -844,8 +870,8 @@ impl TriggerEventVariant {
             "after_update" => Self::AfterUpdate,
             "after_delete" => Self::AfterDelete,
             "after_undelete" => Self::AfterUndelete,
-            _ => panic_unknown_node(n, "TriggerEvent"),
-        }
+            _ => return Err(unknown_node(n, "TriggerEvent")),
+        })
     }
 }
 
@@ -884,19 +910,21 @@ pub enum SelectClauseVariant {
 }
 
 impl SelectClauseVariant {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "select_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "select_clause")?;
 
-        if let Some(count_node) = node.try_c_by_k("count_expression") {
-            Self::Count(CountExpression::new(count_node))
-        } else {
-            Self::Selectable(
-                node.children_vec()
-                    .into_iter()
-                    .map(|n| SelectableExpression::new(n))
-                    .collect(),
-            )
-        }
+        Ok(
+            if let Some(count_node) = node.try_c_by_k("count_expression") {
+                Self::Count(CountExpression::new(count_node)?)
+            } else {
+                Self::Selectable(
+                    node.children_vec()
+                        .into_iter()
+                        .map(SelectableExpression::new)
+                        .collect::<Result<_, _>>()?,
+                )
+            },
+        )
     }
 }
 
@@ -933,17 +961,17 @@ pub enum SelectableExpression {
 }
 
 impl SelectableExpression {
-    pub fn new(node: Node) -> Self {
-        match node.kind() {
-            "field_identifier" => Self::Value(ValueExpression::Field(FieldIdentifier::new(node))),
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        Ok(match node.kind() {
+            "field_identifier" => Self::Value(ValueExpression::Field(FieldIdentifier::new(node)?)),
             "function_expression" => Self::Value(ValueExpression::Function(Box::new(
-                FunctionExpression::new(node),
+                FunctionExpression::new(node)?,
             ))),
-            "alias_expression" => Self::Alias(AliasExpression::new(node)),
-            "fields_expression" => Self::Fields(FieldsExpression::new(node)),
-            "subquery" => Self::Sub(SubQuery::new(node)),
-            _ => panic_unknown_node(node, "SelectableExpression"),
-        }
+            "alias_expression" => Self::Alias(AliasExpression::new(node)?),
+            "fields_expression" => Self::Fields(FieldsExpression::new(node)?),
+            "subquery" => Self::Sub(SubQuery::new(node)?),
+            _ => return Err(unknown_node(node, "SelectableExpression")),
+        })
     }
 }
 
@@ -973,13 +1001,13 @@ pub struct FieldsExpression {
 }
 
 impl FieldsExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "fields_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "fields_expression")?;
 
-        Self {
-            fields_type: ValueNodeUpperCase::new(node.c_by_k("fields_type")),
+        Ok(Self {
+            fields_type: ValueNodeUpperCase::new(node.c_by_k("fields_type")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -1001,14 +1029,14 @@ pub struct AliasExpression {
 }
 
 impl AliasExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "alias_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "alias_expression")?;
 
-        Self {
-            value_exp: ValueExpression::new(node.first_c()),
-            identifier: ValueNode::new(node.c_by_k("identifier")),
+        Ok(Self {
+            value_exp: ValueExpression::new(node.first_c()?)?,
+            identifier: ValueNode::new(node.c_by_k("identifier")?)?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -1029,15 +1057,15 @@ pub enum FieldIdentifierVariant {
 }
 
 impl FieldIdentifierVariant {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "field_identifier");
-
-        let c = node.first_c();
-        match c.kind() {
-            "identifier" => Self::Identifier(ValueNode::new(c)),
-            "dotted_identifier" => Self::Dotted(DottedIdentifier::new(c)),
-            _ => panic_unknown_node(c, "FieldIdentifier"),
-        }
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "field_identifier")?;
+
+        let c = node.first_c()?;
+        Ok(match c.kind() {
+            "identifier" => Self::Identifier(ValueNode::new(c)?),
+            "dotted_identifier" => Self::Dotted(DottedIdentifier::new(c)?),
+            _ => return Err(unknown_node(c, "FieldIdentifier")),
+        })
     }
 }
 
@@ -1061,12 +1089,12 @@ pub enum StorageVariant {
 }
 
 impl StorageVariant {
-    pub fn new(node: Node) -> StorageVariant {
-        match node.kind() {
-            "storage_alias" => Self::Alias(StorageAlias::new(node)),
-            "storage_identifier" => Self::Identifier(StorageIdentifier::new(node)),
-            _ => panic_unknown_node(node, "StorageVariant"),
-        }
+    pub fn new(node: Node) -> Result<StorageVariant, FormatError> {
+        Ok(match node.kind() {
+            "storage_alias" => Self::Alias(StorageAlias::new(node)?),
+            "storage_identifier" => Self::Identifier(StorageIdentifier::new(node)?),
+            _ => return Err(unknown_node(node, "StorageVariant")),
+        })
     }
 }
 
@@ -1090,20 +1118,20 @@ pub enum StorageIdentifierVariant {
 }
 
 impl StorageIdentifierVariant {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "storage_identifier");
-        let c = node.first_c();
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "storage_identifier")?;
+        let c = node.first_c()?;
 
-        match c.kind() {
-            "identifier" => Self::Identifier(ValueNode::new(c)),
+        Ok(match c.kind() {
+            "identifier" => Self::Identifier(ValueNode::new(c)?),
             "dotted_identifier" => Self::Dotted(
-                c.cs_by_k("identifier")
+                c.cs_by_k("identifier")?
                     .into_iter()
-                    .map(|n| ValueNode::new(n))
-                    .collect(),
+                    .map(ValueNode::new)
+                    .collect::<Result<_, _>>()?,
             ),
-            _ => panic_unknown_node(c, "StorageIdentifier"),
-        }
+            _ => return Err(unknown_node(c, "StorageIdentifier")),
+        })
     }
 }
 
@@ -1130,12 +1158,12 @@ pub enum LimitValue {
 }
 
 impl LimitValue {
-    pub fn new(n: Node) -> Self {
-        match n.kind() {
-            "int" => Self::Int(ValueNode::new(n)),
-            "bound_apex_expression" => Self::Bound(BoundApexExpression::new(n)),
-            _ => panic_unknown_node(n, "LimitValue"),
-        }
+    pub fn new(n: Node) -> Result<Self, FormatError> {
+        Ok(match n.kind() {
+            "int" => Self::Int(ValueNode::new(n)?),
+            "bound_apex_expression" => Self::Bound(BoundApexExpression::new(n)?),
+            _ => return Err(unknown_node(n, "LimitValue")),
+        })
     }
 }
 
@@ -1161,13 +1189,13 @@ pub enum BooleanExpression {
 }
 
 impl BooleanExpression {
-    pub fn new(node: Node) -> Self {
-        match node.kind() {
-            "and_expression" => Self::And(AndExpression::new(node)),
-            "or_expression" => Self::Or(OrExpression::new(node)),
-            "not_expression" => Self::Not(NotExpression::new(node)),
-            _ => Self::Condition(Box::new(ConditionExpression::new(node))),
-        }
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        Ok(match node.kind() {
+            "and_expression" => Self::And(AndExpression::new(node)?),
+            "or_expression" => Self::Or(OrExpression::new(node)?),
+            "not_expression" => Self::Not(NotExpression::new(node)?),
+            _ => Self::Condition(Box::new(ConditionExpression::new(node)?)),
+        })
     }
 
     fn operator(&self) -> Option<&str> {
@@ -1207,11 +1235,11 @@ pub enum ConditionExpression {
 }
 
 impl ConditionExpression {
-    pub fn new(node: Node) -> Self {
-        match node.kind() {
-            "comparison_expression" => Self::Comparison(ComparisonExpression::new(node)),
-            _ => Self::Bool(Box::new(BooleanExpression::new(node))),
-        }
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        Ok(match node.kind() {
+            "comparison_expression" => Self::Comparison(ComparisonExpression::new(node)?),
+            _ => Self::Bool(Box::new(BooleanExpression::new(node)?)),
+        })
     }
 
     pub fn build_with_parent<'a>(
@@ -1255,12 +1283,12 @@ pub enum ValueExpression {
 }
 
 impl ValueExpression {
-    pub fn new(n: Node) -> Self {
-        match n.kind() {
-            "field_identifier" => Self::Field(FieldIdentifier::new(n)),
-            "function_expression" => Self::Function(Box::new(FunctionExpression::new(n))),
-            _ => panic_unknown_node(n, "ValueExpression"),
-        }
+    pub fn new(n: Node) -> Result<Self, FormatError> {
+        Ok(match n.kind() {
+            "field_identifier" => Self::Field(FieldIdentifier::new(n)?),
+            "function_expression" => Self::Function(Box::new(FunctionExpression::new(n)?)),
+            _ => return Err(unknown_node(n, "ValueExpression")),
+        })
     }
 }
 
@@ -1289,29 +1317,32 @@ pub enum GeoLocationTypeVariant {
 }
 
 impl GeoLocationTypeVariant {
-    pub fn new(node: Node) -> Self {
-        let child = node.first_c();
-        match child.kind() {
-            "field_identifier" => Self::Field(FieldIdentifier::new(child)),
-            "bound_apex_expression" => Self::Bound(BoundApexExpression::new(child)),
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        let child = node.first_c()?;
+        Ok(match child.kind() {
+            "field_identifier" => Self::Field(FieldIdentifier::new(child)?),
+            "bound_apex_expression" => Self::Bound(BoundApexExpression::new(child)?),
             "identifier" => {
-                let decimals = node.cs_by_k("decimal");
+                let decimals = node.cs_by_k("decimal")?;
                 if decimals.len() != 2 {
-                    panic!(
-                        "expect 2 decimal nodes, found {} in GeoLocationType",
-                        decimals.len()
-                    );
+                    return Err(unsupported_node(
+                        &node,
+                        format!(
+                            "expect 2 decimal nodes, found {} in GeoLocationType",
+                            decimals.len()
+                        ),
+                    ));
                 }
 
                 Self::Func {
-                    function_name: ValueNode::new(child),
-                    decimal1: ValueNode::new(decimals[0]),
-                    decimal2: ValueNode::new(decimals[1]),
+                    function_name: ValueNode::new(child)?,
+                    decimal1: ValueNode::new(decimals[0])?,
+                    decimal2: ValueNode::new(decimals[1])?,
                 }
             }
 
-            _ => panic_unknown_node(child, "GeoLocationType"),
-        }
+            _ => return Err(unknown_node(child, "GeoLocationType")),
+        })
     }
 }
 
@@ -1388,23 +1419,25 @@ pub enum SoqlLiteral {
     Boolean(ValueNode),
     DateLiteral(ValueNode),
     DWithParam(DateLiteralWithParam),
-    CurrentLiteral(ValueNode),
+    CurrencyLiteral(ValueNode),
     NullLiteral(ValueNode),
 }
 
 impl SoqlLiteral {
-    pub fn new(node: Node) -> Self {
-        match node.kind() {
-            "decimal" => Self::Decimal(ValueNode::new(node)),
-            "int" => Self::Int(ValueNode::new(node)),
-            "string_literal" => Self::StringLiteral(ValueNode::new(node)),
-            "boolean" => Self::Boolean(ValueNode::new(node)),
-            "date" => Self::Boolean(ValueNode::new(node)),
-            "date_literal" => Self::DateLiteral(ValueNode::new(node)),
-            "date_literal_with_param" => Self::DWithParam(DateLiteralWithParam::new(node)),
-            "null_literal" => Self::NullLiteral(ValueNode::new(node)),
-            _ => panic_unknown_node(node, "SoqlLiteral"),
-        }
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        Ok(match node.kind() {
+            "decimal" => Self::Decimal(ValueNode::new(node)?),
+            "int" => Self::Int(ValueNode::new(node)?),
+            "string_literal" => Self::StringLiteral(ValueNode::new(node)?),
+            "boolean" => Self::Boolean(ValueNode::new(node)?),
+            "date" => Self::Date(ValueNode::new(node)?),
+            "date_time" => Self::DateTime(ValueNode::new(node)?),
+            "date_literal" => Self::DateLiteral(ValueNode::new(node)?),
+            "date_literal_with_param" => Self::DWithParam(DateLiteralWithParam::new(node)?),
+            "currency_literal" => Self::CurrencyLiteral(ValueNode::new(node)?),
+            "null_literal" => Self::NullLiteral(ValueNode::new(node)?),
+            _ => return Err(unknown_node(node, "SoqlLiteral")),
+        })
     }
 }
 
@@ -1423,6 +1456,9 @@ impl<'a> DocBuild<'a> for SoqlLiteral {
             Self::Date(n) => {
                 result.push(n.build(b));
             }
+            Self::DateTime(n) => {
+                result.push(n.build(b));
+            }
             Self::Boolean(n) => {
                 result.push(n.build(b));
             }
@@ -1432,11 +1468,11 @@ impl<'a> DocBuild<'a> for SoqlLiteral {
             Self::DWithParam(n) => {
                 result.push(n.build(b));
             }
-            Self::NullLiteral(n) => {
+            Self::CurrencyLiteral(n) => {
                 result.push(n.build(b));
             }
-            _ => {
-                unreachable!();
+            Self::NullLiteral(n) => {
+                result.push(n.build(b));
             }
         }
     }
@@ -1450,14 +1486,14 @@ pub struct DateLiteralWithParam {
 }
 
 impl DateLiteralWithParam {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "date_literal_with_param");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "date_literal_with_param")?;
 
-        Self {
-            date_literal: node.cvalue_by_k("date_literal").to_uppercase(),
-            param: node.cvalue_by_k("int"),
+        Ok(Self {
+            date_literal: node.cvalue_by_k("date_literal")?.to_uppercase(),
+            param: node.cvalue_by_k("int")?,
             node_info: NodeInfo::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -1477,13 +1513,13 @@ pub enum SetValue {
 }
 
 impl SetValue {
-    pub fn new(node: Node) -> Self {
-        match node.kind() {
-            "subquery" => Self::Sub(SubQuery::new(node)),
-            "comparable_list" => Self::List(ComparableList::new(node)),
-            "bound_apex_expression" => Self::Bound(BoundApexExpression::new(node)),
-            _ => panic_unknown_node(node, "SetValue"),
-        }
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        Ok(match node.kind() {
+            "subquery" => Self::Sub(SubQuery::new(node)?),
+            "comparable_list" => Self::List(ComparableList::new(node)?),
+            "bound_apex_expression" => Self::Bound(BoundApexExpression::new(node)?),
+            _ => return Err(unknown_node(node, "SetValue")),
+        })
     }
 }
 
@@ -1510,11 +1546,11 @@ pub enum ComparableListValue {
 }
 
 impl ComparableListValue {
-    pub fn new(node: Node) -> Self {
-        match node.kind() {
-            "bound_apex_expression" => Self::Bound(BoundApexExpression::new(node)),
-            _ => Self::Literal(SoqlLiteral::new(node)),
-        }
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        Ok(match node.kind() {
+            "bound_apex_expression" => Self::Bound(BoundApexExpression::new(node)?),
+            _ => Self::Literal(SoqlLiteral::new(node)?),
+        })
     }
 }
 
@@ -1538,15 +1574,15 @@ pub enum OffsetClause {
 }
 
 impl OffsetClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "offset_clause");
-
-        let first_c = node.first_c();
-        match first_c.kind() {
-            "int" => Self::Int(ValueNode::new(first_c)),
-            "bound_apex_expression" => Self::Bound(BoundApexExpression::new(first_c)),
-            _ => panic_unknown_node(first_c, "OffsetClause"),
-        }
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "offset_clause")?;
+
+        let first_c = node.first_c()?;
+        Ok(match first_c.kind() {
+            "int" => Self::Int(ValueNode::new(first_c)?),
+            "bound_apex_expression" => Self::Bound(BoundApexExpression::new(first_c)?),
+            _ => return Err(unknown_node(first_c, "OffsetClause")),
+        })
     }
 }
 
@@ -1571,7 +1607,7 @@ pub enum FunctionExpressionVariant {
         function_name: ValueNode,
         field: Option<FieldIdentifier>,
         bound: Option<BoundApexExpression>,
-        geo: GeoLocationType,
+        geo: Box<GeoLocationType>,
         string_literal: ValueNode,
     },
     WithoutGEO {
@@ -1581,34 +1617,36 @@ pub enum FunctionExpressionVariant {
 }
 
 impl FunctionExpressionVariant {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "function_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "function_expression")?;
 
         let function_expression = if node.try_c_by_k("geo_location_type").is_some() {
             Self::WithGEO {
-                function_name: ValueNode::new(node.c_by_n("function_name")),
+                function_name: ValueNode::new(node.c_by_n("function_name")?)?,
                 field: node
                     .try_c_by_k("field_identifier")
-                    .map(|n| FieldIdentifier::new(n)),
+                    .map(FieldIdentifier::new)
+                    .transpose()?,
                 bound: node
                     .try_c_by_k("bound_apex_expression")
-                    .map(|n| BoundApexExpression::new(n)),
-                geo: GeoLocationType::new(node.c_by_k("geo_location_type")),
-                string_literal: ValueNode::new(node.c_by_k("string_literal")),
+                    .map(BoundApexExpression::new)
+                    .transpose()?,
+                geo: Box::new(GeoLocationType::new(node.c_by_k("geo_location_type")?)?),
+                string_literal: ValueNode::new(node.c_by_k("string_literal")?)?,
             }
         } else {
             Self::WithoutGEO {
-                function_name: ValueNode::new(node.c_by_n("function_name")),
+                function_name: ValueNode::new(node.c_by_n("function_name")?)?,
                 value_exps: node
                     .children_vec()
                     .into_iter()
                     .skip(1)
-                    .map(|n| ValueExpression::new(n))
-                    .collect(),
+                    .map(ValueExpression::new)
+                    .collect::<Result<_, _>>()?,
             }
         };
 
-        function_expression
+        Ok(function_expression)
     }
 }
 
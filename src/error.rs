@@ -0,0 +1,235 @@
+use crate::diff::unified_diff;
+use std::fmt;
+use tree_sitter::{Node, Range};
+
+// Where in the source a `FormatError` happened.
+// `line` and `column` are 1-based, `column` counts bytes like tree-sitter does.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ErrorLocation {
+    pub file: Option<String>,
+    pub start_byte: usize,
+    pub end_byte: usize,
+    pub line: usize,
+    pub column: usize,
+    pub kind: String,
+}
+
+impl ErrorLocation {
+    pub fn from_node(node: &Node) -> Self {
+        Self::from_range(node.range(), node.kind())
+    }
+
+    pub fn from_range(range: Range, kind: &str) -> Self {
+        Self {
+            file: None,
+            start_byte: range.start_byte,
+            end_byte: range.end_byte,
+            line: range.start_point.row + 1,
+            column: range.start_point.column + 1,
+            kind: kind.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ErrorLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(ref file) = self.file {
+            write!(f, "{}:", file)?;
+        }
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+// An ERROR or MISSING node reported by tree-sitter.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyntaxError {
+    pub location: ErrorLocation,
+    pub message: String,
+    // kind of the node in which the error occurs
+    pub context: Option<String>,
+    // tokens the parser would have accepted at this position
+    pub expected: Vec<String>,
+    pub end_line: usize,
+    pub end_column: usize,
+    // the source lines from `location.line` to `end_line`
+    pub excerpt: Vec<String>,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: parse error: {}", self.location, self.message)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FormatError {
+    // all syntax errors of the file, in source order (never empty)
+    Parse {
+        errors: Vec<SyntaxError>,
+    },
+    // the node kind or shape is not handled by the formatter yet
+    UnsupportedNode {
+        location: ErrorLocation,
+        message: String,
+    },
+    // comments which would be erased from the output; `location` is the first of them
+    DroppedComments {
+        location: ErrorLocation,
+        comments: Vec<String>,
+    },
+    // the formatted code doesn't parse to the same tree as the source (verify mode);
+    // `location` is the first diverging node of the source
+    NotEquivalent {
+        location: ErrorLocation,
+        message: String,
+    },
+    // formatting the formatted code again changes it (verify idempotent mode)
+    NotIdempotent {
+        file: Option<String>,
+        // the result of the first and of the second pass
+        first: String,
+        second: String,
+    },
+    Io {
+        file: String,
+        message: String,
+    },
+    // the `.afmt.toml` which applies to the file can't be read or parsed
+    Config {
+        file: String,
+        message: String,
+    },
+    // any other failure inside the formatter
+    Internal {
+        file: Option<String>,
+        message: String,
+    },
+}
+
+impl FormatError {
+    pub fn location(&self) -> Option<&ErrorLocation> {
+        match self {
+            Self::Parse { errors } => errors.first().map(|e| &e.location),
+            Self::UnsupportedNode { location, .. }
+            | Self::DroppedComments { location, .. }
+            | Self::NotEquivalent { location, .. } => Some(location),
+            Self::NotIdempotent { .. }
+            | Self::Io { .. }
+            | Self::Config { .. }
+            | Self::Internal { .. } => None,
+        }
+    }
+
+    pub fn file(&self) -> Option<&str> {
+        match self {
+            Self::Parse { .. } => self.location().and_then(|l| l.file.as_deref()),
+            Self::UnsupportedNode { location, .. }
+            | Self::DroppedComments { location, .. }
+            | Self::NotEquivalent { location, .. } => location.file.as_deref(),
+            Self::Io { file, .. } | Self::Config { file, .. } => Some(file),
+            Self::NotIdempotent { file, .. } | Self::Internal { file, .. } => file.as_deref(),
+        }
+    }
+
+    // A stable name for the variant, used by the machine-readable reports.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Parse { .. } => "parse",
+            Self::UnsupportedNode { .. } => "unsupported_node",
+            Self::DroppedComments { .. } => "dropped_comments",
+            Self::NotEquivalent { .. } => "not_equivalent",
+            Self::NotIdempotent { .. } => "not_idempotent",
+            Self::Io { .. } => "io",
+            Self::Config { .. } => "config",
+            Self::Internal { .. } => "internal",
+        }
+    }
+
+    // The description of the error without its location.
+    pub fn message(&self) -> String {
+        match self {
+            Self::Parse { errors } => {
+                let messages: Vec<_> = errors.iter().map(|e| e.message.as_str()).collect();
+                messages.join("; ")
+            }
+            Self::UnsupportedNode { location, message } => {
+                format!("unsupported node `{}`: {}", location.kind, message)
+            }
+            Self::DroppedComments { comments, .. } => {
+                format!("{} comment(s) would be erased", comments.len())
+            }
+            Self::NotEquivalent { message, .. } => {
+                format!("formatting would change the code: {}", message)
+            }
+            Self::NotIdempotent { .. } => "formatting the result again changes it".to_string(),
+            Self::Io { message, .. } | Self::Config { message, .. } => message.clone(),
+            Self::Internal { message, .. } => format!("internal error: {}", message),
+        }
+    }
+
+    // Attach the path of the formatted file, `format_one()` only sees the source code.
+    pub fn with_file(mut self, path: &str) -> Self {
+        match self {
+            Self::Parse { ref mut errors } => {
+                for e in errors {
+                    e.location.file = Some(path.to_string());
+                }
+            }
+            Self::UnsupportedNode {
+                ref mut location, ..
+            }
+            | Self::DroppedComments {
+                ref mut location, ..
+            }
+            | Self::NotEquivalent {
+                ref mut location, ..
+            } => location.file = Some(path.to_string()),
+            Self::Io { ref mut file, .. } => *file = path.to_string(),
+            // `file` is the config file here, which is what needs fixing
+            Self::Config { .. } => {}
+            Self::NotIdempotent { ref mut file, .. } | Self::Internal { ref mut file, .. } => {
+                *file = Some(path.to_string())
+            }
+        }
+        self
+    }
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse { errors } => {
+                let lines: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+                write!(f, "{}", lines.join("\n"))
+            }
+            Self::UnsupportedNode { location, .. } | Self::NotEquivalent { location, .. } => {
+                write!(f, "{}: {}", location, self.message())
+            }
+            Self::DroppedComments { location, comments } => {
+                write!(f, "{}: {}", location, self.message())?;
+                for c in comments {
+                    write!(f, "\n  {}", c)?;
+                }
+                Ok(())
+            }
+            Self::NotIdempotent {
+                file,
+                first,
+                second,
+            } => {
+                let file = file.as_deref().unwrap_or("<source>");
+                writeln!(f, "{}: {}", file, self.message())?;
+                write!(f, "{}", unified_diff(file, first, second, false).trim_end())
+            }
+            Self::Io { file, message } | Self::Config { file, message } => {
+                write!(f, "{}: {}", file, message)
+            }
+            Self::Internal {
+                file: Some(file), ..
+            } => write!(f, "{}: {}", file, self.message()),
+            Self::Internal { file: None, .. } => write!(f, "{}", self.message()),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
@@ -0,0 +1,294 @@
+use crate::formatter::{is_project_root, Config};
+use crate::message_helper::{red, yellow};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const APEX_EXTENSIONS: [&str; 2] = ["cls", "trigger"];
+
+pub const IGNORE_FILE_NAME: &str = ".afmtignore";
+
+pub fn is_apex_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| APEX_EXTENSIONS.contains(&ext))
+}
+
+#[derive(Debug, Default)]
+pub struct SourceFiles {
+    pub files: Vec<String>,
+    // files left out by `.afmtignore` or the `include`/`exclude` config patterns
+    pub skipped: Vec<String>,
+}
+
+// Expand the paths given on the command line into Apex source files:
+// - a file is taken as-is
+// - a directory is walked recursively for `.cls` and `.trigger` files
+// - anything else is treated as a glob pattern, e.g. `force-app/**/classes/*.cls`
+//
+// Files found through a directory or a glob are skipped when they match a
+// `.afmtignore` file or the `include`/`exclude` patterns of their config.
+// `config_path` is the `--config` file, otherwise each file's config is discovered.
+pub fn collect_source_files(
+    paths: &[String],
+    config_path: Option<&str>,
+) -> Result<SourceFiles, String> {
+    let mut filter = FileFilter::new(config_path);
+    let mut result = SourceFiles::default();
+
+    for path in paths {
+        let p = Path::new(path);
+        if p.is_file() {
+            result.files.push(path.clone());
+        } else if p.is_dir() {
+            collect_from_dir(p, &mut filter, &mut result)?;
+        } else if is_glob_pattern(path) {
+            collect_from_glob(path, &mut filter, &mut result)?;
+        } else {
+            return Err(format!("{}: {}", yellow("Path not found"), red(path)));
+        }
+    }
+
+    // the same file can be reached through several inputs; keep the first one
+    let mut seen = HashSet::new();
+    result.files.retain(|f| seen.insert(f.clone()));
+
+    if result.files.is_empty() && result.skipped.is_empty() {
+        return Err(format!(
+            "{}: {}",
+            yellow("No Apex files found in"),
+            red(&paths.join(" "))
+        ));
+    }
+
+    Ok(result)
+}
+
+// Keep the Apex files among `files` listed by another tool, e.g. git, applying
+// `.afmtignore` and the `include`/`exclude` patterns like for a directory.
+pub fn filter_source_files(files: &[String], config_path: Option<&str>) -> SourceFiles {
+    let mut filter = FileFilter::new(config_path);
+    let mut result = SourceFiles::default();
+    for file in files {
+        let path = Path::new(file);
+        if is_apex_file(path) {
+            add_file(path, &mut filter, &mut result);
+        }
+    }
+    result
+}
+
+fn is_glob_pattern(path: &str) -> bool {
+    path.contains(['*', '?', '['])
+}
+
+fn collect_from_glob(
+    pattern: &str,
+    filter: &mut FileFilter,
+    result: &mut SourceFiles,
+) -> Result<(), String> {
+    let entries = glob::glob(pattern).map_err(|e| {
+        format!(
+            "{}: {} ({})",
+            yellow("Invalid glob pattern"),
+            red(pattern),
+            e
+        )
+    })?;
+
+    for entry in entries {
+        let path = entry.map_err(|e| format!("{}: {}", yellow("Failed to read path"), e))?;
+        if path.is_dir() {
+            add_dir(&path, filter, result)?;
+        } else if is_apex_file(&path) {
+            add_file(&path, filter, result);
+        }
+    }
+    Ok(())
+}
+
+fn collect_from_dir(
+    dir: &Path,
+    filter: &mut FileFilter,
+    result: &mut SourceFiles,
+) -> Result<(), String> {
+    let mut entries = fs::read_dir(dir)
+        .map_err(|e| {
+            format!(
+                "Failed to read directory: {} {}",
+                red(&dir.to_string_lossy()),
+                yellow(&e.to_string())
+            )
+        })?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .collect::<Vec<_>>();
+
+    // read_dir() order is platform dependent
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            // hidden directories such as `.git` or `.sfdx` never hold sources to format
+            let is_hidden = path
+                .file_name()
+                .is_some_and(|n| n.to_string_lossy().starts_with('.'));
+            if !is_hidden {
+                add_dir(&path, filter, result)?;
+            }
+        } else if is_apex_file(&path) {
+            add_file(&path, filter, result);
+        }
+    }
+    Ok(())
+}
+
+fn add_dir(path: &Path, filter: &mut FileFilter, result: &mut SourceFiles) -> Result<(), String> {
+    if filter.is_ignored(path, true) {
+        // an ignored directory is not walked, it is reported as a whole
+        result.skipped.push(format!("{}/", path.to_string_lossy()));
+        Ok(())
+    } else {
+        collect_from_dir(path, filter, result)
+    }
+}
+
+fn add_file(path: &Path, filter: &mut FileFilter, result: &mut SourceFiles) {
+    let name = path.to_string_lossy().to_string();
+    if filter.is_ignored(path, false) || !filter.is_included(path) {
+        result.skipped.push(name);
+    } else {
+        result.files.push(name);
+    }
+}
+
+// `include`/`exclude` patterns of one config, relative to its directory.
+struct ConfigPatterns {
+    root: PathBuf,
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl ConfigPatterns {
+    fn new(config: &Config, root: PathBuf) -> Self {
+        Self {
+            root,
+            include: (!config.include.is_empty()).then(|| build_glob_set(&config.include)),
+            exclude: build_glob_set(&config.exclude),
+        }
+    }
+
+    fn is_included(&self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return true;
+        };
+        self.include.as_ref().is_none_or(|i| i.is_match(relative))
+            && !self.exclude.is_match(relative)
+    }
+}
+
+// A pattern without `/` matches the file name in any directory, like in .gitignore.
+fn build_glob_set(patterns: &[String]) -> GlobSet {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let pattern = if pattern.contains('/') {
+            pattern.trim_start_matches("./").to_string()
+        } else {
+            format!("**/{}", pattern)
+        };
+        match GlobBuilder::new(&pattern).literal_separator(true).build() {
+            Ok(glob) => {
+                builder.add(glob);
+            }
+            Err(e) => eprintln!("{}: {}", yellow("Invalid include/exclude pattern"), e),
+        }
+    }
+    builder.build().unwrap_or_else(|_| GlobSet::empty())
+}
+
+struct FileFilter {
+    config_path: Option<PathBuf>,
+    // parsed `.afmtignore` per directory, `None` when the directory has none
+    ignore_files: HashMap<PathBuf, Option<Gitignore>>,
+    // patterns per config file, `None` when the config can't be loaded
+    config_patterns: HashMap<PathBuf, Option<ConfigPatterns>>,
+}
+
+impl FileFilter {
+    fn new(config_path: Option<&str>) -> Self {
+        Self {
+            config_path: config_path.map(absolute),
+            ignore_files: HashMap::new(),
+            config_patterns: HashMap::new(),
+        }
+    }
+
+    // Check the `.afmtignore` files from the directory of `path` up to the
+    // project root; the nearest file with a matching rule wins.
+    fn is_ignored(&mut self, path: &Path, is_dir: bool) -> bool {
+        let path = absolute(path);
+        let mut dir = path.parent();
+
+        while let Some(d) = dir {
+            let ignore_file = self
+                .ignore_files
+                .entry(d.to_path_buf())
+                .or_insert_with(|| load_ignore_file(d));
+
+            if let Some(gitignore) = ignore_file {
+                let m = gitignore.matched_path_or_any_parents(&path, is_dir);
+                if m.is_ignore() {
+                    return true;
+                }
+                if m.is_whitelist() {
+                    return false;
+                }
+            }
+
+            if is_project_root(d) {
+                break;
+            }
+            dir = d.parent();
+        }
+        false
+    }
+
+    fn is_included(&mut self, path: &Path) -> bool {
+        let path = absolute(path);
+        let config_path = match self.config_path {
+            Some(ref p) => p.clone(),
+            None => match Config::discover(&path) {
+                Some(p) => p,
+                None => return true,
+            },
+        };
+
+        self.config_patterns
+            .entry(config_path.clone())
+            .or_insert_with(|| {
+                let config = Config::from_file(&config_path.to_string_lossy()).ok()?;
+                let root = config_path.parent()?.to_path_buf();
+                Some(ConfigPatterns::new(&config, root))
+            })
+            .as_ref()
+            .is_none_or(|patterns| patterns.is_included(&path))
+    }
+}
+
+fn load_ignore_file(dir: &Path) -> Option<Gitignore> {
+    let file = dir.join(IGNORE_FILE_NAME);
+    if !file.is_file() {
+        return None;
+    }
+    let mut builder = GitignoreBuilder::new(dir);
+    if let Some(e) = builder.add(&file) {
+        eprintln!("{}: {}", yellow("Invalid .afmtignore rule"), e);
+    }
+    builder.build().ok()
+}
+
+fn absolute(path: impl AsRef<Path>) -> PathBuf {
+    let path = path.as_ref();
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
@@ -1,24 +1,68 @@
+use crate::accessor::Accessor;
+use crate::cache::Cache;
 use crate::context::CommentMap;
 use crate::data_model::*;
+use crate::diagnostic::collect_syntax_errors;
+use crate::doc::DocRef;
 use crate::doc::{pretty_print, PrettyConfig};
 use crate::doc_builder::DocBuilder;
-use crate::message_helper::{red, yellow};
+use crate::enum_def::{BodyMember, ClassMember, RootMember, Statement};
+use crate::error::{ErrorLocation, FormatError};
+use crate::message_helper::yellow;
+use crate::output::{convert_newlines, finish_output, newline};
 use crate::utility::{
-    assert_no_missing_comments, collect_comments, enrich, print_comment_map, set_thread_comment_map, set_thread_source_code
+    assert_no_missing_comments, assert_no_missing_comments_in, collect_comments,
+    collect_ignored_nodes, enrich, enter_format_context, format_context, is_punctuation_node,
+    set_comment_map, set_ignored_nodes,
 };
+use crate::verify::check_equivalent;
 use serde::Deserialize;
-use std::sync::mpsc;
-use std::thread;
-use std::{fs, path::Path};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{fs, thread};
 use tree_sitter::{Node, Parser, Tree};
 
-#[derive(Clone, Debug, Deserialize)]
+pub const CONFIG_FILE_NAME: &str = ".afmt.toml";
+
+// Files or directories marking the top of a project, config discovery stops there.
+const PROJECT_ROOT_MARKERS: [&str; 2] = [".git", "sfdx-project.json"];
+
+#[derive(Clone, Debug, Deserialize, Hash)]
 pub struct Config {
     #[serde(default = "default_max_width")]
     pub max_width: u32,
 
     #[serde(default = "default_indent_size")]
     pub indent_size: u32,
+
+    // indent with tabs, each counting as `tab_width` columns, instead of `indent_size` spaces
+    #[serde(default, alias = "hard_tabs")]
+    pub use_tabs: bool,
+
+    #[serde(default = "default_tab_width")]
+    pub tab_width: u32,
+
+    #[serde(default)]
+    pub indent_style: IndentStyle,
+
+    #[serde(default)]
+    pub newline_style: NewlineStyle,
+
+    // end the file with a line break, or with none when `false`
+    #[serde(default = "default_insert_final_newline")]
+    pub insert_final_newline: bool,
+
+    // glob patterns relative to the config file's directory, applied when
+    // the CLI expands directories and globs into files
+    #[serde(default)]
+    pub include: Vec<String>,
+
+    #[serde(default)]
+    pub exclude: Vec<String>,
 }
 
 fn default_max_width() -> u32 {
@@ -29,11 +73,55 @@ fn default_indent_size() -> u32 {
     2
 }
 
+fn default_tab_width() -> u32 {
+    4
+}
+
+fn default_insert_final_newline() -> bool {
+    true
+}
+
+// How the elements of a bracketed list, like arguments or parameters, are laid
+// out when they don't fit on one line.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Hash, PartialEq, Eq)]
+pub enum IndentStyle {
+    // on their own lines, one indent level deeper than the line of the bracket:
+    //     foo(
+    //       a,
+    //       b
+    //     )
+    #[default]
+    Block,
+    // after the opening bracket, the next lines aligned with the first element:
+    //     foo(a,
+    //         b)
+    Visual,
+}
+
+// The line endings of the formatted code.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Hash, PartialEq, Eq)]
+pub enum NewlineStyle {
+    // the ending most lines of the source have, `\n` if there are no more `\r\n`
+    #[default]
+    Auto,
+    Unix,
+    Windows,
+    // the ending of the platform afmt runs on
+    Native,
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
             max_width: default_max_width(),
             indent_size: default_indent_size(),
+            use_tabs: false,
+            tab_width: default_tab_width(),
+            indent_style: IndentStyle::default(),
+            newline_style: NewlineStyle::default(),
+            insert_final_newline: default_insert_final_newline(),
+            include: Vec::new(),
+            exclude: Vec::new(),
         }
     }
 }
@@ -43,15 +131,61 @@ impl Config {
         Self {
             max_width,
             indent_size: 2,
+            use_tabs: false,
+            tab_width: default_tab_width(),
+            indent_style: IndentStyle::default(),
+            newline_style: NewlineStyle::default(),
+            insert_final_newline: default_insert_final_newline(),
+            include: Vec::new(),
+            exclude: Vec::new(),
         }
     }
 
     pub fn from_file(path: &str) -> Result<Self, String> {
         let content =
             fs::read_to_string(path).map_err(|e| format!("Failed to read config file: {}", e))?;
+        Config::from_toml(&content)
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self, String> {
         let config: Config =
-            toml::from_str(&content).map_err(|e| format!("Failed to parse config file: {}", e))?;
-        Ok(config)
+            toml::from_str(content).map_err(|e| format!("Failed to parse config file: {}", e))?;
+        config.validate()
+    }
+
+    pub fn from_json(content: &str) -> Result<Self, String> {
+        let config: Config =
+            serde_json::from_str(content).map_err(|e| format!("Failed to parse config: {}", e))?;
+        config.validate()
+    }
+
+    pub fn validate(self) -> Result<Self, String> {
+        if self.indent_size == 0 {
+            return Err("indent_size must be greater than 0".to_string());
+        }
+        if self.tab_width == 0 {
+            return Err("tab_width must be greater than 0".to_string());
+        }
+        Ok(self)
+    }
+
+    // Find the nearest `.afmt.toml` by walking up from the directory of `source_file`.
+    // The search stops at a git repository or SFDX project root.
+    pub fn discover(source_file: &Path) -> Option<PathBuf> {
+        let path = fs::canonicalize(source_file).unwrap_or_else(|_| source_file.to_path_buf());
+        let mut dir = path.parent();
+
+        while let Some(d) = dir {
+            let candidate = d.join(CONFIG_FILE_NAME);
+            if candidate.is_file() {
+                return Some(candidate);
+            }
+            if is_project_root(d) {
+                return None;
+            }
+            dir = d.parent();
+        }
+        None
     }
 
     pub fn max_width(&self) -> u32 {
@@ -63,10 +197,29 @@ impl Config {
     }
 }
 
+// The outcome of formatting one of the `source_files`.
+#[derive(Debug)]
+pub struct FileResult {
+    pub path: String,
+    pub result: Result<String, FormatError>,
+    // time spent reading and formatting the file
+    pub elapsed: Duration,
+}
+
 #[derive(Clone, Debug)]
 pub struct Formatter {
     config: Config,
     source_files: Vec<String>,
+    // look up the nearest `.afmt.toml` of each file instead of using `config`
+    discover_config: bool,
+    // check every result with `Formatter::verify()`
+    verify: bool,
+    // check every result with `Formatter::verify_idempotent()`
+    verify_idempotent: bool,
+    // number of worker threads of `format()`, 0 for one per CPU
+    jobs: usize,
+    // skips the files known to be formatted, shared by the workers
+    cache: Option<Arc<Mutex<Cache>>>,
     //pub errors: ReportedErrors,
 }
 
@@ -75,6 +228,11 @@ impl Formatter {
         Self {
             config,
             source_files,
+            discover_config: false,
+            verify: false,
+            verify_idempotent: false,
+            jobs: 0,
+            cache: None,
             //errors: ReportedErrors::default(),
         }
     }
@@ -83,6 +241,37 @@ impl Formatter {
         &self.config
     }
 
+    pub fn set_verify(&mut self, verify: bool) {
+        self.verify = verify;
+    }
+
+    pub fn set_verify_idempotent(&mut self, verify_idempotent: bool) {
+        self.verify_idempotent = verify_idempotent;
+    }
+
+    pub fn set_jobs(&mut self, jobs: usize) {
+        self.jobs = jobs;
+    }
+
+    pub fn set_cache(&mut self, cache: Cache) {
+        self.cache = Some(Arc::new(Mutex::new(cache)));
+    }
+
+    // Write back the cache updated by `format()`.
+    pub fn save_cache(&self) -> Result<(), String> {
+        match self.cache {
+            Some(ref cache) => cache.lock().unwrap().save(),
+            None => Ok(()),
+        }
+    }
+
+    fn jobs(&self) -> usize {
+        if self.jobs > 0 {
+            return self.jobs;
+        }
+        thread::available_parallelism().map_or(1, |n| n.get())
+    }
+
     pub fn create_from_config(
         config_path: Option<&str>,
         source_files: Vec<String>,
@@ -92,126 +281,477 @@ impl Formatter {
                 .map_err(|e| format!("{}: {}", yellow(&e.to_string()), path))?,
             None => Config::default(),
         };
-        Ok(Formatter::new(config, source_files))
+        let mut formatter = Formatter::new(config, source_files);
+        formatter.discover_config = config_path.is_none();
+        Ok(formatter)
     }
 
-    pub fn format(&self) -> Vec<Result<String, String>> {
+    // The config to format `source_file` with: the explicit one, or the nearest
+    // `.afmt.toml` found by `Config::discover()`, or the default.
+    pub fn config_for(&self, source_file: &str) -> Result<Config, FormatError> {
+        if !self.discover_config {
+            return Ok(self.config.clone());
+        }
+        match Config::discover(Path::new(source_file)) {
+            Some(config_path) => load_config(&config_path),
+            None => Ok(self.config.clone()),
+        }
+    }
+
+    // Format the `source_files` on `jobs` worker threads, each reusing one parser.
+    // The results are in the order of `source_files`.
+    pub fn format(&self) -> Vec<FileResult> {
+        let configs = self.file_configs();
+        let jobs = self.jobs().min(self.source_files.len()).max(1);
+        let next_file = AtomicUsize::new(0);
         let (tx, rx) = mpsc::channel();
-        let config = self.config.clone();
-
-        for file in &self.source_files {
-            let tx = tx.clone();
-            let config = config.clone();
-            let file = file.clone();
-
-            thread::spawn(move || {
-                let result = std::panic::catch_unwind(|| {
-                    let source_code = fs::read_to_string(Path::new(&file))
-                        .map_err(|e| {
-                            format!(
-                                "Failed to read file: {} {}",
-                                red(&file),
-                                yellow(e.to_string().as_str())
-                            )
-                        })
-                        .unwrap();
-
-                    Formatter::format_one(&source_code, config)
+
+        thread::scope(|s| {
+            let (configs, next_file) = (&configs, &next_file);
+            let workers: Vec<_> = (0..jobs)
+                .map(|_| {
+                    let tx = tx.clone();
+                    s.spawn(move || {
+                        let mut parser = Formatter::new_parser();
+                        loop {
+                            let index = next_file.fetch_add(1, Ordering::Relaxed);
+                            let Some(file) = self.source_files.get(index) else {
+                                break;
+                            };
+                            let start = Instant::now();
+                            let result = self.format_file(&mut parser, file, &configs[index]);
+                            if tx.send((index, result, start.elapsed())).is_err() {
+                                break;
+                            }
+                        }
+                    })
+                })
+                .collect();
+
+            // a panicking worker only loses the file it was formatting, see below
+            for worker in workers {
+                let _ = worker.join();
+            }
+        });
+        drop(tx);
+
+        let mut results: Vec<Option<(Result<String, FormatError>, Duration)>> =
+            vec![None; self.source_files.len()];
+        for (index, result, elapsed) in rx {
+            results[index] = Some((result, elapsed));
+        }
+
+        self.source_files
+            .iter()
+            .zip(results)
+            .map(|(file, result)| {
+                let (result, elapsed) = result.unwrap_or_else(|| {
+                    let error = FormatError::Internal {
+                        file: Some(file.clone()),
+                        message: "Thread panicked".to_string(),
+                    };
+                    (Err(error), Duration::ZERO)
                 });
-                match result {
-                    Ok(result) => {
-                        tx.send(Ok(result)).expect("failed to send result in tx");
-                    }
-                    Err(_) => tx
-                        .send(Err("Thread panicked".to_string()))
-                        .expect("failed to send error in tx"),
+                FileResult {
+                    path: file.clone(),
+                    result,
+                    elapsed,
                 }
-            });
+            })
+            .collect()
+    }
+
+    // The config of each of the `source_files`, in the same order.
+    fn file_configs(&self) -> Vec<Result<Config, FormatError>> {
+        // files of the same package share one `.afmt.toml`, only read it once
+        let mut config_cache: HashMap<PathBuf, Result<Config, FormatError>> = HashMap::new();
+
+        self.source_files
+            .iter()
+            .map(|file| {
+                if !self.discover_config {
+                    return Ok(self.config.clone());
+                }
+                match Config::discover(Path::new(file)) {
+                    Some(config_path) => config_cache
+                        .entry(config_path.clone())
+                        .or_insert_with(|| load_config(&config_path))
+                        .clone(),
+                    None => Ok(self.config.clone()),
+                }
+            })
+            .collect()
+    }
+
+    // Format one file with the config which applies to it, reusing `parser`.
+    pub fn format_file_with(&self, parser: &mut Parser, file: &str) -> Result<String, FormatError> {
+        self.format_file(parser, file, &self.config_for(file))
+    }
+
+    fn format_file(
+        &self,
+        parser: &mut Parser,
+        file: &str,
+        config: &Result<Config, FormatError>,
+    ) -> Result<String, FormatError> {
+        let config = config.clone()?;
+        let source_code = fs::read_to_string(Path::new(file)).map_err(|e| FormatError::Io {
+            file: file.to_string(),
+            message: format!("Failed to read file: {}", e),
+        })?;
+
+        let Some(ref cache) = self.cache else {
+            return self.format_source(parser, file, &source_code, config);
+        };
+
+        let key = Cache::key(&source_code, &config);
+        if cache.lock().unwrap().is_formatted(file, key) {
+            return Ok(source_code);
         }
 
-        drop(tx);
+        let result = self.format_source(parser, file, &source_code, config);
+        // only an unchanged file is known to be formatted, a second pass may still change the result
+        match result {
+            Ok(ref formatted) if *formatted == source_code => {
+                cache.lock().unwrap().set_formatted(file, key)
+            }
+            _ => cache.lock().unwrap().remove(file),
+        }
+        result
+    }
+
+    fn format_source(
+        &self,
+        parser: &mut Parser,
+        file: &str,
+        source_code: &str,
+        config: Config,
+    ) -> Result<String, FormatError> {
+        let formatted = Formatter::format_one_with(parser, source_code, config.clone())
+            .map_err(|e| e.with_file(file))?;
+        if self.verify {
+            Formatter::verify_with(parser, source_code, &formatted)
+                .map_err(|e| e.with_file(file))?;
+        }
+        if self.verify_idempotent {
+            Formatter::verify_idempotent_with(parser, &formatted, config)
+                .map_err(|e| e.with_file(file))?;
+        }
+        Ok(formatted)
+    }
+
+    pub fn format_one(source_code: &str, config: Config) -> Result<String, FormatError> {
+        Formatter::format_one_with(&mut Formatter::new_parser(), source_code, config)
+    }
 
-        rx.into_iter().collect()
+    // Same as `format_one()` with a parser kept by the caller, e.g. for a whole editor session.
+    pub fn format_one_with(
+        parser: &mut Parser,
+        source_code: &str,
+        config: Config,
+    ) -> Result<String, FormatError> {
+        let ast_tree = Formatter::parse_with(parser, source_code)?;
+        Formatter::format_tree(source_code, &ast_tree, config)
     }
 
-    pub fn format_one(source_code: &str, config: Config) -> String {
-        let ast_tree = Formatter::parse(source_code);
-        set_thread_source_code(source_code.to_string()); // important to set thread level source code now;
+    fn format_tree(
+        source_code: &str,
+        ast_tree: &Tree,
+        config: Config,
+    ) -> Result<String, FormatError> {
+        // the source and comments are reachable from every node until the guard drops
+        let _context = enter_format_context(source_code);
 
         let mut cursor = ast_tree.walk();
         let mut comment_map = CommentMap::new();
-        collect_comments(&mut cursor, &mut comment_map);
-        set_thread_comment_map(comment_map); // important to set thread level comment map;
+        collect_comments(&mut cursor, &mut comment_map)?;
+        set_comment_map(comment_map);
+        set_ignored_nodes(collect_ignored_nodes(ast_tree));
 
         // traverse the tree to build enriched data
-        let root: Root = enrich(&ast_tree);
+        let root: Root = enrich(ast_tree)?;
 
         // traverse enriched data and create pretty print combinators
-        let c = PrettyConfig::new(config.indent_size);
+        let c = PrettyConfig::new(&config);
         let b = DocBuilder::new(c);
         let doc_ref = root.build(&b);
 
-        let result = pretty_print(doc_ref, config.max_width);
+        let result = pretty_print(doc_ref, c);
 
-        //print_comment_map(&ast_tree);
+        assert_no_missing_comments()?;
 
-        assert_no_missing_comments();
+        Ok(finish_output(source_code, &result, &config))
+    }
 
-        result
+    // Format only the statements or members overlapping `range` (byte offsets)
+    // at their current indentation, every byte around them is left untouched.
+    // Syntax errors elsewhere in the source don't prevent it.
+    pub fn format_range(
+        source_code: &str,
+        range: Range<usize>,
+        config: Config,
+    ) -> Result<String, FormatError> {
+        Formatter::format_range_with(&mut Formatter::new_parser(), source_code, range, config)
     }
 
-    pub fn parse(source_code: &str) -> Tree {
-        let mut parser = Parser::new();
-        let language_fn = tree_sitter_sfapex::apex::LANGUAGE;
-        parser
-            .set_language(&language_fn.into())
-            .expect("Error loading Apex parser");
+    pub fn format_range_with(
+        parser: &mut Parser,
+        source_code: &str,
+        range: Range<usize>,
+        config: Config,
+    ) -> Result<String, FormatError> {
+        let ast_tree = Formatter::parse_tree(parser, source_code);
+        let end = range.end.min(source_code.len());
+        let range = range.start.min(end)..end;
+
+        let Some((body, members)) = range_members(&ast_tree, &range) else {
+            return Ok(source_code.to_string());
+        };
+        if members.is_empty() {
+            return Ok(source_code.to_string());
+        }
+        if members.iter().any(|n| n.has_error()) {
+            return Err(FormatError::Parse {
+                errors: collect_syntax_errors(&ast_tree, source_code),
+            });
+        }
 
-        let ast_tree = parser.parse(source_code, None).unwrap();
-        let root_node = &ast_tree.root_node();
+        Formatter::format_members(source_code, &ast_tree, body.kind(), &members, config)
+    }
 
-        if root_node.has_error() {
-            if let Some(error_node) = Self::find_last_error_node(root_node) {
-                let error_snippet = &source_code[error_node.start_byte()..error_node.end_byte()];
-                println!(
-                    "Error in node kind: {}, at byte range: {}-{}, snippet: {}",
-                    yellow(error_node.kind()),
-                    error_node.start_byte(),
-                    error_node.end_byte(),
-                    error_snippet,
-                );
-                if let Some(p) = error_node.parent() {
-                    let parent_snippet = &source_code[p.start_byte()..p.end_byte()];
-                    println!(
-                        "Parent node kind: {}, at byte range: {}-{}, snippet: {}",
-                        yellow(p.kind()),
-                        p.start_byte(),
-                        p.end_byte(),
-                        parent_snippet,
-                    );
-                }
+    fn format_members(
+        source_code: &str,
+        ast_tree: &Tree,
+        body_kind: &str,
+        members: &[Node],
+        config: Config,
+    ) -> Result<String, FormatError> {
+        let _context = enter_format_context(source_code);
+
+        let mut cursor = ast_tree.walk();
+        let mut comment_map = CommentMap::new();
+        collect_comments(&mut cursor, &mut comment_map)?;
+        set_comment_map(comment_map);
+        set_ignored_nodes(collect_ignored_nodes(ast_tree));
+
+        let c = PrettyConfig::new(&config);
+        let b = DocBuilder::new(c);
+        let doc_ref = match body_kind {
+            "parser_output" => build_members(&b, members, RootMember::new)?,
+            "class_body" => build_members(&b, members, ClassMember::new)?,
+            "interface_body" => build_members(&b, members, InterfaceMember::new)?,
+            _ => build_members(&b, members, Statement::new)?,
+        };
+
+        // the replaced text grows to the comments and punctuation printed with the members
+        let first = members[0];
+        let last = members[members.len() - 1];
+        let mut span = first.start_byte()..trailing_punctuation_end(&last);
+        for comment in format_context()
+            .comment_map()
+            .values()
+            .flat_map(|bucket| {
+                bucket
+                    .pre_comments
+                    .iter()
+                    .chain(bucket.post_comments.iter())
+                    .chain(bucket.dangling_comments.iter())
+            })
+            .filter(|c| c.is_printed())
+        {
+            span.start = span.start.min(comment.range.start_byte);
+            span.end = span.end.max(comment.range.end_byte);
+        }
+        assert_no_missing_comments_in(&span)?;
+
+        // the printer starts at column 0, so print the text in front of the
+        // members on their first line too and cut it off afterwards
+        let line_start = source_code[..span.start].rfind('\n').map_or(0, |i| i + 1);
+        let prefix = &source_code[line_start..span.start];
+        let indent = prefix
+            .chars()
+            .take_while(|c| c.is_whitespace())
+            .map(|c| if c == '\t' { config.tab_width } else { 1 })
+            .sum();
+        let doc_ref = b.concat(vec![b.txt(prefix), b.indent_by(indent, doc_ref)]);
+        let formatted = pretty_print(doc_ref, c);
+        // the text around the members keeps its line endings, the members get the same
+        let newline = newline(source_code, config.newline_style);
+
+        let mut result = String::with_capacity(source_code.len());
+        result.push_str(&source_code[..span.start]);
+        result.push_str(&convert_newlines(
+            formatted[prefix.len()..].trim_end(),
+            newline,
+        ));
+        result.push_str(&source_code[span.end..]);
+        Ok(result)
+    }
+
+    // Check that `formatted` parses to the same tree as `source_code`, apart from
+    // whitespace and the case the formatter normalizes. The error points to the
+    // first node of the source which differs.
+    pub fn verify(source_code: &str, formatted: &str) -> Result<(), FormatError> {
+        Formatter::verify_with(&mut Formatter::new_parser(), source_code, formatted)
+    }
+
+    pub fn verify_with(
+        parser: &mut Parser,
+        source_code: &str,
+        formatted: &str,
+    ) -> Result<(), FormatError> {
+        let source_tree = Formatter::parse_with(parser, source_code)?;
+        let formatted_tree = match Formatter::parse_with(parser, formatted) {
+            Ok(tree) => tree,
+            Err(FormatError::Parse { errors }) => {
+                let first = &errors[0];
+                return Err(FormatError::NotEquivalent {
+                    location: ErrorLocation::from_range(
+                        source_tree.root_node().range(),
+                        "parser_output",
+                    ),
+                    message: format!(
+                        "the formatted code doesn't parse: {} at {}:{}",
+                        first.message, first.location.line, first.location.column
+                    ),
+                });
             }
-            panic!("{}", red("Parser encounters an error node in the tree."));
+            Err(e) => return Err(e),
+        };
+        check_equivalent(&source_tree, source_code, &formatted_tree, formatted)
+    }
+
+    // Check that formatting `formatted`, the result of a first pass, leaves it unchanged.
+    pub fn verify_idempotent(formatted: &str, config: Config) -> Result<(), FormatError> {
+        Formatter::verify_idempotent_with(&mut Formatter::new_parser(), formatted, config)
+    }
+
+    pub fn verify_idempotent_with(
+        parser: &mut Parser,
+        formatted: &str,
+        config: Config,
+    ) -> Result<(), FormatError> {
+        let second = Formatter::format_one_with(parser, formatted, config)?;
+
+        if second == formatted {
+            Ok(())
+        } else {
+            Err(FormatError::NotIdempotent {
+                file: None,
+                first: formatted.to_string(),
+                second,
+            })
         }
+    }
 
-        ast_tree
+    pub fn parse(source_code: &str) -> Result<Tree, FormatError> {
+        Formatter::parse_with(&mut Formatter::new_parser(), source_code)
     }
 
-    fn find_last_error_node<'tree>(node: &Node<'tree>) -> Option<Node<'tree>> {
-        if !node.has_error() {
-            return None; // If the current node has no error, return None
+    pub fn parse_with(parser: &mut Parser, source_code: &str) -> Result<Tree, FormatError> {
+        let ast_tree = Formatter::parse_tree(parser, source_code);
+        let root_node = &ast_tree.root_node();
+
+        if root_node.has_error() {
+            return Err(FormatError::Parse {
+                errors: collect_syntax_errors(&ast_tree, source_code),
+            });
         }
 
-        let mut last_error_node = Some(*node);
+        Ok(ast_tree)
+    }
 
-        for i in 0..node.child_count() {
-            if let Some(child) = node.child(i) {
-                if child.has_error() {
-                    last_error_node = Self::find_last_error_node(&child);
-                }
+    // A parser for Apex, it can be reused for any number of sources.
+    pub fn new_parser() -> Parser {
+        let mut parser = Parser::new();
+        let language_fn = tree_sitter_sfapex::apex::LANGUAGE;
+        parser
+            .set_language(&language_fn.into())
+            .expect("Error loading Apex parser");
+        parser
+    }
+
+    fn parse_tree(parser: &mut Parser, source_code: &str) -> Tree {
+        parser.parse(source_code, None).unwrap()
+    }
+}
+
+// Nodes whose children are formatted as a list of statements or members.
+const BODY_KINDS: [&str; 5] = [
+    "parser_output",
+    "class_body",
+    "interface_body",
+    "block",
+    "constructor_body",
+];
+
+// The innermost body around `range` and its statements or members overlapping it.
+fn range_members<'t>(
+    ast_tree: &'t Tree,
+    range: &Range<usize>,
+) -> Option<(Node<'t>, Vec<Node<'t>>)> {
+    let mut current = ast_tree
+        .root_node()
+        .descendant_for_byte_range(range.start, range.end)?;
+
+    // an empty range still selects the member around it
+    let end = range.end.max(range.start + 1);
+    loop {
+        if BODY_KINDS.contains(&current.kind()) {
+            let members: Vec<_> = current
+                .children_vec()
+                .into_iter()
+                .filter(|n| n.start_byte() < end && n.end_byte() > range.start)
+                .collect();
+
+            // `this(...)`/`super(...)` only exist in the whole constructor body
+            if !members
+                .iter()
+                .any(|n| n.kind() == "explicit_constructor_invocation")
+            {
+                return Some((current, members));
             }
         }
+        current = current.parent()?;
+    }
+}
 
-        last_error_node // Return the last (deepest) error node
+fn build_members<'a, M: DocBuild<'a>>(
+    b: &'a DocBuilder<'a>,
+    nodes: &[Node],
+    new: impl Fn(Node) -> Result<M, FormatError>,
+) -> Result<DocRef<'a>, FormatError> {
+    let members = nodes
+        .iter()
+        .map(|n| new(*n).map(|member| BodyMember::new(n, member)))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(b.intersperse_body_members(&members))
+}
+
+// A `,` or `;` after the node is printed with it, see `NodeInfo::with_punctuation()`.
+fn trailing_punctuation_end(node: &Node) -> usize {
+    let mut current = *node;
+    while let Some(next) = current.next_sibling() {
+        if next.is_extra() {
+            current = next;
+            continue;
+        }
+        if is_punctuation_node(&next) {
+            return next.end_byte();
+        }
+        break;
     }
+    node.end_byte()
+}
+
+pub(crate) fn is_project_root(dir: &Path) -> bool {
+    PROJECT_ROOT_MARKERS.iter().any(|m| dir.join(m).exists())
+}
+
+fn load_config(config_path: &Path) -> Result<Config, FormatError> {
+    let path = config_path.to_string_lossy().to_string();
+    Config::from_file(&path).map_err(|message| FormatError::Config {
+        file: path,
+        message,
+    })
 }
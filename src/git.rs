@@ -0,0 +1,160 @@
+use crate::message_helper::{red, yellow};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+// The git repository of the current directory, queried with the local `git`
+// executable: only the index and the object database are read, never a remote.
+pub struct Repo {
+    root: PathBuf,
+}
+
+// A file in the index. `path` is relative to the repository root, as git prints it.
+pub struct StagedFile {
+    pub path: String,
+    mode: String,
+}
+
+impl Repo {
+    pub fn discover() -> Result<Self, String> {
+        let root = git(None, &["rev-parse", "--show-toplevel"], None)?;
+        Ok(Self {
+            root: PathBuf::from(root.trim_end()),
+        })
+    }
+
+    // The path of a repository file from the current directory, as the user would type it.
+    pub fn display_path(&self, path: &str) -> String {
+        let full_path = self.root.join(path);
+        std::env::current_dir()
+            .ok()
+            .and_then(|cwd| full_path.strip_prefix(cwd).ok().map(Path::to_path_buf))
+            .unwrap_or(full_path)
+            .to_string_lossy()
+            .to_string()
+    }
+
+    // Added, copied, modified or renamed files in the index, limited to `pathspecs` if any.
+    pub fn staged_files(&self, pathspecs: &[String]) -> Result<Vec<StagedFile>, String> {
+        let mut args = vec![
+            "diff",
+            "--cached",
+            "--name-only",
+            "--diff-filter=ACMR",
+            "-z",
+        ];
+        add_pathspecs(&mut args, pathspecs);
+        let paths = git(None, &args, None)?;
+
+        split_nul(&paths)
+            .into_iter()
+            .map(|path| {
+                let pathspec = format!(":(literal){}", path);
+                let entry = git(
+                    Some(&self.root),
+                    &["ls-files", "-s", "-z", "--", &pathspec],
+                    None,
+                )?;
+                // <mode> SP <object> SP <stage> TAB <path>
+                let mode = entry.split(' ').next().unwrap_or("100644").to_string();
+                Ok(StagedFile { path, mode })
+            })
+            .collect()
+    }
+
+    // Files changed between `rev` and the working tree, plus untracked files.
+    pub fn changed_files(&self, rev: &str, pathspecs: &[String]) -> Result<Vec<String>, String> {
+        let mut args = vec!["diff", "--name-only", "--diff-filter=ACMR", "-z", rev];
+        add_pathspecs(&mut args, pathspecs);
+        let mut paths = split_nul(&git(None, &args, None)?);
+
+        let mut args = vec![
+            "ls-files",
+            "--others",
+            "--exclude-standard",
+            "--full-name",
+            "-z",
+        ];
+        add_pathspecs(&mut args, pathspecs);
+        paths.extend(split_nul(&git(None, &args, None)?));
+        Ok(paths)
+    }
+
+    pub fn staged_content(&self, file: &StagedFile) -> Result<String, String> {
+        git(
+            Some(&self.root),
+            &["cat-file", "blob", &format!(":{}", file.path)],
+            None,
+        )
+    }
+
+    // Replace the content of `file` in the index, the working tree is left alone.
+    pub fn stage_content(&self, file: &StagedFile, content: &str) -> Result<(), String> {
+        let object = git(
+            Some(&self.root),
+            &["hash-object", "-w", "--stdin", "--no-filters"],
+            Some(content),
+        )?;
+        let cache_info = format!("{},{},{}", file.mode, object.trim_end(), file.path);
+        git(
+            Some(&self.root),
+            &["update-index", "--cacheinfo", &cache_info],
+            None,
+        )
+        .map(|_| ())
+    }
+
+    pub fn worktree_path(&self, file: &StagedFile) -> PathBuf {
+        self.root.join(&file.path)
+    }
+}
+
+fn add_pathspecs<'a>(args: &mut Vec<&'a str>, pathspecs: &'a [String]) {
+    args.push("--");
+    args.extend(pathspecs.iter().map(String::as_str));
+}
+
+fn split_nul(output: &str) -> Vec<String> {
+    output
+        .split('\0')
+        .filter(|p| !p.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn git(dir: Option<&Path>, args: &[&str], stdin: Option<&str>) -> Result<String, String> {
+    let mut command = Command::new("git");
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+    command
+        .args(args)
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let mut child = command
+        .spawn()
+        .map_err(|e| format!("{}: {}", yellow("Failed to run git"), e))?;
+    if let (Some(input), Some(mut pipe)) = (stdin, child.stdin.take()) {
+        pipe.write_all(input.as_bytes())
+            .map_err(|e| format!("{}: {}", yellow("Failed to write to git"), e))?;
+    }
+
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("{}: {}", yellow("Failed to run git"), e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} {}: {}",
+            yellow("git"),
+            args.join(" "),
+            red(String::from_utf8_lossy(&output.stderr).trim())
+        ));
+    }
+    String::from_utf8(output.stdout).map_err(|e| e.to_string())
+}
@@ -1,28 +1,32 @@
 mod accessor;
 pub mod args;
+pub mod cache;
 mod context;
 mod data_model;
+pub mod diagnostic;
+pub mod diff;
 mod doc;
 mod doc_builder;
 mod enum_def;
+pub mod error;
+pub mod files;
 pub mod formatter;
+pub mod git;
 pub mod message_helper;
+mod output;
+pub mod position;
+pub mod report;
 mod utility;
-use formatter::Config;
-use formatter::Formatter;
+mod verify;
+pub mod wasm;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod watch;
+use formatter::{FileResult, Formatter};
 
-use wasm_bindgen::prelude::*;
-
-pub fn format(f: Formatter) -> Vec<Result<String, String>> {
+pub fn format(f: Formatter) -> Vec<FileResult> {
     f.format()
 }
 
-#[wasm_bindgen]
-pub fn greet(source_code: &str) -> String {
-    let config = Config::default();
-    Formatter::format_one(source_code, config)
-}
-
 //#[wasm_bindgen]
 //pub fn greet(source_code: &str) -> String {
 //    "hello".to_string()
@@ -1,53 +1,485 @@
-use afmt::args::{get_args, Args};
-use afmt::format;
-use afmt::formatter::Formatter;
-use log::error;
+use afmt::args::{get_args, Args, GitSelection, OutputFormat};
+use afmt::cache::Cache;
+use afmt::diagnostic::render;
+use afmt::diff::unified_diff;
+use afmt::error::FormatError;
+use afmt::files::{collect_source_files, filter_source_files};
+use afmt::formatter::{Config, FileResult, Formatter};
+use afmt::git::Repo;
+use afmt::message_helper::{paint, Style};
+use afmt::report::Report;
+use afmt::watch::watch;
 use log::info;
+use std::io::{self, Read};
+use std::path::Path;
 use std::time::Instant;
 use std::{fs, process};
 
+const EXIT_SUCCESS: i32 = 0;
+const EXIT_UNFORMATTED: i32 = 1; // `--check`/`--diff` found files that would be reformatted
+const EXIT_ERROR: i32 = 2; // parse failures, I/O errors, bad arguments
+
 fn main() {
     let start = Instant::now();
     info!("starting up");
 
-    let result = run(get_args());
+    let args = get_args();
+
+    // stdout carries only the formatted code in stdin mode
+    if args.stdin {
+        match run_stdin(&args) {
+            Ok(exit_code) => process::exit(exit_code),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                process::exit(EXIT_ERROR);
+            }
+        }
+    }
+
+    // stdout only carries the report otherwise
+    let text_output = args.output_format == OutputFormat::Text;
+    let diff = args.diff;
+    let result = run(args);
 
     match result {
-        Ok(_) => {
-            println!("Afmt completed successfully.");
-            let duration = start.elapsed();
-            println!("\nExecution time: {:?}", duration);
-            process::exit(0);
+        Ok(exit_code) => {
+            if text_output {
+                if exit_code == EXIT_SUCCESS {
+                    print_status(diff, "Afmt completed successfully.");
+                }
+                let duration = start.elapsed();
+                print_status(diff, &format!("\nExecution time: {:?}", duration));
+            }
+            process::exit(exit_code);
         }
         Err(e) => {
-            error!("Error: {}", e);
-            process::exit(1);
+            eprintln!("Error: {}", e);
+            process::exit(EXIT_ERROR);
         }
     }
 }
 
-fn run(args: Args) -> Result<(), String> {
-    let formatter = Formatter::create_from_config(args.config.as_deref(), vec![args.path.clone()])?;
-    let results = format(formatter);
+fn run(args: Args) -> Result<i32, String> {
+    if args.watch {
+        return run_watch(&args);
+    }
 
-    for (index, result) in results.iter().enumerate() {
-        match result {
-            Ok(value) => {
-                if args.write {
-                    fs::write(&args.path, value).map_err(|e| {
-                        format!("Failed to write formatted content to {}: {}", args.path, e)
-                    })?;
-                    println!("Formatted content written back to: {}\n", args.path);
-                } else {
-                    println!("Result {}: Ok\n{}", index, value);
+    let collected = match args.git {
+        Some(GitSelection::Staged) => return run_staged(&args),
+        Some(GitSelection::ChangedSince(ref rev)) => {
+            let repo = Repo::discover()?;
+            let files: Vec<_> = repo
+                .changed_files(rev, &args.paths)?
+                .iter()
+                .map(|path| repo.display_path(path))
+                .collect();
+            filter_source_files(&files, args.config.as_deref())
+        }
+        None => collect_source_files(&args.paths, args.config.as_deref())?,
+    };
+    if args.verbose && args.output_format == OutputFormat::Text {
+        for path in &collected.skipped {
+            print_status(
+                args.diff,
+                &format!(
+                    "{}: {}",
+                    paint("Skipped", Style::Yellow, status_color(&args)),
+                    path
+                ),
+            );
+        }
+    }
+
+    let source_files = collected.files;
+    if args.range.is_some() && source_files.len() != 1 {
+        return Err("--lines and --range need exactly one file".to_string());
+    }
+
+    let mut report =
+        (args.output_format != OutputFormat::Text).then(|| Report::new(args.include_formatted));
+    let mut formatter =
+        Formatter::create_from_config(args.config.as_deref(), source_files.clone())?;
+    formatter.set_verify(args.verify);
+    formatter.set_verify_idempotent(args.verify_idempotent);
+    formatter.set_jobs(args.jobs);
+    if let Some(ref cache_file) = args.cache {
+        formatter.set_cache(Cache::load(Path::new(cache_file)));
+    }
+    let results = match args.range {
+        Some(_) => {
+            let start = Instant::now();
+            let result = format_file_range(&formatter, &source_files[0], &args);
+            vec![FileResult {
+                path: source_files[0].clone(),
+                result,
+                elapsed: start.elapsed(),
+            }]
+        }
+        None => formatter.format(),
+    };
+    formatter.save_cache()?;
+
+    let mut unformatted = 0;
+    let mut errors = 0;
+
+    for (index, file) in results.iter().enumerate() {
+        let path = &file.path;
+        let value = match file.result {
+            Ok(ref value) => value,
+            Err(ref e) => {
+                match report {
+                    Some(ref mut report) => {
+                        let source_code = fs::read_to_string(path).unwrap_or_default();
+                        report.add_error(path, &source_code, e, file.elapsed)
+                    }
+                    None => report_error(e, args.color_stderr),
                 }
+                errors += 1;
+                continue;
+            }
+        };
+
+        let original =
+            fs::read_to_string(path).map_err(|e| format!("Failed to read file {}: {}", path, e))?;
+        let changed = original != *value;
+        if changed && (args.check || args.diff) {
+            unformatted += 1;
+        }
+        // leave formatted files untouched, e.g. for tools watching their mtime
+        if changed && args.write {
+            fs::write(path, value)
+                .map_err(|e| format!("Failed to write formatted content to {}: {}", path, e))?;
+            if report.is_none() {
+                print_status(
+                    args.diff,
+                    &format!("Formatted content written back to: {}\n", path),
+                );
+            }
+        }
+
+        match report {
+            Some(ref mut report) => report.add_formatted(path, &original, value, file.elapsed),
+            None if (args.check || args.diff) && changed => {
+                print_check(path, &original, value, &args)
             }
+            None if args.check || args.diff || args.write => {}
+            None => println!("Result {}: Ok\n{}", index, value),
+        }
+    }
+
+    Ok(finish(
+        &args,
+        source_files.len(),
+        unformatted,
+        errors,
+        report,
+    ))
+}
+
+// Format the staged content of the staged Apex files. With `--write` the result
+// is staged, and written to the working tree too unless the file has unstaged
+// changes: a partially staged file keeps them as they are.
+fn run_staged(args: &Args) -> Result<i32, String> {
+    let repo = Repo::discover()?;
+    let staged = repo.staged_files(&args.paths)?;
+    let paths: Vec<_> = staged.iter().map(|f| repo.display_path(&f.path)).collect();
+
+    let selected = filter_source_files(&paths, args.config.as_deref());
+    if args.verbose && args.output_format == OutputFormat::Text {
+        for path in &selected.skipped {
+            print_status(
+                args.diff,
+                &format!(
+                    "{}: {}",
+                    paint("Skipped", Style::Yellow, status_color(args)),
+                    path
+                ),
+            );
+        }
+    }
+
+    let formatter = Formatter::create_from_config(args.config.as_deref(), vec![])?;
+    let mut report =
+        (args.output_format != OutputFormat::Text).then(|| Report::new(args.include_formatted));
+    let mut unformatted = 0;
+    let mut errors = 0;
+
+    for (index, (file, path)) in staged.iter().zip(&paths).enumerate() {
+        if !selected.files.contains(path) {
+            continue;
+        }
+
+        let start = Instant::now();
+        let result = repo
+            .staged_content(file)
+            .map_err(|message| FormatError::Io {
+                file: path.clone(),
+                message,
+            })
+            .and_then(|original| {
+                let config = formatter.config_for(path)?;
+                let value = format_code(&original, config, args).map_err(|e| e.with_file(path))?;
+                Ok((original, value))
+            });
+
+        let (original, value) = match result {
+            Ok(result) => result,
             Err(e) => {
-                //println!("Result {}: Err\n{}", index, e);
-                return Err(format!("Error processing result {}: {}", index, e));
+                match report {
+                    Some(ref mut report) => {
+                        let source_code = repo.staged_content(file).unwrap_or_default();
+                        report.add_error(path, &source_code, &e, start.elapsed())
+                    }
+                    None => report_error(&e, args.color_stderr),
+                }
+                errors += 1;
+                continue;
+            }
+        };
+
+        let changed = original != value;
+        if changed && (args.check || args.diff) {
+            unformatted += 1;
+        }
+        if changed && args.write {
+            repo.stage_content(file, &value)?;
+
+            let worktree_path = repo.worktree_path(file);
+            let note = if fs::read_to_string(&worktree_path).is_ok_and(|c| c == original) {
+                fs::write(&worktree_path, &value)
+                    .map_err(|e| format!("Failed to write formatted content to {}: {}", path, e))?;
+                ""
+            } else {
+                " (unstaged changes left as they are)"
+            };
+            if report.is_none() {
+                print_status(
+                    args.diff,
+                    &format!("Formatted and staged: {}{}", path, note),
+                );
             }
         }
+
+        match report {
+            Some(ref mut report) => report.add_formatted(path, &original, &value, start.elapsed()),
+            None if (args.check || args.diff) && changed => {
+                print_check(path, &original, &value, args)
+            }
+            None if args.check || args.diff || args.write => {}
+            None => println!("Result {}: Ok\n{}", index, value),
+        }
     }
 
-    Ok(())
+    Ok(finish(
+        args,
+        selected.files.len(),
+        unformatted,
+        errors,
+        report,
+    ))
+}
+
+fn run_watch(args: &Args) -> Result<i32, String> {
+    let mut formatter = Formatter::create_from_config(args.config.as_deref(), vec![])?;
+    formatter.set_verify(args.verify);
+    formatter.set_verify_idempotent(args.verify_idempotent);
+
+    println!(
+        "Watching {} for changes, press Ctrl-C to stop",
+        args.paths.join(" ")
+    );
+    watch(&formatter, &args.paths, args.config.as_deref(), |event| {
+        match event.result {
+            Ok(true) => println!(
+                "{}: {} ({:?})",
+                paint("Formatted", Style::Green, args.color),
+                event.path,
+                event.elapsed
+            ),
+            Ok(false) => println!("Already formatted: {}", event.path),
+            // one line per file, the first error is enough to find the others
+            Err(e) => eprintln!(
+                "{}: {}",
+                paint("error", Style::Red, args.color_stderr),
+                e.to_string().lines().next().unwrap_or_default()
+            ),
+        }
+    })?;
+    Ok(EXIT_SUCCESS)
+}
+
+// Print the JSON report or the `--check`/`--diff` summary, and return the exit code.
+fn finish(
+    args: &Args,
+    checked: usize,
+    unformatted: usize,
+    errors: usize,
+    report: Option<Report>,
+) -> i32 {
+    if let Some(mut report) = report {
+        match args.output_format {
+            OutputFormat::Sarif => println!("{}", report.to_sarif()),
+            OutputFormat::Checkstyle => print!("{}", report.to_checkstyle()),
+            _ => println!("{}", report.to_json()),
+        }
+    } else if args.check || args.diff {
+        print_status(
+            args.diff,
+            &format!(
+                "{} file(s) checked, {} would be reformatted, {} failed",
+                checked, unformatted, errors
+            ),
+        );
+    }
+
+    if errors > 0 {
+        EXIT_ERROR
+    } else if unformatted > 0 {
+        EXIT_UNFORMATTED
+    } else {
+        EXIT_SUCCESS
+    }
+}
+
+fn run_stdin(args: &Args) -> Result<i32, String> {
+    let mut source_code = String::new();
+    io::stdin()
+        .read_to_string(&mut source_code)
+        .map_err(|e| format!("Failed to read from stdin: {}", e))?;
+
+    let formatter = Formatter::create_from_config(args.config.as_deref(), vec![])?;
+    let path = args.stdin_filepath.as_deref().unwrap_or("<stdin>");
+
+    let config = match args.stdin_filepath {
+        Some(ref p) => formatter.config_for(p),
+        None => Ok(formatter.config().clone()),
+    };
+
+    let start = Instant::now();
+    let result = config.and_then(|config| format_code(&source_code, config, args));
+
+    if args.output_format != OutputFormat::Text {
+        let mut report = Report::new(args.include_formatted);
+        let (unformatted, errors) = match result {
+            Ok(ref value) => {
+                report.add_formatted(path, &source_code, value, start.elapsed());
+                let unformatted = (args.check || args.diff) && source_code != *value;
+                (usize::from(unformatted), 0)
+            }
+            Err(ref e) => {
+                report.add_error(path, &source_code, e, start.elapsed());
+                (0, 1)
+            }
+        };
+        return Ok(finish(args, 1, unformatted, errors, Some(report)));
+    }
+
+    match result {
+        Ok(value) => {
+            if !(args.check || args.diff) {
+                print!("{}", value);
+                return Ok(EXIT_SUCCESS);
+            }
+
+            if source_code == value {
+                return Ok(EXIT_SUCCESS);
+            }
+            print_check(path, &source_code, &value, args);
+            Ok(EXIT_UNFORMATTED)
+        }
+        Err(e) => {
+            report_error(&e.with_file(path), args.color_stderr);
+            Ok(EXIT_ERROR)
+        }
+    }
+}
+
+// Print a line about the run rather than its result. With `--diff` it goes to
+// stderr, so that stdout is only the patch, e.g. redirected to a file.
+fn print_status(diff: bool, line: &str) {
+    if diff {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
+
+// Whether `print_status()` may use colors.
+fn status_color(args: &Args) -> bool {
+    if args.diff {
+        args.color_stderr
+    } else {
+        args.color
+    }
+}
+
+// Show a file which `--check` or `--diff` would reformat.
+fn print_check(path: &str, original: &str, formatted: &str, args: &Args) {
+    if args.diff {
+        print!("{}", unified_diff(path, original, formatted, args.color));
+    } else {
+        println!(
+            "{}: {}",
+            paint("Would reformat", Style::Yellow, args.color),
+            path
+        );
+    }
+}
+
+fn format_file_range(
+    formatter: &Formatter,
+    path: &str,
+    args: &Args,
+) -> Result<String, FormatError> {
+    let config = formatter.config_for(path)?;
+    let source_code = fs::read_to_string(path).map_err(|e| FormatError::Io {
+        file: path.to_string(),
+        message: format!("Failed to read file: {}", e),
+    })?;
+
+    format_code(&source_code, config, args).map_err(|e| e.with_file(path))
+}
+
+// Format code which doesn't come from a file of `Formatter::format()`,
+// with the range and verify options of `args`.
+fn format_code(source_code: &str, config: Config, args: &Args) -> Result<String, FormatError> {
+    let value = match args.range {
+        Some(range) => {
+            Formatter::format_range(source_code, range.byte_range(source_code), config.clone())?
+        }
+        None => Formatter::format_one(source_code, config.clone())?,
+    };
+    if args.verify {
+        Formatter::verify(source_code, &value)?;
+    }
+    if args.verify_idempotent {
+        Formatter::verify_idempotent(&value, config)?;
+    }
+    Ok(value)
+}
+
+// Parse errors are rendered with a source excerpt, the rest as a single line.
+fn report_error(e: &FormatError, color: bool) {
+    match e {
+        FormatError::Parse { errors } => {
+            for error in errors {
+                eprint!("{}", render(error, color));
+            }
+        }
+        FormatError::NotIdempotent {
+            file,
+            first,
+            second,
+        } => {
+            let file = file.as_deref().unwrap_or("<stdin>");
+            eprintln!(
+                "{}: {}: {}",
+                paint("error", Style::Red, color),
+                file,
+                e.message()
+            );
+            eprint!("{}", unified_diff(file, first, second, color));
+        }
+        _ => eprintln!("{}: {}", paint("error", Style::Red, color), e),
+    }
 }
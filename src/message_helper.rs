@@ -20,3 +20,43 @@ pub fn yellow(text: &str) -> &str {
     //format!("<strong style=\"color: yellow;\">{}</strong>", text) // HTML bold yellow
 }
 
+#[cfg(not(target_arch = "wasm32"))]
+pub fn green(text: &str) -> String {
+    format!("\x1b[32m{}\x1b[0m", text) // ANSI green
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn green(text: &str) -> &str {
+    text
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn cyan(text: &str) -> String {
+    format!("\x1b[36m{}\x1b[0m", text) // ANSI cyan
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn cyan(text: &str) -> &str {
+    text
+}
+
+#[derive(Clone, Copy)]
+pub enum Style {
+    Red,
+    Green,
+    Yellow,
+    Cyan,
+}
+
+// Color `text` only when `enabled`, e.g. unless `--no-color` is given.
+pub fn paint(text: &str, style: Style, enabled: bool) -> String {
+    if !enabled {
+        return text.to_string();
+    }
+    match style {
+        Style::Red => red(text).to_string(),
+        Style::Green => green(text).to_string(),
+        Style::Yellow => yellow(text).to_string(),
+        Style::Cyan => cyan(text).to_string(),
+    }
+}
@@ -0,0 +1,55 @@
+use crate::formatter::{Config, NewlineStyle};
+
+const BOM: char = '\u{feff}';
+
+// The last step of formatting a whole file: the printer always ends lines with
+// `\n`, this gives the result the line endings, final newline and BOM asked for.
+pub fn finish_output(source_code: &str, printed: &str, config: &Config) -> String {
+    let mut body = printed
+        .trim_start_matches(BOM)
+        .trim_end_matches(['\r', '\n']);
+    if body.trim().is_empty() {
+        body = "";
+    }
+
+    let newline = newline(source_code, config.newline_style);
+    let mut result = String::with_capacity(body.len() + body.len() / 16 + 4);
+    if source_code.starts_with(BOM) {
+        result.push(BOM);
+    }
+    result.push_str(&convert_newlines(body, newline));
+    if config.insert_final_newline && !body.is_empty() {
+        result.push_str(newline);
+    }
+    result
+}
+
+// The line ending for `source_code`. `Auto` keeps the one most of its lines
+// end with, `\n` when there's a tie.
+pub fn newline(source_code: &str, style: NewlineStyle) -> &'static str {
+    match style {
+        NewlineStyle::Unix => "\n",
+        NewlineStyle::Windows => "\r\n",
+        NewlineStyle::Native if cfg!(windows) => "\r\n",
+        NewlineStyle::Native => "\n",
+        NewlineStyle::Auto => {
+            let crlf = source_code.matches("\r\n").count();
+            let lf = source_code.matches('\n').count() - crlf;
+            if crlf > lf {
+                "\r\n"
+            } else {
+                "\n"
+            }
+        }
+    }
+}
+
+// Replace every line ending of `text`, whatever it is, with `newline`.
+pub fn convert_newlines(text: &str, newline: &str) -> String {
+    let unix = text.replace("\r\n", "\n");
+    if newline == "\n" {
+        unix
+    } else {
+        unix.replace('\n', newline)
+    }
+}
@@ -0,0 +1,53 @@
+// Conversions between byte offsets and the positions of editors, JS strings
+// and SARIF, whose columns count UTF-16 code units. Lines and columns are 0-based.
+
+// The line and UTF-16 column of the byte `offset` in `text`.
+pub fn utf16_position(text: &str, offset: usize) -> (usize, usize) {
+    let before = before(text, offset);
+    (
+        before.matches('\n').count(),
+        line_before(before).encode_utf16().count(),
+    )
+}
+
+// The column of the byte `offset` in `text` counting chars, e.g. for Checkstyle.
+pub fn char_column(text: &str, offset: usize) -> usize {
+    line_before(before(text, offset)).chars().count()
+}
+
+// The text before `offset`, moved back to a char boundary.
+fn before(text: &str, offset: usize) -> &str {
+    let mut offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    &text[..offset]
+}
+
+fn line_before(before: &str) -> &str {
+    &before[before.rfind('\n').map_or(0, |i| i + 1)..]
+}
+
+// The byte offset of a line and UTF-16 column, clamped to the end of the
+// line and of `text`. A column inside a surrogate pair moves past it.
+pub fn byte_offset(text: &str, line: usize, column: usize) -> usize {
+    let mut line_start = 0;
+    for _ in 0..line {
+        match text[line_start..].find('\n') {
+            Some(i) => line_start += i + 1,
+            None => return text.len(),
+        }
+    }
+
+    let line = text[line_start..].split('\n').next().unwrap_or_default();
+    // a column past the end of a CRLF line stays in front of the `\r`
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    let mut units = 0;
+    for (i, c) in line.char_indices() {
+        if units >= column {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    line_start + line.len()
+}
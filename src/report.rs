@@ -0,0 +1,390 @@
+use crate::diff::first_changed_lines;
+use crate::error::{ErrorLocation, FormatError};
+use crate::position::{char_column, utf16_position};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// Bumped on any change which could break a consumer of the JSON report: a
+// field removed, renamed or retyped, or a new `status` or error `kind`. Adding
+// a field doesn't.
+pub const REPORT_VERSION: u32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Unchanged,
+    // reformatted, or would be with `--check`
+    Changed,
+    Error,
+}
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const HOMEPAGE: &str = "https://github.com/xixiaofinland/afmt";
+// the rule of the files `--check` would reformat, errors use their `kind`
+const UNFORMATTED_RULE: &str = "unformatted";
+
+// The outcome of one run, printed with `--output-format json`, `sarif` or
+// `checkstyle`.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    version: u32,
+    files: Vec<FileReport>,
+    summary: Summary,
+    #[serde(skip)]
+    include_formatted: bool,
+    #[serde(skip)]
+    start: Instant,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileReport {
+    pub path: String,
+    pub status: Status,
+    pub duration_ms: f64,
+    // every syntax error for `parse`, one entry for the other kinds
+    pub errors: Vec<ErrorReport>,
+    // the first lines formatting changes, for a `changed` file
+    pub first_change: Option<LineRange>,
+    // only with `--include-formatted`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub formatted: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorReport {
+    // `FormatError::kind()`
+    pub kind: &'static str,
+    pub message: String,
+    pub location: Option<LocationReport>,
+}
+
+// 1-based lines of the original file, end inclusive.
+#[derive(Debug, Serialize)]
+pub struct LineRange {
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+// Lines and columns are 1-based, columns count bytes.
+#[derive(Debug, Serialize)]
+pub struct LocationReport {
+    pub line: usize,
+    pub column: usize,
+    // only known for syntax errors
+    pub end_line: Option<usize>,
+    pub end_column: Option<usize>,
+    pub start_byte: usize,
+    pub end_byte: usize,
+    pub node_kind: String,
+    // 1-based columns in UTF-16 code units, as SARIF counts them
+    #[serde(skip)]
+    pub utf16_column: usize,
+    #[serde(skip)]
+    pub utf16_end_column: Option<usize>,
+    // 1-based column in chars, for Checkstyle
+    #[serde(skip)]
+    pub char_column: usize,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Summary {
+    pub files: usize,
+    pub unchanged: usize,
+    pub changed: usize,
+    pub errors: usize,
+    pub duration_ms: f64,
+}
+
+impl Report {
+    pub fn new(include_formatted: bool) -> Self {
+        Self {
+            version: REPORT_VERSION,
+            files: Vec::new(),
+            summary: Summary::default(),
+            include_formatted,
+            start: Instant::now(),
+        }
+    }
+
+    pub fn files(&self) -> &[FileReport] {
+        &self.files
+    }
+
+    pub fn add_formatted(
+        &mut self,
+        path: &str,
+        original: &str,
+        formatted: &str,
+        elapsed: Duration,
+    ) {
+        let status = if original == formatted {
+            Status::Unchanged
+        } else {
+            Status::Changed
+        };
+        self.add(FileReport {
+            path: path.to_string(),
+            status,
+            duration_ms: millis(elapsed),
+            errors: Vec::new(),
+            first_change: first_changed_lines(original, formatted).map(|(start_line, end_line)| {
+                LineRange {
+                    start_line,
+                    end_line,
+                }
+            }),
+            formatted: self.include_formatted.then(|| formatted.to_string()),
+        });
+    }
+
+    // `source_code` is the content of the file the error locations point to.
+    pub fn add_error(
+        &mut self,
+        path: &str,
+        source_code: &str,
+        error: &FormatError,
+        elapsed: Duration,
+    ) {
+        let errors = match error {
+            FormatError::Parse { errors } => errors
+                .iter()
+                .map(|e| ErrorReport {
+                    kind: error.kind(),
+                    message: e.message.clone(),
+                    location: Some(LocationReport {
+                        end_line: Some(e.end_line),
+                        end_column: Some(e.end_column),
+                        utf16_end_column: Some(
+                            utf16_position(source_code, e.location.end_byte).1 + 1,
+                        ),
+                        ..LocationReport::new(&e.location, source_code)
+                    }),
+                })
+                .collect(),
+            _ => vec![ErrorReport {
+                kind: error.kind(),
+                message: error.message(),
+                location: error
+                    .location()
+                    .map(|location| LocationReport::new(location, source_code)),
+            }],
+        };
+        self.add(FileReport {
+            path: path.to_string(),
+            status: Status::Error,
+            duration_ms: millis(elapsed),
+            errors,
+            first_change: None,
+            formatted: None,
+        });
+    }
+
+    fn add(&mut self, file: FileReport) {
+        self.summary.files += 1;
+        match file.status {
+            Status::Unchanged => self.summary.unchanged += 1,
+            Status::Changed => self.summary.changed += 1,
+            Status::Error => self.summary.errors += 1,
+        }
+        self.files.push(file);
+    }
+
+    pub fn to_json(&mut self) -> String {
+        self.summary.duration_ms = millis(self.start.elapsed());
+        serde_json::to_string_pretty(self).expect("the report is always serializable")
+    }
+
+    // SARIF 2.1.0 with one result per unformatted file and per error. Columns
+    // count UTF-16 code units, SARIF's default `columnKind`.
+    pub fn to_sarif(&self) -> String {
+        let mut rules = vec![json!({
+            "id": UNFORMATTED_RULE,
+            "shortDescription": { "text": "The file is not formatted" },
+        })];
+        let mut results = Vec::new();
+
+        for file in &self.files {
+            if let Some(ref range) = file.first_change {
+                results.push(json!({
+                    "ruleId": UNFORMATTED_RULE,
+                    "level": "warning",
+                    "message": { "text": unformatted_message(range) },
+                    "locations": [sarif_location(&file.path, Some(json!({
+                        "startLine": range.start_line,
+                        "endLine": range.end_line,
+                    })))],
+                }));
+            }
+
+            for error in &file.errors {
+                if !rules.iter().any(|rule| rule["id"] == error.kind) {
+                    rules.push(json!({
+                        "id": error.kind,
+                        "shortDescription": { "text": "afmt can't format the file" },
+                    }));
+                }
+                let region = error.location.as_ref().map(|location| {
+                    let mut region = json!({
+                        "startLine": location.line,
+                        "startColumn": location.utf16_column,
+                        "byteOffset": location.start_byte,
+                        "byteLength": location.end_byte - location.start_byte,
+                    });
+                    if let (Some(end_line), Some(end_column)) =
+                        (location.end_line, location.utf16_end_column)
+                    {
+                        region["endLine"] = end_line.into();
+                        region["endColumn"] = end_column.into();
+                    }
+                    region
+                });
+                results.push(json!({
+                    "ruleId": error.kind,
+                    "level": "error",
+                    "message": { "text": error.message },
+                    "locations": [sarif_location(&file.path, region)],
+                }));
+            }
+        }
+
+        let sarif = json!({
+            "$schema": SARIF_SCHEMA,
+            "version": "2.1.0",
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": "afmt",
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "informationUri": HOMEPAGE,
+                        "rules": rules,
+                    },
+                },
+                "results": results,
+            }],
+        });
+        serde_json::to_string_pretty(&sarif).expect("the report is always serializable")
+    }
+
+    // Checkstyle XML, listing every file like Checkstyle does. An error
+    // without a location is reported on line 0, i.e. the whole file. Columns
+    // count chars.
+    pub fn to_checkstyle(&self) -> String {
+        let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        out.push_str("<checkstyle version=\"4.3\">\n");
+
+        for file in &self.files {
+            out.push_str(&format!("  <file name=\"{}\">\n", xml_escape(&file.path)));
+            if let Some(ref range) = file.first_change {
+                out.push_str(&format!(
+                    "    <error line=\"{}\" severity=\"warning\" message=\"{}\" source=\"afmt.{}\"/>\n",
+                    range.start_line,
+                    xml_escape(&unformatted_message(range)),
+                    UNFORMATTED_RULE
+                ));
+            }
+            for error in &file.errors {
+                let position = match error.location {
+                    Some(ref location) => {
+                        format!(
+                            "line=\"{}\" column=\"{}\"",
+                            location.line, location.char_column
+                        )
+                    }
+                    None => "line=\"0\"".to_string(),
+                };
+                out.push_str(&format!(
+                    "    <error {} severity=\"error\" message=\"{}\" source=\"afmt.{}\"/>\n",
+                    position,
+                    xml_escape(&error.message),
+                    error.kind
+                ));
+            }
+            out.push_str("  </file>\n");
+        }
+
+        out.push_str("</checkstyle>\n");
+        out
+    }
+}
+
+impl LocationReport {
+    fn new(location: &ErrorLocation, source_code: &str) -> Self {
+        Self {
+            line: location.line,
+            column: location.column,
+            end_line: None,
+            end_column: None,
+            start_byte: location.start_byte,
+            end_byte: location.end_byte,
+            node_kind: location.kind.clone(),
+            utf16_column: utf16_position(source_code, location.start_byte).1 + 1,
+            utf16_end_column: None,
+            char_column: char_column(source_code, location.start_byte) + 1,
+        }
+    }
+}
+
+fn unformatted_message(range: &LineRange) -> String {
+    if range.start_line == range.end_line {
+        format!("Line {} would be reformatted", range.start_line)
+    } else {
+        format!(
+            "Lines {} to {} would be reformatted",
+            range.start_line, range.end_line
+        )
+    }
+}
+
+// The characters to percent-encode in the path of a URI, `/` separates the segments.
+const URI_PATH: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'[')
+    .add(b']')
+    .add(b'\\')
+    .add(b'^')
+    .add(b'`')
+    .add(b'{')
+    .add(b'|')
+    .add(b'}');
+
+fn sarif_location(path: &str, region: Option<Value>) -> Value {
+    let uri_path = path.replace('\\', "/");
+    // relative paths are resolved against the checkout by code scanning tools
+    let artifact = if Path::new(path).is_absolute() {
+        // `file:///C:/...` on Windows
+        let root = if uri_path.starts_with('/') { "" } else { "/" };
+        json!({ "uri": format!("file://{}{}", root, utf8_percent_encode(&uri_path, URI_PATH)) })
+    } else {
+        json!({
+            "uri": utf8_percent_encode(uri_path.trim_start_matches("./"), URI_PATH).to_string(),
+            "uriBaseId": "%SRCROOT%",
+        })
+    };
+    let mut location = json!({ "artifactLocation": artifact });
+    if let Some(region) = region {
+        location["region"] = region;
+    }
+    json!({ "physicalLocation": location })
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+// rounded to the microsecond, more would only be noise
+fn millis(duration: Duration) -> f64 {
+    duration.as_micros() as f64 / 1000.0
+}
@@ -1,118 +1,147 @@
 use crate::{
     accessor::Accessor,
-    context::{Comment, CommentBucket, CommentMap, CommentType, NodeInfo},
+    context::{Comment, CommentBucket, CommentMap, NodeInfo},
     data_model::*,
     doc::{Doc, DocRef},
     doc_builder::DocBuilder,
     enum_def::{Comparison, SetValue, SoqlLiteral, ValueComparedWith},
-    message_helper::{red, yellow},
+    error::{ErrorLocation, FormatError},
 };
 #[allow(unused_imports)]
 use log::debug;
-use std::{cell::Cell, collections::HashMap};
+use std::{
+    cell::{OnceCell, RefCell},
+    collections::HashSet,
+    marker::PhantomData,
+    ops::Range,
+    rc::Rc,
+};
 use tree_sitter::{Node, Tree, TreeCursor};
 
-thread_local! {
-    static THREAD_SOURCE_CODE: Cell<Option<&'static str>>
-        = const{ Cell::new(None) };
+// The source code and the comments of the file being formatted. It is entered
+// by `enter_format_context()` for one format and freed when that is done.
+pub struct FormatContext {
+    source_code: String,
+    // set by `set_comment_map()`, collecting the comments needs the source code
+    comment_map: OnceCell<CommentMap>,
+    // set by `set_ignored_nodes()`, see `is_format_ignored()`
+    ignored_nodes: OnceCell<HashSet<usize>>,
+    no_comments: CommentBucket,
 }
 
-pub fn set_thread_source_code(source_code: String) {
-    // TODO: use OnceCell to not leak?
-    let leaked_code: &'static str = Box::leak(source_code.into_boxed_str());
-    THREAD_SOURCE_CODE.with(|sc| {
-        if sc.get().is_some() {
-            panic!("Source code is already set for this thread");
-        }
-        sc.set(Some(leaked_code));
-    });
-}
+impl FormatContext {
+    pub fn source_code(&self) -> &str {
+        &self.source_code
+    }
 
-pub fn get_source_code() -> &'static str {
-    THREAD_SOURCE_CODE.with(|sc| sc.get().expect("Source code not set for this thread"))
+    pub fn comment_map(&self) -> &CommentMap {
+        self.comment_map
+            .get()
+            .expect("## CommentMap not set for this format context")
+    }
+
+    pub fn ignored_nodes(&self) -> &HashSet<usize> {
+        self.ignored_nodes
+            .get()
+            .expect("## ignored nodes not set for this format context")
+    }
+
+    // A node `collect_comments()` didn't give a bucket has no comments.
+    pub fn comment_bucket(&self, node_id: &usize) -> &CommentBucket {
+        self.comment_map().get(node_id).unwrap_or(&self.no_comments)
+    }
 }
 
 thread_local! {
-    static THREAD_COMMENT_MAP: Cell<Option<&'static CommentMap>> = const{ Cell::new(None) };
+    // the last entered context is the current one; a stack, so a format can
+    // run while another one is in progress on the same thread
+    static FORMAT_CONTEXTS: RefCell<Vec<Rc<FormatContext>>> = const { RefCell::new(Vec::new()) };
 }
 
-pub fn set_thread_comment_map(comment_map: CommentMap) {
-    // TODO: use OnceCell to not leak?
-    let leaked_map: &'static CommentMap = Box::leak(Box::new(comment_map));
-
-    THREAD_COMMENT_MAP.with(|cm| {
-        if cm.get().is_some() {
-            panic!("CommentMap is already set for this thread");
-        }
-        cm.set(Some(leaked_map));
-    });
+// Leaves the context when dropped, also when the format panics.
+pub struct FormatContextGuard {
+    // the context belongs to the thread which entered it
+    _not_send: PhantomData<*const ()>,
 }
 
-pub fn get_comment_bucket(node_id: &usize) -> &CommentBucket {
-    get_comment_map()
-        .get(node_id)
-        .unwrap_or_else(|| panic!("## comment_map missing bucket for node: {}", node_id))
+impl Drop for FormatContextGuard {
+    fn drop(&mut self) {
+        FORMAT_CONTEXTS.with(|c| c.borrow_mut().pop());
+    }
 }
 
-pub fn get_comment_map() -> &'static CommentMap {
-    THREAD_COMMENT_MAP.with(|cm| cm.get().expect("## CommentMap not set for this thread"))
+#[must_use]
+pub fn enter_format_context(source_code: &str) -> FormatContextGuard {
+    let context = FormatContext {
+        source_code: source_code.to_string(),
+        comment_map: OnceCell::new(),
+        ignored_nodes: OnceCell::new(),
+        no_comments: CommentBucket::new(),
+    };
+    FORMAT_CONTEXTS.with(|c| c.borrow_mut().push(Rc::new(context)));
+    FormatContextGuard {
+        _not_send: PhantomData,
+    }
 }
 
-// Used for debugging purpose only
-pub fn print_comment_map(tree: &Tree) {
-    let comment_map = get_comment_map();
-    let node_map = build_id_node_map(tree);
-
-    let filtered_map: HashMap<usize, &CommentBucket> = comment_map
-        .iter()
-        .filter(|(_, bucket)| {
-            !bucket.pre_comments.is_empty()
-                || !bucket.post_comments.is_empty()
-                || !bucket.dangling_comments.is_empty()
-        })
-        .map(|(k, v)| (*k, v))
-        .collect();
+pub fn format_context() -> Rc<FormatContext> {
+    FORMAT_CONTEXTS.with(|c| {
+        c.borrow()
+            .last()
+            .cloned()
+            .expect("no format context entered on this thread")
+    })
+}
 
-    for (node_id, bucket) in &filtered_map {
-        if let Some(node) = node_map.get(node_id) {
-            eprintln!(
-                "{}, {} ({}) : CommentBucket {{",
-                node_id,
-                yellow(node.kind()),
-                yellow(&node.value().chars().take(8).collect::<String>())
-            );
-        } else {
-            eprintln!("{} (Unknown Node) : CommentBucket {{", node_id);
-        }
-        eprintln!("pre_comments: {:#?},", bucket.pre_comments);
-        eprintln!("post_comments: {:#?},", bucket.post_comments);
-        eprintln!("dangling_comments: {:#?},", bucket.dangling_comments);
-        eprintln!("--------------------");
+pub fn set_comment_map(comment_map: CommentMap) {
+    if format_context().comment_map.set(comment_map).is_err() {
+        panic!("CommentMap is already set for this format context");
     }
 }
 
-fn build_id_node_map(ast_tree: &Tree) -> HashMap<usize, Node> {
-    let mut cursor = ast_tree.walk();
-    let mut node_map = HashMap::new();
+pub fn set_ignored_nodes(ignored_nodes: HashSet<usize>) {
+    if format_context().ignored_nodes.set(ignored_nodes).is_err() {
+        panic!("ignored nodes are already set for this format context");
+    }
+}
 
-    loop {
-        let node = cursor.node();
-        node_map.insert(node.id(), node);
+pub fn assert_no_missing_comments() -> Result<(), FormatError> {
+    assert_no_missing_comments_in(&(0..usize::MAX))
+}
 
-        if cursor.goto_first_child() {
-            continue;
-        }
+// Only check the comments starting in `byte_range`, the part of the source being formatted.
+pub fn assert_no_missing_comments_in(byte_range: &Range<usize>) -> Result<(), FormatError> {
+    let context = format_context();
+    let mut missing_comments: Vec<&Comment> = context
+        .comment_map()
+        .values()
+        .flat_map(|bucket| {
+            bucket
+                .pre_comments
+                .iter()
+                .chain(bucket.post_comments.iter())
+                .chain(bucket.dangling_comments.iter())
+        })
+        .filter(|comment| !comment.is_printed() && byte_range.contains(&comment.range.start_byte))
+        .collect();
 
-        while !cursor.goto_next_sibling() {
-            if !cursor.goto_parent() {
-                return node_map;
-            }
-        }
+    if missing_comments.is_empty() {
+        return Ok(());
     }
+
+    // HashMap iteration order is random, report them in source order
+    missing_comments.sort_by_key(|c| c.range.start_byte);
+    let first = missing_comments[0];
+    Err(FormatError::DroppedComments {
+        location: ErrorLocation::from_range(first.range, first.comment_type.kind()),
+        comments: missing_comments.iter().map(|c| c.value.clone()).collect(),
+    })
 }
 
-pub fn assert_no_missing_comments() {
-    let missing_comments: Vec<&'static Comment> = get_comment_map()
+// Mark the comments inside a node printed as written in the source, e.g. by `Verbatim`.
+pub fn mark_comments_as_printed(byte_range: &Range<usize>) {
+    format_context()
+        .comment_map()
         .values()
         .flat_map(|bucket| {
             bucket
@@ -121,26 +150,102 @@ pub fn assert_no_missing_comments() {
                 .chain(bucket.post_comments.iter())
                 .chain(bucket.dangling_comments.iter())
         })
-        .filter(|comment| !comment.is_printed())
-        .collect();
+        .filter(|c| byte_range.contains(&c.range.start_byte))
+        .for_each(|c| c.mark_as_printed());
+}
+
+const IGNORE_DIRECTIVE: &str = "afmt-ignore";
+const OFF_DIRECTIVE: &str = "afmt:off";
+const ON_DIRECTIVE: &str = "afmt:on";
 
-    if !missing_comments.is_empty() {
-        for comment in missing_comments {
-            eprintln!("Erased comment: {}", red(&comment.value));
+// Whether a statement or body member must be kept as written in the source:
+// - the comments right above it, on their own lines, include `// afmt-ignore`
+// - or it follows an `// afmt:off` comment of the same body, with no `// afmt:on` in between
+pub fn is_format_ignored(node: &Node) -> bool {
+    format_context().ignored_nodes().contains(&node.id())
+}
+
+// The ids of the nodes `is_format_ignored()` is true for, found in one pass
+// over the children of each node rather than by looking back from every
+// statement and member.
+pub fn collect_ignored_nodes(tree: &Tree) -> HashSet<usize> {
+    let mut ignored_nodes = HashSet::new();
+    collect_ignored_children(&mut tree.walk(), &mut ignored_nodes);
+    ignored_nodes
+}
+
+fn collect_ignored_children(cursor: &mut TreeCursor, ignored_nodes: &mut HashSet<usize>) {
+    if !cursor.goto_first_child() {
+        return;
+    }
+
+    let mut is_off = false;
+    // an `// afmt-ignore` since the last node which isn't a comment
+    let mut ignore_next = false;
+    // a comment sharing its line with the node before is one of that node's
+    // post_comments, see `collect_comments()`
+    let mut last_associable_row = None;
+
+    loop {
+        let child = cursor.node();
+
+        if child.is_extra() {
+            match directive(&child) {
+                Some(IGNORE_DIRECTIVE) => {
+                    ignore_next |=
+                        last_associable_row.is_none_or(|row| row < child.start_position().row);
+                }
+                Some(OFF_DIRECTIVE) => is_off = true,
+                Some(ON_DIRECTIVE) => {
+                    is_off = false;
+                    ignore_next = false;
+                }
+                _ => {}
+            }
+        } else {
+            if is_off || ignore_next {
+                ignored_nodes.insert(child.id());
+            }
+            ignore_next = false;
+            if child.is_named() || is_punctuation_node(&child) {
+                last_associable_row = Some(child.end_position().row);
+            }
+            collect_ignored_children(cursor, ignored_nodes);
+        }
+
+        if !cursor.goto_next_sibling() {
+            break;
         }
-        panic!("## There are erased comment node(s)");
     }
+
+    cursor.goto_parent();
+}
+
+// The text of a `// ...` or `/* ... */` comment, if it is one of the directives.
+fn directive(comment: &Node) -> Option<&'static str> {
+    let value = comment.value();
+    let text = value
+        .strip_prefix("//")
+        .or_else(|| value.strip_prefix("/*").and_then(|v| v.strip_suffix("*/")))?
+        .trim();
+
+    [IGNORE_DIRECTIVE, OFF_DIRECTIVE, ON_DIRECTIVE]
+        .into_iter()
+        .find(|d| *d == text)
 }
 
 pub fn is_punctuation_node(node: &Node) -> bool {
     node.kind() == "," || node.kind() == ";"
 }
 
-pub fn collect_comments(cursor: &mut TreeCursor, comment_map: &mut CommentMap) {
+pub fn collect_comments(
+    cursor: &mut TreeCursor,
+    comment_map: &mut CommentMap,
+) -> Result<(), FormatError> {
     let node = cursor.node();
 
     if (!node.is_named() || node.is_extra()) && !is_punctuation_node(&node) {
-        return;
+        return Ok(());
     }
 
     let current_id = node.id();
@@ -150,7 +255,7 @@ pub fn collect_comments(cursor: &mut TreeCursor, comment_map: &mut CommentMap) {
 
     // If this node has no children, we simply return
     if !cursor.goto_first_child() {
-        return;
+        return Ok(());
     }
 
     // We'll track comments that appear before the next assciable node in this vector
@@ -163,7 +268,7 @@ pub fn collect_comments(cursor: &mut TreeCursor, comment_map: &mut CommentMap) {
 
         if child.is_extra() {
             // It's a comment node
-            let comment = Comment::from_node(child);
+            let comment = Comment::from_node(child)?;
 
             if let Some((last_id, last_row)) = last_associable_node_info {
                 // We'll wrap the comment in an Option so we can move it exactly once
@@ -233,7 +338,7 @@ pub fn collect_comments(cursor: &mut TreeCursor, comment_map: &mut CommentMap) {
             }
 
             // Recurse down into the child node
-            collect_comments(cursor, comment_map);
+            collect_comments(cursor, comment_map)?;
 
             // After returning, we know child is fully processed
             last_associable_node_info = Some((child_id, child.end_position().row));
@@ -263,6 +368,7 @@ pub fn collect_comments(cursor: &mut TreeCursor, comment_map: &mut CommentMap) {
 
     // Step back up to the parent node
     cursor.goto_parent();
+    Ok(())
 }
 
 pub fn build_with_comments<'a, F>(
@@ -273,7 +379,8 @@ pub fn build_with_comments<'a, F>(
 ) where
     F: FnOnce(&'a DocBuilder<'a>, &mut Vec<DocRef<'a>>),
 {
-    let bucket = get_comment_bucket(&node_info.id);
+    let context = format_context();
+    let bucket = context.comment_bucket(&node_info.id);
     handle_pre_comments(b, bucket, result);
 
     if bucket.dangling_comments.is_empty() {
@@ -317,10 +424,6 @@ pub fn handle_dangling_comments<'a>(
     b: &'a DocBuilder<'a>,
     bucket: &CommentBucket,
 ) -> Vec<&'a Doc<'a>> {
-    if bucket.dangling_comments.is_empty() {
-        panic!("handle_dangling_comments() should not have empty dangling_comments input")
-    }
-
     let mut docs = Vec::new();
     for comment in &bucket.dangling_comments {
         if comment.has_leading_content() {
@@ -416,7 +519,7 @@ pub fn handle_post_comments<'a>(
     result.push(b.concat(docs));
 }
 
-pub fn enrich(ast_tree: &Tree) -> Root {
+pub fn enrich(ast_tree: &Tree) -> Result<Root, FormatError> {
     let root_node = ast_tree.root_node();
     Root::new(root_node)
     // TODO: check enum size
@@ -424,18 +527,22 @@ pub fn enrich(ast_tree: &Tree) -> Root {
     //eprintln!("Class={:#?}", std::mem::size_of::<FieldDeclaration>());
 }
 
-pub fn assert_check(node: Node, expected_kind: &str) {
-    assert!(
-        node.kind() == expected_kind,
-        "## Expected node kind '{}', found '{}'.\n## Source_code: {}",
-        yellow(expected_kind),
-        red(node.kind()),
-        node.value()
-    );
+pub fn assert_check(node: Node, expected_kind: &str) -> Result<(), FormatError> {
+    if node.kind() != expected_kind {
+        return Err(unsupported_node(
+            &node,
+            format!(
+                "expected node kind '{}', found '{}'",
+                expected_kind,
+                node.kind()
+            ),
+        ));
+    }
+    Ok(())
 }
 
-pub fn get_precedence(op: &str) -> u8 {
-    match op {
+pub fn get_precedence(op: &Node) -> Result<u8, FormatError> {
+    Ok(match op.kind() {
         "=" | "+=" | "-=" | "*=" | "/=" | "%=" | "&=" | "|=" | "^=" | "<<=" | ">>=" | ">>>=" => 1, // Assignment
         "?" | ":" => 2,                               // Ternary
         "||" => 3,                                    // Logical OR
@@ -450,8 +557,13 @@ pub fn get_precedence(op: &str) -> u8 {
         "+" | "-" => 12,                              // Additive
         "*" | "/" | "%" => 13,                        // Multiplicative
         "!" | "~" | "++" | "--" => 14,                // Unary operators
-        _ => panic!("## Not supported operator: {}", op),
-    }
+        _ => {
+            return Err(unsupported_node(
+                op,
+                format!("not supported operator: {}", op.kind()),
+            ))
+        }
+    })
 }
 
 pub fn is_binary_exp(node: &Node) -> bool {
@@ -464,35 +576,36 @@ pub fn is_query_expression(node: &Node) -> bool {
 
 // TODO: AST use a comparison concrete node so this can be moved into Comparison::new()
 // TODO: get rid of next_named()?
-pub fn get_comparsion(node: &Node) -> Comparison {
+pub fn get_comparsion(node: &Node) -> Result<Comparison, FormatError> {
     if let Some(operator_node) = node.try_c_by_k("value_comparison_operator") {
-        let next_node = operator_node.next_named();
+        let next_node = operator_node.next_named()?;
         let compared_with = match next_node.kind() {
             "bound_apex_expression" => {
-                ValueComparedWith::Bound(BoundApexExpression::new(next_node))
+                ValueComparedWith::Bound(BoundApexExpression::new(next_node)?)
             }
-            _ => ValueComparedWith::Literal(SoqlLiteral::new(next_node)),
+            _ => ValueComparedWith::Literal(SoqlLiteral::new(next_node)?),
         };
 
-        Comparison::Value(ValueComparison {
+        Ok(Comparison::Value(ValueComparison {
             operator: operator_node.value(),
             compared_with,
-        })
+        }))
     } else if let Some(operator_node) = node.try_c_by_k("set_comparison_operator") {
-        let next_node = operator_node.next_named();
-        Comparison::Set(SetComparison {
+        let next_node = operator_node.next_named()?;
+        Ok(Comparison::Set(SetComparison {
             operator: operator_node.value(),
-            set_value: SetValue::new(next_node),
-        })
+            set_value: SetValue::new(next_node)?,
+        }))
     } else {
-        unreachable!()
+        Err(unsupported_node(
+            node,
+            "missing comparison operator".to_string(),
+        ))
     }
 }
 
 pub fn build_chaining_context(node: &Node) -> Option<ChainingContext> {
-    let parent_node = node
-        .parent()
-        .expect("node must have parent node in build_chaining_context()");
+    let parent_node = node.parent()?;
 
     let is_parent_a_chaining_node = is_a_chaining_node(&parent_node);
 
@@ -523,13 +636,16 @@ fn is_a_chaining_node(node: &Node) -> bool {
     .contains(&node.kind())
 }
 
-pub fn panic_unknown_node(node: Node, name: &str) -> ! {
-    panic!(
-        "## unknown node: {} in {}\n## Source_code: {}",
-        red(node.kind()),
-        name,
-        node.value()
-    );
+pub fn unknown_node(node: Node, name: &str) -> FormatError {
+    unsupported_node(&node, format!("unknown node in {}", name))
+}
+
+// The error for a node the formatter can't handle, e.g. a kind it doesn't know.
+pub fn unsupported_node(node: &Node, message: String) -> FormatError {
+    FormatError::UnsupportedNode {
+        location: ErrorLocation::from_node(node),
+        message,
+    }
 }
 
 pub fn is_bracket_composite_node(node: &Node) -> bool {
@@ -0,0 +1,224 @@
+use crate::error::{ErrorLocation, FormatError};
+use tree_sitter::{Node, Tree};
+
+// Nodes whose text is printed in lower or upper case by `ValueNodeLowerCase`,
+// `ValueNodeUpperCase` and `DateLiteralWithParam`.
+const CASE_NORMALIZED_KINDS: [&str; 6] = [
+    "boolean",
+    "null_literal",
+    "decimal_floating_point_literal",
+    "with_data_cat_filter_type",
+    "fields_type",
+    "date_literal",
+];
+
+// SOQL conditions whose parentheses are dropped around the same operator,
+// e.g. `a AND (b AND c)` is printed as `a AND b AND c`.
+const FLATTENED_KINDS: [&str; 2] = ["and_expression", "or_expression"];
+
+// Compare the tree of the source with the tree of the formatted code node by
+// node. Whitespace is ignored, and so is the case of keywords and of the nodes
+// in `CASE_NORMALIZED_KINDS`. Comments are compared on their own, in source
+// order, since the formatter may attach them to a neighbouring node.
+pub fn check_equivalent(
+    source_tree: &Tree,
+    source_code: &str,
+    formatted_tree: &Tree,
+    formatted_code: &str,
+) -> Result<(), FormatError> {
+    let mut stack = vec![(source_tree.root_node(), formatted_tree.root_node(), false)];
+    while let Some((a, b, ignore_case)) = stack.pop() {
+        if a.kind_id() != b.kind_id() {
+            return Err(difference(
+                &a,
+                &b,
+                format!("`{}` became `{}`", a.kind(), b.kind()),
+            ));
+        }
+        let ignore_case = ignore_case || CASE_NORMALIZED_KINDS.contains(&a.kind());
+
+        let a_children = children(&a);
+        let b_children = children(&b);
+
+        if a_children.is_empty() && b_children.is_empty() {
+            let a_text = &source_code[a.byte_range()];
+            let b_text = &formatted_code[b.byte_range()];
+            // keywords are case-insensitive, and some like `>=` may contain whitespace
+            let same = if !a.is_named() {
+                without_whitespace(a_text).to_lowercase()
+                    == without_whitespace(b_text).to_lowercase()
+            } else if ignore_case {
+                a_text.to_lowercase() == b_text.to_lowercase()
+            } else {
+                a_text == b_text
+            };
+            if !same {
+                return Err(difference(
+                    &a,
+                    &b,
+                    format!("`{}` `{}` became `{}`", a.kind(), a_text, b_text),
+                ));
+            }
+            continue;
+        }
+
+        if a_children.len() != b_children.len() {
+            return Err(difference(
+                &a,
+                &b,
+                format!(
+                    "`{}` has {} children, {} after formatting",
+                    a.kind(),
+                    a_children.len(),
+                    b_children.len()
+                ),
+            ));
+        }
+
+        // text of hidden tokens, which no child covers
+        let a_rest = uncovered_text(&a, source_code);
+        let b_rest = uncovered_text(&b, formatted_code);
+        if a_rest != b_rest {
+            return Err(difference(
+                &a,
+                &b,
+                format!("`{}` `{}` became `{}`", a.kind(), a_rest, b_rest),
+            ));
+        }
+
+        // reversed, so the first child is compared first
+        for (a, b) in a_children.into_iter().zip(b_children).rev() {
+            stack.push((a, b, ignore_case));
+        }
+    }
+
+    let a_comments = comments(source_tree);
+    let b_comments = comments(formatted_tree);
+    for (i, a) in a_comments.iter().enumerate() {
+        let a_text = &source_code[a.byte_range()];
+        match b_comments.get(i) {
+            Some(b) => {
+                let b_text = &formatted_code[b.byte_range()];
+                if comment_body(a_text) != comment_body(b_text) {
+                    return Err(difference(
+                        a,
+                        b,
+                        format!("comment `{}` became `{}`", a_text, b_text),
+                    ));
+                }
+            }
+            None => {
+                return Err(FormatError::NotEquivalent {
+                    location: ErrorLocation::from_node(a),
+                    message: format!("comment `{}` is missing", a_text),
+                })
+            }
+        }
+    }
+    if let Some(b) = b_comments.get(a_comments.len()) {
+        return Err(FormatError::NotEquivalent {
+            location: ErrorLocation::from_range(source_tree.root_node().range(), "comment"),
+            message: format!(
+                "comment `{}` was added at {}:{} of the formatted code",
+                &formatted_code[b.byte_range()],
+                b.start_position().row + 1,
+                b.start_position().column + 1
+            ),
+        });
+    }
+
+    Ok(())
+}
+
+// The location is the one of the original node, the formatted one is in the message.
+fn difference(a: &Node, b: &Node, message: String) -> FormatError {
+    FormatError::NotEquivalent {
+        location: ErrorLocation::from_node(a),
+        message: format!(
+            "{} at {}:{} of the formatted code",
+            message,
+            b.start_position().row + 1,
+            b.start_position().column + 1
+        ),
+    }
+}
+
+// The children to compare: no comments, and none of the tokens the formatter
+// leaves out on purpose.
+fn children<'t>(node: &Node<'t>) -> Vec<Node<'t>> {
+    let mut cursor = node.walk();
+    let children: Vec<_> = node
+        .children(&mut cursor)
+        .filter(|c| !c.is_extra())
+        // `@IsTest()` is printed as `@IsTest`
+        .filter(|c| !(c.kind() == "annotation_argument_list" && c.named_child_count() == 0))
+        // `Contact AS c` is printed as `Contact c`
+        .filter(|c| !(node.kind() == "storage_alias" && c.kind().eq_ignore_ascii_case("as")))
+        .collect();
+
+    if !FLATTENED_KINDS.contains(&node.kind()) {
+        return children;
+    }
+
+    let mut flattened = Vec::new();
+    let mut i = 0;
+    while i < children.len() {
+        if children[i].kind() == "("
+            && i + 2 < children.len()
+            && children[i + 1].kind() == node.kind()
+            && children[i + 2].kind() == ")"
+        {
+            flattened.extend(self::children(&children[i + 1]));
+            i += 3;
+        } else {
+            flattened.push(children[i]);
+            i += 1;
+        }
+    }
+    flattened
+}
+
+fn uncovered_text(node: &Node, code: &str) -> String {
+    let mut rest = String::new();
+    let mut offset = node.start_byte();
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        rest.push_str(&code[offset..child.start_byte().max(offset)]);
+        offset = offset.max(child.end_byte());
+    }
+    rest.push_str(&code[offset..node.end_byte().max(offset)]);
+    without_whitespace(&rest)
+}
+
+// comments and other extras, in source order
+fn comments(tree: &Tree) -> Vec<Node<'_>> {
+    let mut comments = Vec::new();
+    let mut stack = vec![tree.root_node()];
+    while let Some(node) = stack.pop() {
+        if node.is_extra() {
+            comments.push(node);
+            continue;
+        }
+        let mut cursor = node.walk();
+        let children: Vec<_> = node.children(&mut cursor).collect();
+        stack.extend(children.into_iter().rev());
+    }
+    comments
+}
+
+// A line comment may be printed as a block comment, e.g. before a `;`.
+fn comment_body(text: &str) -> String {
+    let text = text.trim();
+    let body = match text.strip_prefix("//") {
+        Some(body) => body,
+        None => text
+            .strip_prefix("/*")
+            .and_then(|t| t.strip_suffix("*/"))
+            .unwrap_or(text),
+    };
+    without_whitespace(body)
+}
+
+fn without_whitespace(text: &str) -> String {
+    text.chars().filter(|c| !c.is_whitespace()).collect()
+}
@@ -0,0 +1,176 @@
+// wasm-bindgen exports for the browser and web extensions.
+//
+// Nothing here throws: a bad config, a syntax error or a construct the
+// formatter doesn't support comes back in the `errors` of the result object.
+use crate::diagnostic::expected_note;
+use crate::error::FormatError;
+use crate::formatter::{Config, Formatter};
+use crate::position::utf16_position;
+use serde::Serialize;
+use std::ops::Range;
+use wasm_bindgen::prelude::*;
+
+// `{ formatted, errors: [{ line, column, message }] }`, `formatted` is null on failure.
+#[derive(Debug, Serialize)]
+pub struct FormatResult {
+    pub formatted: Option<String>,
+    pub errors: Vec<ResultError>,
+}
+
+// `line` and `column` are 1-based, `column` counts UTF-16 code units like JS
+// strings do. Both are null when the error has no location, e.g. a bad config.
+#[derive(Debug, Serialize)]
+pub struct ResultError {
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl FormatResult {
+    fn new(source_code: &str, result: Result<String, FormatError>) -> Self {
+        match result {
+            Ok(formatted) => Self {
+                formatted: Some(formatted),
+                errors: vec![],
+            },
+            Err(FormatError::Parse { errors }) => Self::failed(
+                errors
+                    .iter()
+                    .map(|e| {
+                        let mut message = e.message.clone();
+                        if let Some(expected) = expected_note(e) {
+                            message.push_str(&format!(", {}", expected));
+                        }
+                        ResultError::at(source_code, e.location.start_byte, message)
+                    })
+                    .collect(),
+            ),
+            Err(e) => {
+                let error = match e.location() {
+                    Some(location) => {
+                        ResultError::at(source_code, location.start_byte, e.message())
+                    }
+                    None => ResultError::without_location(e.message()),
+                };
+                Self::failed(vec![error])
+            }
+        }
+    }
+
+    fn failed(errors: Vec<ResultError>) -> Self {
+        Self {
+            formatted: None,
+            errors,
+        }
+    }
+}
+
+impl ResultError {
+    fn at(source_code: &str, byte: usize, message: String) -> Self {
+        let (line, column) = utf16_position(source_code, byte);
+        Self {
+            line: Some(line + 1),
+            column: Some(column + 1),
+            message,
+        }
+    }
+
+    fn without_location(message: String) -> Self {
+        Self {
+            line: None,
+            column: None,
+            message,
+        }
+    }
+}
+
+// `config` is `undefined`/`null` for the defaults, an object such as
+// `{ max_width: 100 }`, or the JSON or TOML text of one.
+#[wasm_bindgen(js_name = format)]
+pub fn format_js(source_code: &str, config: JsValue) -> JsValue {
+    to_js(&format_with(source_code, &config, None))
+}
+
+// Only format the statements and members overlapping `start..end`, which are
+// JS string indices (UTF-16 code units).
+#[wasm_bindgen(js_name = formatRange)]
+pub fn format_range_js(source_code: &str, start: usize, end: usize, config: JsValue) -> JsValue {
+    let range = byte_offset(source_code, start)..byte_offset(source_code, end);
+    to_js(&format_with(source_code, &config, Some(range)))
+}
+
+#[wasm_bindgen]
+pub fn version() -> String {
+    env!("CARGO_PKG_VERSION").to_string()
+}
+
+// Kept for existing callers, it throws on failure and has no config.
+#[wasm_bindgen]
+pub fn greet(source_code: &str) -> Result<String, JsError> {
+    let config = Config::default();
+    Formatter::format_one(source_code, config).map_err(|e| JsError::new(&e.to_string()))
+}
+
+fn format_with(source_code: &str, config: &JsValue, range: Option<Range<usize>>) -> FormatResult {
+    let config = match parse_config(config) {
+        Ok(config) => config,
+        Err(message) => return FormatResult::failed(vec![ResultError::without_location(message)]),
+    };
+
+    let result = match range {
+        Some(range) => Formatter::format_range(source_code, range, config),
+        None => Formatter::format_one(source_code, config),
+    };
+    FormatResult::new(source_code, result)
+}
+
+fn parse_config(config: &JsValue) -> Result<Config, String> {
+    if config.is_undefined() || config.is_null() {
+        return Ok(Config::default());
+    }
+    if let Some(text) = config.as_string() {
+        return if text.trim_start().starts_with('{') {
+            Config::from_json(&text)
+        } else {
+            Config::from_toml(&text)
+        };
+    }
+
+    serde_wasm_bindgen::from_value::<Config>(config.clone())
+        .map_err(|e| format!("Failed to parse config: {}", e))?
+        .validate()
+}
+
+fn byte_offset(source_code: &str, utf16_offset: usize) -> usize {
+    let mut units = 0;
+    for (i, c) in source_code.char_indices() {
+        if units >= utf16_offset {
+            return i;
+        }
+        units += c.len_utf16();
+    }
+    source_code.len()
+}
+
+fn to_js(result: &FormatResult) -> JsValue {
+    // `json_compatible()` turns `None` into `null` rather than `undefined`
+    result
+        .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+        .unwrap_or(JsValue::NULL)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unsupported_node_is_an_error() {
+        let source =
+            "class A {\n  List<A> l = [SELECT Id FROM A WITH DATA CATEGORY G__c AT u__c];\n}\n";
+        let result = FormatResult::new(source, Formatter::format_one(source, Config::default()));
+        assert_eq!(result.formatted, None);
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].line, Some(2));
+        assert!(result.errors[0].message.contains("with_type"));
+    }
+}
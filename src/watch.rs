@@ -0,0 +1,103 @@
+use crate::error::FormatError;
+use crate::files::{filter_source_files, is_apex_file};
+use crate::formatter::Formatter;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+// Saves of a file closer together than this are formatted once.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+// A file formatted after it changed.
+pub struct WatchEvent {
+    pub path: String,
+    // whether the file was rewritten, `false` when it was already formatted
+    pub result: Result<bool, FormatError>,
+    pub elapsed: Duration,
+}
+
+// Reformat the Apex files under `paths` in place each time they are saved,
+// until the process ends. Files are filtered like when expanding directories,
+// `config_path` being the `--config` file if any.
+pub fn watch(
+    formatter: &Formatter,
+    paths: &[String],
+    config_path: Option<&str>,
+    mut report: impl FnMut(WatchEvent),
+) -> Result<(), String> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher =
+        notify::recommended_watcher(tx).map_err(|e| format!("Failed to watch files: {}", e))?;
+    for path in paths {
+        let mode = if Path::new(path).is_dir() {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+        watcher
+            .watch(Path::new(path), mode)
+            .map_err(|e| format!("Failed to watch {}: {}", path, e))?;
+    }
+
+    let mut parser = Formatter::new_parser();
+    // what was last written to each file, so that writing it doesn't format it again
+    let mut written: HashMap<PathBuf, String> = HashMap::new();
+
+    while let Ok(event) = rx.recv() {
+        let mut changed = BTreeSet::new();
+        add_changed(event, &mut changed);
+        while let Ok(event) = rx.recv_timeout(DEBOUNCE) {
+            add_changed(event, &mut changed);
+        }
+
+        let files: Vec<_> = changed
+            .iter()
+            .map(|p| p.to_string_lossy().to_string())
+            .collect();
+        for file in filter_source_files(&files, config_path).files {
+            let path = PathBuf::from(&file);
+            // gone again, e.g. an editor's temporary file
+            let Ok(content) = fs::read_to_string(&path) else {
+                continue;
+            };
+            if written.get(&path) == Some(&content) {
+                continue;
+            }
+
+            let start = Instant::now();
+            let result = formatter
+                .format_file_with(&mut parser, &file)
+                .and_then(|formatted| {
+                    if formatted == content {
+                        return Ok(false);
+                    }
+                    fs::write(&path, &formatted).map_err(|e| FormatError::Io {
+                        file: file.clone(),
+                        message: format!("Failed to write file: {}", e),
+                    })?;
+                    written.insert(path.clone(), formatted);
+                    Ok(true)
+                });
+            report(WatchEvent {
+                path: file,
+                result,
+                elapsed: start.elapsed(),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn add_changed(event: notify::Result<Event>, changed: &mut BTreeSet<PathBuf>) {
+    let Ok(event) = event else {
+        return;
+    };
+    // reads and deletions leave nothing to format
+    if matches!(event.kind, EventKind::Access(_) | EventKind::Remove(_)) {
+        return;
+    }
+    changed.extend(event.paths.into_iter().filter(|p| is_apex_file(p)));
+}
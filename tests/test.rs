@@ -1,6 +1,6 @@
 #[cfg(test)]
 mod tests {
-    use afmt::message_helper::{self, red};
+    use afmt::message_helper::red;
     use afmt::{formatter::*, message_helper::yellow};
     use similar::{ChangeTag, TextDiff};
     use std::fs::File;
@@ -63,6 +63,649 @@ mod tests {
         }
     }
 
+    #[test]
+    fn format_errors() {
+        let source = "class A {\n  void m() {\n    Integer x = 1 +;\n  }\n  Integer y = 2\n}\n";
+        match Formatter::format_one(source, Config::default()) {
+            Err(afmt::error::FormatError::Parse { errors }) => {
+                assert_eq!(errors.len(), 2);
+                let location = &errors[0].location;
+                assert_eq!((location.line, location.column), (3, 19));
+                assert_eq!(&source[location.start_byte..location.end_byte], "+");
+                // the unexpected token isn't offered as a fix
+                assert!(errors[0].expected.contains(&"`;`".to_string()));
+                assert!(!errors[0].expected.contains(&"`+`".to_string()));
+                assert_eq!(errors[1].message, "missing `;`");
+                assert_eq!(errors[1].location.line, 5);
+            }
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+
+        let source =
+            "class A {\n  List<A> l = [SELECT Id FROM A WITH DATA CATEGORY G__c AT u__c];\n}\n";
+        match Formatter::format_one(source, Config::default()) {
+            Err(afmt::error::FormatError::UnsupportedNode { location, .. }) => {
+                assert_eq!(location.kind, "with_type");
+                assert_eq!(location.line, 2);
+            }
+            other => panic!("expected an unsupported node error, got {:?}", other),
+        }
+        let start = source.find("List").unwrap();
+        assert!(matches!(
+            Formatter::format_range(source, start..start + 4, Config::default()),
+            Err(afmt::error::FormatError::UnsupportedNode { .. })
+        ));
+
+        let source = "class A {}\n";
+        assert_eq!(
+            Formatter::format_one(source, Config::default()),
+            Ok("class A {\n}\n".to_string())
+        );
+    }
+
+    #[test]
+    fn format_range() {
+        let source = "class A {\n    Integer   a=1;\n  void m( ) {\n      Integer   x  =  1;\n      if(x==1){ x   = 2; }\n  }\n}\n";
+        let start = source.find("if(").unwrap();
+        assert_eq!(
+            Formatter::format_range(source, start..start + 2, Config::default()),
+            Ok("class A {\n    Integer   a=1;\n  void m( ) {\n      Integer   x  =  1;\n      if (x == 1) {\n        x = 2;\n      }\n  }\n}\n".to_string())
+        );
+    }
+
+    #[test]
+    fn format_many_times_on_one_thread() {
+        let config = Config::default();
+        assert_eq!(
+            Formatter::format_one("class A {}", config.clone()),
+            Ok("class A {\n}\n".to_string())
+        );
+        // a format failing halfway leaves nothing behind for the next one
+        let unsupported =
+            "class B { List<A> l = [SELECT Id FROM A WITH DATA CATEGORY G__c AT u__c]; }";
+        assert!(matches!(
+            Formatter::format_one(unsupported, config.clone()),
+            Err(afmt::error::FormatError::UnsupportedNode { .. })
+        ));
+        assert!(
+            Formatter::format_one("class C { void m() { x(); } // c\n }", config.clone()).is_ok()
+        );
+        assert_eq!(
+            Formatter::format_one("class D {}", config),
+            Ok("class D {\n}\n".to_string())
+        );
+    }
+
+    #[test]
+    fn verify() {
+        let source = "class A {\n  Boolean b = TRUE; // flag\n}\n";
+        let formatted = Formatter::format_one(source, Config::default()).unwrap();
+        assert_eq!(Formatter::verify(source, &formatted), Ok(()));
+
+        let changed = formatted.replace("true", "false");
+        match Formatter::verify(source, &changed) {
+            Err(afmt::error::FormatError::NotEquivalent { location, .. }) => {
+                assert_eq!((location.line, location.column), (2, 15));
+                assert_eq!(&source[location.start_byte..location.end_byte], "TRUE");
+            }
+            other => panic!("expected a verify error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn verify_idempotent() {
+        let config = Config::default();
+        let formatted = Formatter::format_one("class A {   }", config.clone()).unwrap();
+        assert_eq!(
+            Formatter::verify_idempotent(&formatted, config.clone()),
+            Ok(())
+        );
+
+        // a known case where the second pass moves a comment, see tests/to-do
+        let source = std::fs::read_to_string("tests/to-do/twice_format_issue1.in").unwrap();
+        let formatted = Formatter::format_one(&source, config.clone()).unwrap();
+        match Formatter::verify_idempotent(&formatted, config.clone()) {
+            Err(afmt::error::FormatError::NotIdempotent {
+                file: None,
+                first,
+                second,
+            }) => {
+                assert_eq!(first, formatted);
+                assert_eq!(Ok(second), Formatter::format_one(&formatted, config));
+            }
+            other => panic!("expected an idempotency error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn format_in_parallel() {
+        let dir = std::env::temp_dir().join(format!("afmt_jobs_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // the first files are the biggest, so the workers finish out of order
+        let sources: Vec<String> = (0..12)
+            .map(|i| match i {
+                5 => "class Broken {".to_string(),
+                _ => format!(
+                    "class A{} {{ {} }}",
+                    i,
+                    "Integer   x = 1; ".repeat((12 - i) * 10)
+                ),
+            })
+            .collect();
+        let files: Vec<String> = sources
+            .iter()
+            .enumerate()
+            .map(|(i, source)| {
+                let file = dir.join(format!("A{}.cls", i));
+                std::fs::write(&file, source).unwrap();
+                file.to_string_lossy().to_string()
+            })
+            .collect();
+
+        let mut formatter = Formatter::new(Config::default(), files.clone());
+        formatter.set_jobs(4);
+        let results = formatter.format();
+
+        assert_eq!(results.len(), files.len());
+        for ((result, file), source) in results.iter().zip(&files).zip(&sources) {
+            assert_eq!(&result.path, file);
+            assert_eq!(
+                result.result,
+                Formatter::format_one(source, Config::default()).map_err(|e| e.with_file(file))
+            );
+        }
+        assert!(results[5].result.is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn config_discovery() {
+        let dir = std::env::temp_dir().join(format!("afmt_config_test_{}", std::process::id()));
+        let write = |path: &str, content: &str| {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        };
+        let source = "class A {\nvoid f(){}\n}\n";
+        // above the project roots, never used
+        write(".afmt.toml", "indent_size = 8");
+        std::fs::create_dir_all(dir.join("repo/.git")).unwrap();
+        write("repo/pkg-a/.afmt.toml", "indent_size = 4");
+        write("repo/pkg-a/classes/deep/A.cls", source);
+        write("repo/pkg-b/B.cls", source);
+        write("sfdx/sfdx-project.json", "{}");
+        write("sfdx/C.cls", source);
+        let path = |p: &str| dir.join(p).to_string_lossy().to_string();
+        let canonical = |p: &str| std::fs::canonicalize(dir.join(p)).unwrap();
+
+        // the nearest config up the tree, none past a `.git` or `sfdx-project.json` root
+        assert_eq!(
+            Config::discover(Path::new(&path("repo/pkg-a/classes/deep/A.cls"))),
+            Some(canonical("repo/pkg-a/.afmt.toml"))
+        );
+        assert_eq!(Config::discover(Path::new(&path("repo/pkg-b/B.cls"))), None);
+        assert_eq!(Config::discover(Path::new(&path("sfdx/C.cls"))), None);
+
+        let files = vec![
+            path("repo/pkg-a/classes/deep/A.cls"),
+            path("repo/pkg-b/B.cls"),
+            path("sfdx/C.cls"),
+        ];
+        let formatter = Formatter::create_from_config(None, files.clone()).unwrap();
+        assert_eq!(formatter.config_for(&files[0]).unwrap().indent_size(), 4);
+        assert_eq!(formatter.config_for(&files[1]).unwrap().indent_size(), 2);
+
+        // each file of a run formatted with the config of its package
+        let results: Vec<_> = formatter
+            .format()
+            .into_iter()
+            .map(|r| r.result.unwrap())
+            .collect();
+        assert_eq!(
+            results,
+            vec![
+                "class A {\n    void f() {\n    }\n}\n",
+                "class A {\n  void f() {\n  }\n}\n",
+                "class A {\n  void f() {\n  }\n}\n",
+            ]
+        );
+
+        // an explicit config applies to every file
+        let config = path(".afmt.toml");
+        let formatter = Formatter::create_from_config(Some(&config), files.clone()).unwrap();
+        assert_eq!(formatter.config_for(&files[0]).unwrap().indent_size(), 8);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ignore_files() {
+        use afmt::files::collect_source_files;
+
+        let dir = std::env::temp_dir().join(format!("afmt_ignore_test_{}", std::process::id()));
+        let write = |path: &str, content: &str| {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        };
+        // the project root stops the lookup of `.afmtignore` files
+        write("sfdx-project.json", "{}");
+        write(".afmtignore", "generated/\n*.trigger\n");
+        write("sub/.afmtignore", "!Keep.trigger\nLocal.cls\n");
+        for file in [
+            "A.cls",
+            "T.trigger",
+            "generated/G.cls",
+            "sub/Keep.trigger",
+            "sub/Other.trigger",
+            "sub/Local.cls",
+        ] {
+            write(file, "class A {\n}\n");
+        }
+        let path = |p: &str| dir.join(p).to_string_lossy().to_string();
+
+        let collected = collect_source_files(&[path("")], None).unwrap();
+        // the nearest `.afmtignore` wins, a file it doesn't match falls back to the parent's
+        assert_eq!(
+            collected.files,
+            vec![path("A.cls"), path("sub/Keep.trigger")]
+        );
+        assert_eq!(
+            collected.skipped,
+            vec![
+                path("T.trigger"),
+                format!("{}/", path("generated")),
+                path("sub/Local.cls"),
+                path("sub/Other.trigger"),
+            ]
+        );
+
+        // a file given on the command line is always formatted
+        let collected = collect_source_files(&[path("T.trigger")], None).unwrap();
+        assert_eq!(collected.files, vec![path("T.trigger")]);
+        assert!(collected.skipped.is_empty());
+
+        let output = Command::new(env!("CARGO_BIN_EXE_afmt"))
+            .args(["--check", "-v", "--no-color", &path("")])
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{}", stdout);
+        assert!(stdout.contains(&format!("Skipped: {}", path("sub/Local.cls"))));
+        assert!(stdout.contains("generated/"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn stdin() {
+        use std::process::Stdio;
+
+        let dir = std::env::temp_dir().join(format!("afmt_stdin_test_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("pkg")).unwrap();
+        std::fs::write(dir.join("sfdx-project.json"), "{}").unwrap();
+        std::fs::write(dir.join("pkg/.afmt.toml"), "indent_size = 4").unwrap();
+        // the file doesn't need to exist, only its directory is searched for a config
+        let file_path = dir.join("pkg/A.cls").to_string_lossy().to_string();
+
+        let run = |args: &[&str], input: &str| {
+            let mut child = Command::new(env!("CARGO_BIN_EXE_afmt"))
+                .args(args)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .unwrap();
+            child
+                .stdin
+                .take()
+                .unwrap()
+                .write_all(input.as_bytes())
+                .unwrap();
+            let output = child.wait_with_output().unwrap();
+            (
+                output.status.code(),
+                String::from_utf8_lossy(&output.stdout).to_string(),
+                String::from_utf8_lossy(&output.stderr).to_string(),
+            )
+        };
+        let source = "class A {\nvoid f(){}\n}\n";
+
+        // only the formatted code on stdout
+        assert_eq!(
+            run(&["-"], source),
+            (
+                Some(0),
+                "class A {\n  void f() {\n  }\n}\n".to_string(),
+                String::new()
+            )
+        );
+
+        // the config of the named file's package
+        let (code, stdout, _) = run(&["--stdin-filepath", &file_path, "-"], source);
+        assert_eq!(code, Some(0));
+        assert_eq!(stdout, "class A {\n    void f() {\n    }\n}\n");
+
+        // and its path in the errors
+        let (code, stdout, stderr) = run(
+            &["--no-color", "--stdin-filepath", &file_path, "-"],
+            "class A {",
+        );
+        assert_eq!(code, Some(2));
+        assert_eq!(stdout, "");
+        assert!(
+            stderr.starts_with(&format!("{}:1:10: error: missing `}}`", file_path)),
+            "{}",
+            stderr
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn check_exit_codes() {
+        let dir = std::env::temp_dir().join(format!("afmt_check_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let check = |content: &str| {
+            std::fs::write(dir.join("A.cls"), content).unwrap();
+            let output = Command::new(env!("CARGO_BIN_EXE_afmt"))
+                .args(["--check", "--no-color", &dir.to_string_lossy()])
+                .output()
+                .unwrap();
+            (
+                output.status.code(),
+                String::from_utf8_lossy(&output.stdout).to_string(),
+            )
+        };
+
+        let (code, stdout) = check("class A {\n}\n");
+        assert_eq!(code, Some(0), "{}", stdout);
+        assert!(stdout.contains("1 file(s) checked, 0 would be reformatted, 0 failed"));
+
+        let (code, stdout) = check("class A {   }");
+        assert_eq!(code, Some(1), "{}", stdout);
+        assert!(stdout.contains("Would reformat: "));
+        // the file is left as it is
+        assert_eq!(
+            std::fs::read_to_string(dir.join("A.cls")).unwrap(),
+            "class A {   }"
+        );
+
+        let (code, stdout) = check("class A {");
+        assert_eq!(code, Some(2), "{}", stdout);
+        assert!(stdout.contains("1 file(s) checked, 0 would be reformatted, 1 failed"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn diff_output() {
+        let dir = std::env::temp_dir().join(format!("afmt_diff_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("A.cls");
+        std::fs::write(&file, "class A {\nvoid f(){}\n}\n").unwrap();
+        let file = file.to_string_lossy().to_string();
+
+        let output = Command::new(env!("CARGO_BIN_EXE_afmt"))
+            .args(["--diff", "--no-color", &dir.to_string_lossy()])
+            .output()
+            .unwrap();
+        // stdout is only the patch, e.g. for `> afmt.patch`
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            format!(
+                "--- {0}\n+++ {0}\n@@ -1,3 +1,4 @@\n class A {{\n-void f(){{}}\n+  void f() {{\n+  }}\n }}\n",
+                file
+            )
+        );
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("1 file(s) checked, 1 would be reformatted, 0 failed"));
+        assert!(stderr.contains("Execution time"));
+        assert_eq!(output.status.code(), Some(1));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn staged_files() {
+        let dir = std::env::temp_dir().join(format!("afmt_staged_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let git = |args: &[&str]| {
+            let output = Command::new("git")
+                .args(args)
+                .current_dir(&dir)
+                .env_remove("GIT_DIR")
+                .env_remove("GIT_INDEX_FILE")
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "git {:?}", args);
+            String::from_utf8_lossy(&output.stdout).to_string()
+        };
+        let write = |path: &str, content: &str| std::fs::write(dir.join(path), content).unwrap();
+        let read = |path: &str| std::fs::read_to_string(dir.join(path)).unwrap();
+
+        git(&["init", "-q"]);
+        write("A.cls", "class A {\nvoid f(){}\n}\n");
+        // B.cls is partially staged: the working tree has changes on top of the index
+        write("B.cls", "class B {\nvoid f(){}\n}\n");
+        git(&["add", "A.cls", "B.cls"]);
+        write("B.cls", "class B {\nvoid g(){}\n}\n");
+
+        let output = Command::new(env!("CARGO_BIN_EXE_afmt"))
+            .args(["--write", "--staged", "--no-color"])
+            .current_dir(&dir)
+            .env_remove("GIT_DIR")
+            .env_remove("GIT_INDEX_FILE")
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{}", stdout);
+        assert!(stdout.contains("Formatted and staged: B.cls (unstaged changes left as they are)"));
+
+        // a fully staged file is formatted in the index and in the working tree
+        let formatted_a = "class A {\n  void f() {\n  }\n}\n";
+        assert_eq!(git(&["show", ":A.cls"]), formatted_a);
+        assert_eq!(read("A.cls"), formatted_a);
+        // a partially staged one only in the index
+        assert_eq!(
+            git(&["show", ":B.cls"]),
+            "class B {\n  void f() {\n  }\n}\n"
+        );
+        assert_eq!(read("B.cls"), "class B {\nvoid g(){}\n}\n");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cache() {
+        use afmt::cache::Cache;
+
+        let dir = std::env::temp_dir().join(format!("afmt_cache_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("A.cls");
+        std::fs::write(&file, "class A {\n}\n").unwrap();
+        let file = file.to_string_lossy().to_string();
+        let cache_file = dir.join(".afmtcache");
+
+        let run = || {
+            let mut formatter = Formatter::new(Config::default(), vec![file.clone()]);
+            formatter.set_cache(Cache::load(&cache_file));
+            let result = formatter.format().remove(0).result;
+            formatter.save_cache().unwrap();
+            result
+        };
+        assert_eq!(run(), Ok("class A {\n}\n".to_string()));
+
+        let key = Cache::key("class A {\n}\n", &Config::default());
+        assert!(Cache::load(&cache_file).is_formatted(&file, key));
+        // any config change gives another key
+        assert_ne!(key, Cache::key("class A {\n}\n", &Config::new(100)));
+
+        // a changed file is formatted again and leaves the cache
+        std::fs::write(&file, "class A {   }").unwrap();
+        assert_eq!(run(), Ok("class A {\n}\n".to_string()));
+        let key = Cache::key("class A {   }", &Config::default());
+        assert!(!Cache::load(&cache_file).is_formatted(&file, key));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn json_report() {
+        use afmt::report::{Report, REPORT_VERSION};
+        use std::time::Duration;
+
+        let mut report = Report::new(true);
+        let formatted = Formatter::format_one("class A {   }", Config::default()).unwrap();
+        report.add_formatted("A.cls", "class A {   }", &formatted, Duration::ZERO);
+        let error = Formatter::format_one("class B {", Config::default()).unwrap_err();
+        report.add_error("B.cls", "class B {", &error, Duration::ZERO);
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["version"], REPORT_VERSION);
+        assert_eq!(json["files"][0]["status"], "changed");
+        assert_eq!(json["files"][0]["formatted"], "class A {\n}\n");
+        assert_eq!(json["files"][1]["status"], "error");
+        assert_eq!(json["files"][1]["errors"][0]["kind"], "parse");
+        assert_eq!(json["files"][1]["errors"][0]["location"]["line"], 1);
+        assert_eq!(json["summary"]["changed"], 1);
+        assert_eq!(json["summary"]["errors"], 1);
+    }
+
+    #[test]
+    fn sarif_and_checkstyle_reports() {
+        use afmt::report::Report;
+        use std::time::Duration;
+
+        let mut report = Report::new(false);
+        let source = "class A {\n  void f() {\n  }\n  void g(){ }\n}\n";
+        let formatted = Formatter::format_one(source, Config::default()).unwrap();
+        report.add_formatted("A.cls", source, &formatted, Duration::ZERO);
+        let source = "class B {\n  Integer x = 1 +;\n}";
+        let error = Formatter::format_one(source, Config::default()).unwrap_err();
+        report.add_error("B.cls", source, &error, Duration::ZERO);
+        // `é` is 2 bytes and `😀` 4 bytes, SARIF counts 1 and 2 UTF-16 code units
+        let source = "class C {\n  String s = 'é😀' +;\n}";
+        let error = Formatter::format_one(source, Config::default()).unwrap_err();
+        report.add_error("C.cls", source, &error, Duration::ZERO);
+        // paths are percent-encoded in URIs
+        let source = "class D {   }";
+        let formatted = Formatter::format_one(source, Config::default()).unwrap();
+        report.add_formatted("./My Classes/D#1.cls", source, &formatted, Duration::ZERO);
+        report.add_formatted("/src/é 100%.cls", source, &formatted, Duration::ZERO);
+
+        let sarif: serde_json::Value = serde_json::from_str(&report.to_sarif()).unwrap();
+        let results = &sarif["runs"][0]["results"];
+        assert_eq!(sarif["version"], "2.1.0");
+        assert_eq!(results[0]["ruleId"], "unformatted");
+        assert_eq!(
+            results[0]["locations"][0]["physicalLocation"]["region"]["startLine"],
+            4
+        );
+        assert_eq!(results[1]["level"], "error");
+        let region = &results[1]["locations"][0]["physicalLocation"]["region"];
+        assert_eq!(
+            (&region["startLine"], &region["startColumn"]),
+            (&2.into(), &17.into())
+        );
+        let region = &results[2]["locations"][0]["physicalLocation"]["region"];
+        assert_eq!(
+            (&region["startColumn"], &region["endColumn"]),
+            (&20.into(), &21.into())
+        );
+        let artifact = &results[3]["locations"][0]["physicalLocation"]["artifactLocation"];
+        assert_eq!(artifact["uri"], "My%20Classes/D%231.cls");
+        assert_eq!(artifact["uriBaseId"], "%SRCROOT%");
+        let artifact = &results[4]["locations"][0]["physicalLocation"]["artifactLocation"];
+        assert_eq!(artifact["uri"], "file:///src/%C3%A9%20100%25.cls");
+
+        let checkstyle = report.to_checkstyle();
+        assert!(checkstyle.contains(r#"<error line="4" severity="warning""#));
+        assert!(checkstyle.contains(r#"<error line="2" column="17" severity="error""#));
+        // a column in chars, 23 in bytes and 20 in UTF-16 code units
+        assert!(checkstyle.contains(r#"<error line="2" column="19" severity="error""#));
+    }
+
+    #[test]
+    fn tabs_and_visual_indent() {
+        let source = "class A {\n  void f() {\n    foo(aaaaaaaaaaaaaaaaaaaa, bbbbbbbbbbbbbbbbbbbbbbbb, cccccccccccccccc);\n  }\n}\n";
+        let format =
+            |toml: &str| Formatter::format_one(source, Config::from_toml(toml).unwrap()).unwrap();
+
+        // the call fits in 80 columns with spaces, not with two tabs of 8
+        assert_eq!(
+            format("use_tabs = true\ntab_width = 8"),
+            "class A {\n\tvoid f() {\n\t\tfoo(\n\t\t\taaaaaaaaaaaaaaaaaaaa,\n\t\t\tbbbbbbbbbbbbbbbbbbbbbbbb,\n\t\t\tcccccccccccccccc\n\t\t);\n\t}\n}\n"
+        );
+        assert_eq!(
+            format("indent_style = \"Visual\"\nmax_width = 60"),
+            "class A {\n  void f() {\n    foo(aaaaaaaaaaaaaaaaaaaa,\n        bbbbbbbbbbbbbbbbbbbbbbbb,\n        cccccccccccccccc);\n  }\n}\n"
+        );
+        // tabs up to the indentation, spaces for the alignment
+        assert_eq!(
+            format("indent_style = \"Visual\"\nhard_tabs = true\ntab_width = 8"),
+            "class A {\n\tvoid f() {\n\t\tfoo(aaaaaaaaaaaaaaaaaaaa,\n\t\t    bbbbbbbbbbbbbbbbbbbbbbbb,\n\t\t    cccccccccccccccc);\n\t}\n}\n"
+        );
+    }
+
+    #[test]
+    fn visual_alignment() {
+        let source = "class A {\n  Integer f() {\n    if (aaaaaaaaaaaaaaaaaaaa && bbbbbbbbbbbbbbbbbbbbbbbb) {}\n    return aaaaaaaaaaaaaaaaaaaa + bbbbbbbbbbbbbbbbbbbbbbbb + cccc;\n  }\n}\n";
+        let config = Config::from_toml("indent_style = \"Visual\"\nmax_width = 50").unwrap();
+
+        // conditions under the `(`, chained operands under the first one
+        assert_eq!(
+            Formatter::format_one(source, config).unwrap(),
+            "class A {\n  Integer f() {\n    if (aaaaaaaaaaaaaaaaaaaa\n        && bbbbbbbbbbbbbbbbbbbbbbbb) {\n    }\n    return aaaaaaaaaaaaaaaaaaaa\n           + bbbbbbbbbbbbbbbbbbbbbbbb\n           + cccc;\n  }\n}\n"
+        );
+    }
+
+    #[test]
+    fn line_endings_and_bom() {
+        let format = |source: &str, toml: &str| {
+            Formatter::format_one(source, Config::from_toml(toml).unwrap()).unwrap()
+        };
+
+        // the dominant ending and the BOM are kept
+        assert_eq!(
+            format("\u{feff}class A {\r\n  // a\r\n}\nclass B {}\r\n", ""),
+            "\u{feff}class A {\r\n  // a\r\n}\r\nclass B {\r\n}\r\n"
+        );
+        assert_eq!(
+            format("class A {\r\n}\r\n", "newline_style = \"Unix\""),
+            "class A {\n}\n"
+        );
+        assert_eq!(
+            format(
+                "class A {}",
+                "newline_style = \"Windows\"\ninsert_final_newline = false"
+            ),
+            "class A {\r\n}"
+        );
+    }
+
+    #[test]
+    fn unicode_width() {
+        // 76 columns but 91 bytes: wide characters take two columns, accents one
+        let source = "class A {\n  String s = foo('Größe', 'カテゴリの名前', '🎉🎉🎉', 'aaaaaaaaaaaaaaaaaa');\n}\n";
+        assert_eq!(
+            Formatter::format_one(source, Config::default()).unwrap(),
+            source
+        );
+
+        // a tab in a comment goes to the next multiple of `tab_width`
+        let source = "class A {\n  Integer i = foo(aaaaaaaaaaaaaaaaaaaaaa, bbbbbbbbbbbbbbbbbbbbbbbbb); //\tc\n}\n";
+        assert_eq!(
+            Formatter::format_one(source, Config::from_toml("tab_width = 4").unwrap()).unwrap(),
+            source
+        );
+        assert_ne!(
+            Formatter::format_one(source, Config::from_toml("tab_width = 16").unwrap()).unwrap(),
+            source
+        );
+    }
+
     fn run_scenario(dir_path: &str, scenario_name: &str) -> (u32, u32) {
         let mut total_tests = 0;
         let mut failed_tests = 0;
@@ -149,7 +792,7 @@ mod tests {
         compare("Prettier:", output, prettier_output, source)
     }
 
-    fn normalize(label: &str, content: &str) -> String {
+    fn normalize(_label: &str, content: &str) -> String {
         //println!("{} (Hex):", label);
         let mut normalized = String::new();
 
@@ -194,14 +837,17 @@ mod tests {
             .expect("PathBuf to String failed.")
             .to_string();
 
-        let formatter = Formatter::create_from_config(config_path, vec![file_path.clone()])
+        let mut formatter = Formatter::create_from_config(config_path, vec![file_path.clone()])
             .expect("Create formatter failed.");
+        // every fixture must also be stable when formatted a second time
+        formatter.set_verify_idempotent(true);
 
         let vec = formatter.format();
         vec.into_iter()
             .next()
-            .and_then(|result| result.ok())
             .expect("format result failed.")
+            .result
+            .unwrap_or_else(|e| panic!("{}", e))
     }
 
     fn print_side_by_side_diff(against: &str, output: &str, expected: &str) {
@@ -234,8 +880,8 @@ mod tests {
                     println!("...");
                 }
 
-                for k in start..end {
-                    print_change_line(&changes[k]);
+                for change in &changes[start..end] {
+                    print_change_line(change);
                 }
 
                 if end < changes.len() {
@@ -244,8 +890,8 @@ mod tests {
 
                 // Also print raw diff lines for easy visual comparison
                 println!("\n--- Raw diff ---");
-                for k in start..end {
-                    print_raw_diff(&changes[k]);
+                for change in &changes[start..end] {
+                    print_raw_diff(change);
                 }
 
                 i = j;